@@ -0,0 +1,59 @@
+//! Decoding a cached ~100 KB page against parsing it again.
+//!
+//! Run with `cargo bench --features binary-cache --bench binary_cache`.
+use std::time::{Duration, Instant};
+
+use roxy::dom::{self, Node};
+
+const RUNS: u32 = 50;
+
+/// A page of about 100 KB with the usual mix of sections, lists, tables and
+/// links.
+fn page() -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html><html><head><title>Benchmark</title>\
+         <style>p { color: #333; } .note { margin: 1em; }</style></head><body>",
+    );
+    let mut section = 0;
+    while html.len() < 100_000 {
+        html.push_str(&format!(
+            "<section id=\"s{0}\" class=\"section\"><h2>Section {0}</h2>\
+             <p class=\"note\">Some <b>bold</b> and <a href=\"/page/{0}\">linked</a> text \
+             &amp; an entity.</p>\
+             <ul><li>one</li><li>two</li><li>three</li></ul>\
+             <table><tr><th>key</th><th>value</th></tr><tr><td>{0}</td><td>x</td></tr></table>\
+             <!-- section {0} --></section>",
+            section
+        ));
+        section += 1;
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+fn time<T>(mut run: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        std::hint::black_box(run());
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let html = page();
+    let node = dom::parse(html.clone()).unwrap();
+    let bytes = node.to_bytes();
+    assert_eq!(Node::from_bytes(&bytes).unwrap(), node);
+
+    let encode = time(|| node.to_bytes());
+    let decode = time(|| Node::from_bytes(&bytes).unwrap());
+    let reparse = time(|| dom::parse(html.clone()).unwrap());
+    println!("encode   {:?}", encode);
+    println!("decode   {:?}", decode);
+    println!("reparse  {:?}", reparse);
+    println!(
+        "size     {} KB encoded vs. {} KB of HTML",
+        bytes.len() / 1000,
+        html.len() / 1000
+    );
+}
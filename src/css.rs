@@ -1,11 +1,51 @@
-use core::fmt;
+use core::{fmt, ops};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    str::FromStr,
+};
+
+use crate::{
+    dom::{ElementData, Node, NodeType},
+    style::{CursorKeyword, ListStyleType},
+};
 
 struct Parser {
     pos: usize,
     input: String,
+    /// Where the rule parsed by the last `parse_rule` call starts.
+    rule_start: usize,
+    /// Where each declaration parsed by the last `parse_declarations` call starts.
+    declaration_starts: Vec<usize>,
+    /// The query of the `@media` block being parsed, if any.
+    media: Option<String>,
+    /// How many selector arguments such as `:not(` are open.
+    selector_depth: usize,
+    /// How many function calls in a value are open.
+    function_depth: usize,
 }
 
+type ParseResult<T> = Result<T, CssParseError>;
+
+/// How deeply pseudo-classes taking selectors, such as `:not()`, may be
+/// nested, so that hostile stylesheets can't exhaust the stack.
+pub const MAX_SELECTOR_DEPTH: usize = 32;
+
+/// How deeply function calls in a value may be nested, for the same reason.
+pub const MAX_FUNCTION_DEPTH: usize = 32;
+
 impl Parser {
+    fn new(input: String) -> Self {
+        Self {
+            pos: 0,
+            input,
+            rule_start: 0,
+            declaration_starts: vec![],
+            media: None,
+            selector_depth: 0,
+            function_depth: 0,
+        }
+    }
+
     fn eof(&self) -> bool {
         self.pos >= self.input.len()
     }
@@ -15,299 +55,3100 @@ impl Parser {
         F: Fn(char) -> bool,
     {
         let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
+        while self.next_char().is_some_and(&test) {
             result.push(self.consume_char());
         }
         result
     }
 
-    fn next_char(&self) -> char {
-        self.input[self.pos..].chars().next().unwrap()
+    fn next_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn starts_with_ignore_case(&self, s: &str) -> bool {
+        self.input[self.pos..]
+            .get(..s.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(s))
     }
 
     fn consume_char(&mut self) -> char {
         let mut iter = self.input[self.pos..].char_indices();
         let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
+        let (next_pos, _) = iter.next().unwrap_or((cur_char.len_utf8(), ' '));
         self.pos += next_pos;
         cur_char
     }
 
+    /// Skips whitespace along with any `/* ... */` comments.
     fn consume_whitespace(&mut self) {
-        self.consume_while(|c| c.is_whitespace());
+        loop {
+            self.consume_while(|c| c.is_whitespace());
+            if !self.input[self.pos..].starts_with("/*") {
+                return;
+            }
+            self.pos = match self.input[self.pos + 2..].find("*/") {
+                Some(end) => self.pos + 2 + end + 2,
+                None => self.input.len(),
+            };
+        }
+    }
+
+    fn unexpected(&self) -> CssParseError {
+        match self.next_char() {
+            Some(found) => CssParseError::UnexpectedChar {
+                pos: self.pos,
+                found,
+            },
+            None => CssParseError::UnexpectedEof,
+        }
     }
 
-    fn parse_single_selector(&mut self) -> SingleSelector {
+    fn unrecognized(&self, token: &str) -> CssParseError {
+        CssParseError::Unrecognized {
+            pos: self.pos - token.len(),
+            token: token.to_string(),
+        }
+    }
+
+    fn expect_char(&mut self, expected: char) -> ParseResult<()> {
+        if self.next_char() != Some(expected) {
+            return Err(self.unexpected());
+        }
+        self.consume_char();
+        Ok(())
+    }
+
+    /// A compound selector such as `a.external[href]:hover`.
+    fn parse_single_selector(&mut self) -> ParseResult<SingleSelector> {
+        let start = self.pos;
         let mut selector = SingleSelector::default();
-        while !self.eof() {
-            self.consume_whitespace();
-            match self.next_char() {
+        while let Some(c) = self.next_char() {
+            match c {
                 '#' => {
                     self.consume_char();
-                    assert!(self.next_char().is_ascii_alphanumeric());
-                    selector.id = Some(self.parse_identifier());
+                    selector.id = Some(self.parse_name()?);
                 }
                 '.' => {
                     self.consume_char();
-                    assert!(self.next_char().is_ascii_alphanumeric());
-                    selector.classes.push(self.parse_identifier());
+                    selector.classes.push(self.parse_name()?);
                 }
                 '*' => {
                     self.consume_char();
-                    assert!(self.next_char().is_ascii_whitespace());
-                }
-                '{' => {
-                    break;
                 }
-                c => {
-                    if !c.is_ascii_alphanumeric() {
-                        break;
-                    }
+                '[' => selector.attributes.push(self.parse_attribute_selector()?),
+                ':' => self.parse_pseudo(&mut selector)?,
+                c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => {
                     selector.tag_name = Some(self.parse_identifier());
                 }
+                _ => break,
+            }
+        }
+        if self.pos == start {
+            return Err(self.unexpected());
+        }
+        Ok(selector)
+    }
+
+    fn parse_attribute_selector(&mut self) -> ParseResult<AttributeSelector> {
+        self.expect_char('[')?;
+        self.consume_whitespace();
+        let name = self.parse_name()?;
+        self.consume_whitespace();
+        let operator = match self.next_char() {
+            Some(']') => None,
+            Some('=') => Some(AttrOperator::Equals),
+            Some('~') => Some(AttrOperator::Includes),
+            Some('|') => Some(AttrOperator::DashMatch),
+            Some('^') => Some(AttrOperator::Prefix),
+            Some('$') => Some(AttrOperator::Suffix),
+            Some('*') => Some(AttrOperator::Substring),
+            _ => return Err(self.unexpected()),
+        };
+        let matcher = match operator {
+            Some(operator) => {
+                if operator != AttrOperator::Equals {
+                    self.consume_char();
+                }
+                self.expect_char('=')?;
+                self.consume_whitespace();
+                let value = match self.next_char() {
+                    Some('"' | '\'') => self.parse_quoted()?,
+                    _ => self.parse_name()?,
+                };
+                self.consume_whitespace();
+                Some((operator, value))
+            }
+            None => None,
+        };
+        self.expect_char(']')?;
+        Ok(AttributeSelector { name, matcher })
+    }
+
+    fn parse_quoted(&mut self) -> ParseResult<String> {
+        let quote = self.consume_char();
+        let value = self.consume_while(|c| c != quote);
+        self.expect_char(quote)?;
+        Ok(value)
+    }
+
+    /// `:pseudo-class`, `:pseudo-class(argument)` or `::pseudo-element`.
+    fn parse_pseudo(&mut self, selector: &mut SingleSelector) -> ParseResult<()> {
+        self.expect_char(':')?;
+        if self.next_char() == Some(':') {
+            self.consume_char();
+            selector.pseudo_element = Some(self.parse_name()?.to_ascii_lowercase());
+            return Ok(());
+        }
+        let name = self.parse_name()?.to_ascii_lowercase();
+        if LEGACY_PSEUDO_ELEMENTS.contains(&&*name) {
+            selector.pseudo_element = Some(name);
+            return Ok(());
+        }
+        let argument = if self.next_char() == Some('(') {
+            self.consume_char();
+            self.consume_whitespace();
+            let argument = if SELECTOR_PSEUDO_CLASSES.contains(&&*name) {
+                if self.selector_depth == MAX_SELECTOR_DEPTH {
+                    return Err(CssParseError::TooDeep { pos: self.pos });
+                }
+                self.selector_depth += 1;
+                let selectors = self.parse_selectors_until(')');
+                self.selector_depth -= 1;
+                PseudoArgument::Selectors(selectors?)
+            } else {
+                let raw = self.consume_while(|c| c != ')');
+                PseudoArgument::Raw(raw.trim().to_string())
+            };
+            self.consume_whitespace();
+            self.expect_char(')')?;
+            Some(argument)
+        } else {
+            None
+        };
+        selector.pseudo_classes.push(PseudoClass { name, argument });
+        Ok(())
+    }
+
+    /// Compound selectors joined by combinators, e.g. `nav > ul li`.
+    fn parse_selector(&mut self) -> ParseResult<Selector> {
+        let mut selector = Selector::Single(self.parse_single_selector()?);
+        loop {
+            let start = self.pos;
+            self.consume_whitespace();
+            let combinator = match self.next_char() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                None | Some(',' | '{' | ')') => break,
+                _ if self.pos > start => Combinator::Descendant,
+                _ => return Err(self.unexpected()),
+            };
+            if combinator != Combinator::Descendant {
+                self.consume_char();
+                self.consume_whitespace();
             }
+            let right = self.parse_single_selector()?;
+            selector = Selector::Combined(Box::new(selector), combinator, right);
         }
-        selector
+        Ok(selector)
     }
 
     fn parse_identifier(&mut self) -> String {
-        self.consume_while(|c| (c.is_ascii_alphanumeric() || c == '-'))
+        self.consume_while(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// An identifier that must not be empty, e.g. a class name after `.`.
+    fn parse_name(&mut self) -> ParseResult<String> {
+        let name = self.parse_identifier();
+        if name.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(name)
+    }
+
+    fn parse_rule(&mut self) -> ParseResult<Rule> {
+        self.rule_start = self.pos;
+        Ok(Rule {
+            selectors: self.parse_selectors()?,
+            declarations: self.parse_declaration_block()?,
+            media: self.media.clone(),
+        })
     }
 
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    /// Opens an `@media` block, up to and including its `{`. Nested blocks
+    /// aren't supported.
+    fn parse_media_prelude(&mut self) -> ParseResult<()> {
+        if self.media.is_some() {
+            return Err(self.unexpected());
         }
+        self.pos += "@media".len();
+        let query = self.consume_while(|c| c != '{');
+        let query = query.split_whitespace().collect::<Vec<&str>>().join(" ");
+        self.expect_char('{')?;
+        self.media = Some(query);
+        Ok(())
     }
 
-    fn parse_selectors(&mut self) -> Vec<Selector> {
+    fn parse_selectors(&mut self) -> ParseResult<Vec<Selector>> {
+        self.parse_selectors_until('{')
+    }
+
+    /// A comma-separated selector list, stopping before `terminator`.
+    fn parse_selectors_until(&mut self, terminator: char) -> ParseResult<Vec<Selector>> {
         let mut selectors = Vec::new();
         loop {
-            selectors.push(Selector::Single(self.parse_single_selector()));
+            selectors.push(self.parse_selector()?);
             self.consume_whitespace();
             match self.next_char() {
-                ',' => {
+                Some(',') => {
                     self.consume_char();
                     self.consume_whitespace();
                 }
-                '{' => break,
-                c => panic!("Unexpected character {} in selector list", c),
+                Some(c) if c == terminator => break,
+                _ => return Err(self.unexpected()),
             }
         }
-        selectors
+        Ok(selectors)
+    }
+
+    fn parse_declaration_block(&mut self) -> ParseResult<Vec<Declaration>> {
+        self.expect_char('{')?;
+        let declarations = self.parse_declarations()?;
+        self.expect_char('}')?;
+        Ok(declarations)
     }
 
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert!(self.consume_char() == '{');
+    /// Parses declarations up to a closing `}` or the end of input.
+    fn parse_declarations(&mut self) -> ParseResult<Vec<Declaration>> {
         let mut result = Vec::new();
-        while !self.eof() {
+        self.declaration_starts.clear();
+        loop {
             self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
-                break;
+            match self.next_char() {
+                None | Some('}') => break,
+                Some(';') => {
+                    self.consume_char();
+                    continue;
+                }
+                _ => {}
             }
-            let name = self.parse_identifier();
+            self.declaration_starts.push(self.pos);
+            let name = self.parse_property_name()?;
             self.consume_whitespace();
-            assert!(self.consume_char() == ':');
+            self.expect_char(':')?;
             self.consume_whitespace();
-            let value = self.parse_declaration_value();
-            result.push(Declaration { name, value });
+            let value = if name.eq_ignore_ascii_case("cursor") {
+                self.parse_cursor()?
+            } else {
+                self.parse_declaration_value()?
+            };
+            let important = self.parse_important()?;
+            result.push(Declaration {
+                name,
+                value,
+                important,
+            });
             self.consume_whitespace();
-            assert!(self.consume_char() == ';');
+            match self.next_char() {
+                None | Some('}') => break,
+                _ => self.expect_char(';')?,
+            }
         }
-        result
+        Ok(result)
+    }
+
+    fn parse_property_name(&mut self) -> ParseResult<String> {
+        let name = self.parse_name()?;
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self.unrecognized(&name));
+        }
+        Ok(name)
+    }
+
+    fn parse_declaration_value(&mut self) -> ParseResult<CssValue> {
+        self.parse_value_until(&[';', '}', '!'])
+    }
+
+    /// A `cursor` value: any number of `url()` images, each followed by a
+    /// comma, then a keyword to fall back on. Hotspot coordinates aren't supported.
+    fn parse_cursor(&mut self) -> ParseResult<CssValue> {
+        let mut values = Vec::new();
+        loop {
+            self.consume_whitespace();
+            let name = self.parse_name()?;
+            if self.next_char() != Some('(') {
+                let Some(keyword) = CursorKeyword::from_keyword(&name) else {
+                    return Err(self.unrecognized(&name));
+                };
+                values.push(CursorValue::Keyword(keyword));
+                self.consume_whitespace();
+                return Ok(CssValue::Cursor(values));
+            }
+            if !name.eq_ignore_ascii_case("url") {
+                return Err(self.unrecognized(&name));
+            }
+            self.consume_char();
+            self.consume_whitespace();
+            values.push(CursorValue::Url(self.parse_url()?));
+            self.consume_whitespace();
+            self.expect_char(')')?;
+            self.consume_whitespace();
+            self.expect_char(',')?;
+        }
+    }
+
+    /// An optional `!important` after a declaration value.
+    fn parse_important(&mut self) -> ParseResult<bool> {
+        if self.next_char() != Some('!') {
+            return Ok(false);
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        let keyword = self.parse_identifier();
+        if !keyword.eq_ignore_ascii_case("important") {
+            return Err(self.unrecognized(&keyword));
+        }
+        Ok(true)
+    }
+
+    /// Component values up to one of `terminators`, grouping comma-separated runs.
+    fn parse_value_until(&mut self, terminators: &[char]) -> ParseResult<CssValue> {
+        let mut groups = Vec::new();
+        let mut values = Vec::new();
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                None => break,
+                Some(c) if terminators.contains(&c) => break,
+                Some(',') => {
+                    groups.push(self.group_values(values)?);
+                    values = Vec::new();
+                    self.consume_char();
+                }
+                _ => values.push(self.parse_component_value()?),
+            }
+        }
+        groups.push(self.group_values(values)?);
+        if groups.len() == 1 {
+            Ok(groups.swap_remove(0))
+        } else {
+            Ok(CssValue::CommaList(groups))
+        }
+    }
+
+    fn group_values(&self, mut values: Vec<CssValue>) -> ParseResult<CssValue> {
+        match values.len() {
+            0 => Err(self.unexpected()),
+            1 => Ok(values.swap_remove(0)),
+            _ => Ok(CssValue::List(values)),
+        }
+    }
+
+    fn starts_numeric(&self) -> bool {
+        let mut chars = self.input[self.pos..].chars();
+        match chars.next() {
+            Some('0'..='9' | '.') => true,
+            Some('-' | '+') => matches!(chars.next(), Some('0'..='9' | '.')),
+            _ => false,
+        }
+    }
+
+    fn parse_component_value(&mut self) -> ParseResult<CssValue> {
+        if self.starts_numeric() {
+            return self.parse_numeric();
+        }
+        if self.next_char() == Some('#') {
+            return Ok(CssValue::Color(self.parse_color()?));
+        }
+        let name = self.parse_name()?;
+        if self.next_char() == Some('(') {
+            return self.parse_function(name);
+        }
+        Ok(CssValue::Keyword(name))
+    }
+
+    fn parse_numeric(&mut self) -> ParseResult<CssValue> {
+        let value = self.parse_float()?;
+        if self.next_char() == Some('%') {
+            self.consume_char();
+            return Ok(CssValue::Length(CssLength {
+                value,
+                unit: Unit::Percent,
+            }));
+        }
+        let unit = self.parse_identifier();
+        match &*unit.to_ascii_lowercase() {
+            "" => Ok(CssValue::Number(value)),
+            "s" => Ok(CssValue::Time(value)),
+            "ms" => Ok(CssValue::Time(value / 1000.0)),
+            "deg" | "grad" | "rad" | "turn" => Ok(CssValue::Angle(self.angle(value, &unit)?)),
+            _ => Ok(CssValue::Length(CssLength {
+                value,
+                unit: self.length_unit(&unit)?,
+            })),
+        }
+    }
+
+    fn parse_function(&mut self, name: String) -> ParseResult<CssValue> {
+        if self.function_depth == MAX_FUNCTION_DEPTH {
+            return Err(CssParseError::TooDeep { pos: self.pos });
+        }
+        self.function_depth += 1;
+        let value = self.parse_function_call(name);
+        self.function_depth -= 1;
+        value
+    }
+
+    fn parse_function_call(&mut self, name: String) -> ParseResult<CssValue> {
+        self.expect_char('(')?;
+        self.consume_whitespace();
+        let value = match &*name.to_ascii_lowercase() {
+            "url" => CssValue::Url(self.parse_url()?),
+            "counter" => self.parse_counter()?,
+            "linear-gradient" => CssValue::Gradient(self.parse_linear_gradient()?),
+            "radial-gradient" => CssValue::Gradient(self.parse_radial_gradient()?),
+            _ => {
+                let args = match self.parse_value_until(&[')'])? {
+                    CssValue::CommaList(args) => args,
+                    arg => vec![arg],
+                };
+                CssValue::Function(name, args)
+            }
+        };
+        self.consume_whitespace();
+        self.expect_char(')')?;
+        Ok(value)
+    }
+
+    /// `counter(name)` or `counter(name, <list-style-type>)`.
+    fn parse_counter(&mut self) -> ParseResult<CssValue> {
+        let name = self.parse_name()?;
+        self.consume_whitespace();
+        let style = if self.next_char() == Some(',') {
+            self.consume_char();
+            self.consume_whitespace();
+            let keyword = self.parse_name()?;
+            match ListStyleType::from_keyword(&keyword) {
+                Some(style) => Some(style),
+                None => return Err(self.unrecognized(&keyword)),
+            }
+        } else {
+            None
+        };
+        Ok(CssValue::Counter { name, style })
     }
 
-    fn parse_declaration_value(&mut self) -> Value {
+    /// The contents of `url(...)`, which may be quoted or bare.
+    fn parse_url(&mut self) -> ParseResult<String> {
         match self.next_char() {
-            '0'..='9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+            Some(quote @ ('"' | '\'')) => {
+                self.consume_char();
+                let url = self.consume_while(|c| c != quote);
+                self.expect_char(quote)?;
+                Ok(url)
+            }
+            _ => Ok(self
+                .consume_while(|c| c != ')' && !c.is_whitespace())
+                .to_string()),
         }
     }
 
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    fn parse_linear_gradient(&mut self) -> ParseResult<Gradient> {
+        let angle = if self.starts_numeric() {
+            Some(self.parse_angle()?)
+        } else if self.starts_with_ignore_case("to ") {
+            Some(self.parse_side_angle()?)
+        } else {
+            None
+        };
+        if angle.is_some() {
+            self.consume_whitespace();
+            self.expect_char(',')?;
+        }
+        Ok(Gradient::Linear {
+            angle: angle.unwrap_or(Angle(180.0)),
+            stops: self.parse_color_stops()?,
+        })
     }
 
-    fn parse_color(&mut self) -> Value {
-        assert_eq!(self.consume_char(), '#');
-        Value::Color(ColorValue::Rgba(
-            self.parse_hex_pair(),
-            self.parse_hex_pair(),
-            self.parse_hex_pair(),
-            255,
-        ))
+    fn parse_radial_gradient(&mut self) -> ParseResult<Gradient> {
+        // Without a shape, the first argument is already a color stop.
+        let start = self.pos;
+        let shape = match &*self.parse_identifier().to_ascii_lowercase() {
+            "circle" => Some(RadialShape::Circle),
+            "ellipse" => Some(RadialShape::Ellipse),
+            _ => None,
+        };
+        let shape = match shape {
+            Some(shape) => {
+                self.consume_whitespace();
+                self.expect_char(',')?;
+                shape
+            }
+            None => {
+                self.pos = start;
+                RadialShape::Ellipse
+            }
+        };
+        Ok(Gradient::Radial {
+            shape,
+            stops: self.parse_color_stops()?,
+        })
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    fn parse_side_angle(&mut self) -> ParseResult<Angle> {
+        self.parse_identifier();
+        let mut vertical = None;
+        let mut horizontal = None;
+        loop {
+            self.consume_whitespace();
+            let side = self.parse_identifier();
+            match &*side.to_ascii_lowercase() {
+                "top" => vertical = Some(0.0),
+                "bottom" => vertical = Some(180.0),
+                "left" => horizontal = Some(270.0),
+                "right" => horizontal = Some(90.0),
+                "" => break,
+                _ => return Err(self.unrecognized(&side)),
+            }
+        }
+        match (vertical, horizontal) {
+            (Some(v), None) => Ok(Angle(v)),
+            (None, Some(h)) => Ok(Angle(h)),
+            (Some(0.0), Some(h)) => Ok(Angle(if h == 90.0 { 45.0 } else { 315.0 })),
+            (Some(_), Some(h)) => Ok(Angle(if h == 90.0 { 135.0 } else { 225.0 })),
+            (None, None) => Err(self.unexpected()),
+        }
     }
 
-    fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
-        s.parse().unwrap()
+    fn parse_color_stops(&mut self) -> ParseResult<Vec<ColorStop>> {
+        let mut stops = Vec::new();
+        loop {
+            self.consume_whitespace();
+            let color = self.parse_color()?;
+            self.consume_whitespace();
+            let position = if self.starts_numeric() {
+                Some(self.parse_length_value()?)
+            } else {
+                None
+            };
+            stops.push(ColorStop { color, position });
+            self.consume_whitespace();
+            if self.next_char() != Some(',') {
+                break;
+            }
+            self.consume_char();
+        }
+        Ok(stops)
     }
 
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+    /// A `#hex`, `rgb()`/`rgba()` or named color.
+    fn parse_color(&mut self) -> ParseResult<CssColor> {
+        if self.next_char() != Some('#') {
+            let name = self.parse_name()?;
+            if self.next_char() == Some('(') {
+                return match &*name.to_ascii_lowercase() {
+                    "rgb" | "rgba" => self.parse_rgb(),
+                    _ => Err(self.unrecognized(&name)),
+                };
+            }
+            return named_color(&name).ok_or_else(|| self.unrecognized(&name));
+        }
+        self.expect_char('#')?;
+        let hex = self.consume_while(|c| c.is_ascii_hexdigit());
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).unwrap() * 17;
+        let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        match hex.len() {
+            3 => Ok(CssColor::Rgba(digit(0), digit(1), digit(2), 255)),
+            4 => Ok(CssColor::Rgba(digit(0), digit(1), digit(2), digit(3))),
+            6 => Ok(CssColor::Rgba(pair(0), pair(2), pair(4), 255)),
+            8 => Ok(CssColor::Rgba(pair(0), pair(2), pair(4), pair(6))),
+            _ => Err(self.unrecognized(&format!("#{}", hex))),
         }
     }
 
-    fn parse_rules(&mut self) -> Vec<Rule> {
-        let mut rules = Vec::new();
-        while !self.eof() {
-            rules.push(self.parse_rule());
+    /// The arguments of `rgb()` or `rgba()`, with either commas or spaces and
+    /// an optional `/` before the alpha. Channels may be percentages.
+    fn parse_rgb(&mut self) -> ParseResult<CssColor> {
+        self.expect_char('(')?;
+        let mut channels = vec![];
+        loop {
             self.consume_whitespace();
+            match self.next_char() {
+                Some(')') => break,
+                Some(',' | '/') => {
+                    self.consume_char();
+                    continue;
+                }
+                _ => {}
+            }
+            let value = self.parse_float()?;
+            let percent = self.next_char() == Some('%');
+            if percent {
+                self.consume_char();
+            }
+            channels.push((value, percent));
+        }
+        self.consume_char();
+        let channel = |(value, percent): (f32, bool)| {
+            let value = if percent { value * 2.55 } else { value };
+            value.round().clamp(0.0, 255.0) as u8
+        };
+        let alpha = |(value, percent): (f32, bool)| {
+            let value = if percent { value / 100.0 } else { value };
+            (value * 255.0).round().clamp(0.0, 255.0) as u8
+        };
+        match channels[..] {
+            [r, g, b] => Ok(CssColor::Rgba(channel(r), channel(g), channel(b), 255)),
+            [r, g, b, a] => Ok(CssColor::Rgba(channel(r), channel(g), channel(b), alpha(a))),
+            _ => Err(self.unrecognized("rgb")),
+        }
+    }
+
+    fn parse_length_value(&mut self) -> ParseResult<CssLength> {
+        Ok(CssLength {
+            value: self.parse_float()?,
+            unit: self.parse_unit()?,
+        })
+    }
+
+    fn parse_angle(&mut self) -> ParseResult<Angle> {
+        let amount = self.parse_float()?;
+        let unit = self.parse_identifier();
+        self.angle(amount, &unit)
+    }
+
+    fn angle(&self, amount: f32, unit: &str) -> ParseResult<Angle> {
+        match &*unit.to_ascii_lowercase() {
+            "deg" => Ok(Angle(amount)),
+            "grad" => Ok(Angle(amount * 0.9)),
+            "rad" => Ok(Angle(amount.to_degrees())),
+            "turn" => Ok(Angle(amount * 360.0)),
+            _ => Err(self.unrecognized(unit)),
         }
-        rules
     }
-}
 
-pub fn parse(input: String) -> StyleSheet {
-    let mut parser = Parser { pos: 0, input };
-    StyleSheet {
-        rules: parser.parse_rules(),
+    fn parse_float(&mut self) -> ParseResult<f32> {
+        let mut s = String::new();
+        if matches!(self.next_char(), Some('-' | '+')) {
+            s.push(self.consume_char());
+        }
+        s.push_str(&self.consume_while(|c| matches!(c, '0'..='9' | '.')));
+        s.parse().map_err(|_| self.unrecognized(&s))
+    }
+
+    fn parse_unit(&mut self) -> ParseResult<Unit> {
+        if self.next_char() == Some('%') {
+            self.consume_char();
+            return Ok(Unit::Percent);
+        }
+        let unit = self.parse_identifier();
+        self.length_unit(&unit)
+    }
+
+    fn length_unit(&self, unit: &str) -> ParseResult<Unit> {
+        match &*unit.to_ascii_lowercase() {
+            "px" => Ok(Unit::Px),
+            "em" => Ok(Unit::Em),
+            "rem" => Ok(Unit::Rem),
+            _ => Err(self.unrecognized(unit)),
+        }
     }
 }
 
-#[derive(Default, Debug, Clone)]
-struct SingleSelector {
-    tag_name: Option<String>,
-    id: Option<String>,
-    classes: Vec<String>,
+/// The CSS 2.1 color keywords, plus `grey` and `transparent`.
+fn named_color(name: &str) -> Option<CssColor> {
+    let (r, g, b, a) = match &*name.to_ascii_lowercase() {
+        "black" => (0, 0, 0, 255),
+        "silver" => (192, 192, 192, 255),
+        "gray" | "grey" => (128, 128, 128, 255),
+        "white" => (255, 255, 255, 255),
+        "maroon" => (128, 0, 0, 255),
+        "red" => (255, 0, 0, 255),
+        "purple" => (128, 0, 128, 255),
+        "fuchsia" => (255, 0, 255, 255),
+        "green" => (0, 128, 0, 255),
+        "lime" => (0, 255, 0, 255),
+        "olive" => (128, 128, 0, 255),
+        "yellow" => (255, 255, 0, 255),
+        "navy" => (0, 0, 128, 255),
+        "blue" => (0, 0, 255, 255),
+        "teal" => (0, 128, 128, 255),
+        "aqua" => (0, 255, 255, 255),
+        "orange" => (255, 165, 0, 255),
+        "transparent" => (0, 0, 0, 0),
+        _ => return None,
+    };
+    Some(CssColor::Rgba(r, g, b, a))
 }
 
-#[derive(Debug, Clone)]
-enum Selector {
-    Single(SingleSelector),
+/// Parses a stylesheet one rule at a time, without collecting them into a
+/// [`StyleSheet`]. As an iterator it stops after the first error.
+pub struct CssParser {
+    parser: Parser,
+    failed: bool,
 }
 
-impl fmt::Display for Selector {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self {
-            Self::Single(selector) => {
-                if let Some(tag_name) = &selector.tag_name {
-                    write!(f, "{}", tag_name).unwrap();
-                }
-                if selector.classes.is_empty() {
-                    write!(f, ".{}", selector.classes.join(".")).unwrap();
-                }
-                if let Some(id) = &selector.id {
-                    write!(f, "#{}", id).unwrap();
+impl CssParser {
+    pub fn new(input: &str) -> CssParser {
+        CssParser {
+            parser: Parser::new(input.to_string()),
+            failed: false,
+        }
+    }
+
+    /// The next rule, or `None` once the input is exhausted.
+    pub fn parse_next_rule(&mut self) -> Result<Option<Rule>, CssParseError> {
+        loop {
+            self.parser.consume_whitespace();
+            if self.parser.eof() {
+                if self.parser.media.is_some() {
+                    return Err(CssParseError::UnexpectedEof);
                 }
-                Ok(())
+                return Ok(None);
+            }
+            if self.parser.next_char() == Some('}') && self.parser.media.is_some() {
+                self.parser.consume_char();
+                self.parser.media = None;
+            } else if self.parser.starts_with_ignore_case("@media") {
+                self.parser.parse_media_prelude()?;
+            } else {
+                return self.parser.parse_rule().map(Some);
             }
         }
     }
 }
 
-#[derive(Debug, Clone)]
-enum Value {
-    Keyword(String),
-    Length(f32, Unit),
-    Color(ColorValue),
+impl Iterator for CssParser {
+    type Item = Result<Rule, CssParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.parse_next_rule();
+        self.failed = result.is_err();
+        result.transpose()
+    }
 }
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self {
-            Self::Keyword(keyword) => {
-                write!(f, "{}", keyword)
-            }
-            Self::Color(color) => {
-                write!(f, "{}", color)
-            }
-            Self::Length(amount, unit) => {
-                write!(f, "{}{}", amount, unit)
+pub fn parse(input: String) -> Result<StyleSheet, CssParseError> {
+    parse_with_source_map(&input).map(|(sheet, _)| sheet)
+}
+
+/// A 1-based line and column, counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// Where each rule of a parsed [`StyleSheet`], and each of its declarations,
+/// starts in the source.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    rules: Vec<SourceLocation>,
+    declarations: Vec<Vec<SourceLocation>>,
+}
+
+impl SourceMap {
+    pub fn rule(&self, rule: usize) -> Option<SourceLocation> {
+        self.rules.get(rule).copied()
+    }
+
+    pub fn declaration(&self, rule: usize, declaration: usize) -> Option<SourceLocation> {
+        self.declarations.get(rule)?.get(declaration).copied()
+    }
+}
+
+/// Like [`parse`], also returning where each rule and declaration starts.
+pub fn parse_with_source_map(input: &str) -> Result<(StyleSheet, SourceMap), CssParseError> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let locate = |pos: usize| {
+        let line = line_starts.partition_point(|start| *start <= pos);
+        let line_start = line_starts[line - 1];
+        SourceLocation {
+            line: line as u32,
+            col: input[line_start..pos].chars().count() as u32 + 1,
+        }
+    };
+
+    let mut parser = CssParser::new(input);
+    let mut rules = vec![];
+    let mut map = SourceMap::default();
+    while let Some(rule) = parser.parse_next_rule()? {
+        map.rules.push(locate(parser.parser.rule_start));
+        map.declarations.push(
+            parser
+                .parser
+                .declaration_starts
+                .iter()
+                .map(|pos| locate(*pos))
+                .collect(),
+        );
+        rules.push(rule);
+    }
+    Ok((StyleSheet { rules }, map))
+}
+
+/// Replaces `{{name}}` and `$(name)` placeholders in raw CSS with their value
+/// in `vars`, for theming before the sheet is parsed. Unknown names and
+/// unclosed placeholders are left as written.
+pub fn preprocess_css(input: &str, vars: &HashMap<&str, &str>) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find(['{', '$']) {
+        let close = match &rest[start..] {
+            s if s.starts_with("{{") => "}}",
+            s if s.starts_with("$(") => ")",
+            _ => {
+                result.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+                continue;
             }
+        };
+        let Some(end) = rest[start + 2..].find(close).map(|end| start + 2 + end) else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        match vars.get(rest[start + 2..end].trim()) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(&rest[start..end + close.len()]),
         }
+        rest = &rest[end + close.len()..];
     }
+    result.push_str(rest);
+    result
 }
 
-#[derive(Debug, Clone)]
-enum ColorValue {
-    Rgba(u8, u8, u8, u8),
+pub fn preprocess_and_parse(
+    input: &str,
+    vars: &HashMap<&str, &str>,
+) -> Result<StyleSheet, CssParseError> {
+    parse(preprocess_css(input, vars))
 }
 
-impl fmt::Display for ColorValue {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self {
-            Self::Rgba(r, g, b, a) => write!(f, "rgba({}, {}, {}, {})", r, g, b, a),
-        }
+/// Parses the body of a declaration block, without the selector and braces.
+pub fn parse_declarations(input: &str) -> Result<Vec<Declaration>, CssParseError> {
+    let mut parser = Parser::new(input.to_string());
+    let declarations = parser.parse_declarations()?;
+    if !parser.eof() {
+        return Err(parser.unexpected());
     }
+    Ok(declarations)
 }
 
-#[derive(Debug, Clone)]
-enum Unit {
-    Px,
+/// Parses the contents of a `style` attribute. The same as
+/// [`parse_declarations`].
+pub fn parse_inline(style: &str) -> Result<Vec<Declaration>, CssParseError> {
+    parse_declarations(style)
 }
 
-impl fmt::Display for Unit {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self {
-            Self::Px => write!(f, "px"),
-        }
+/// The specificity of a single selector, e.g. `(1, 1, 1)` for `div.class#id`.
+pub fn specificity_of(selector: &str) -> Result<Specificity, CssParseError> {
+    let mut parser = Parser::new(selector.to_string());
+    parser.consume_whitespace();
+    let selector = parser.parse_selector()?;
+    parser.consume_whitespace();
+    if !parser.eof() {
+        return Err(parser.unexpected());
     }
+    Ok(selector.specificity())
 }
 
-#[derive(Debug, Clone)]
-struct Declaration {
-    name: String,
-    value: Value,
+/// A comma-separated list of selectors, such as the prelude of a rule.
+pub fn parse_selector_list(s: &str) -> Result<Vec<Selector>, CssParseError> {
+    let mut parser = Parser::new(s.to_string());
+    let mut selectors = Vec::new();
+    parser.consume_whitespace();
+    loop {
+        selectors.push(parser.parse_selector()?);
+        parser.consume_whitespace();
+        if parser.eof() {
+            return Ok(selectors);
+        }
+        parser.expect_char(',')?;
+        parser.consume_whitespace();
+    }
 }
 
-impl fmt::Display for Declaration {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}: {};", self.name, self.value)
+impl FromStr for StyleSheet {
+    type Err = CssParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s.to_string())
     }
 }
 
-#[derive(Debug, Clone)]
-struct Rule {
-    selectors: Vec<Selector>,
-    declarations: Vec<Declaration>,
+/// Pseudo-elements that may also be written with a single colon.
+const LEGACY_PSEUDO_ELEMENTS: &[&str] = &["before", "after", "first-line", "first-letter"];
+
+/// Pseudo-classes whose argument is a selector list.
+const SELECTOR_PSEUDO_CLASSES: &[&str] = &["not", "is", "where", "has"];
+
+/// A compound selector: everything between two combinators.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SingleSelector {
+    tag_name: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<AttributeSelector>,
+    pseudo_classes: Vec<PseudoClass>,
+    pseudo_element: Option<String>,
 }
 
-impl fmt::Display for Rule {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let prepadding = "  ";
-        let selectors = self
-            .selectors
-            .iter()
-            .map(|d| format!("{d}"))
-            .collect::<Vec<String>>();
-        writeln!(f, "{} {{", selectors.join(", ")).unwrap();
-        let declarations = self
-            .declarations
+impl SingleSelector {
+    pub fn tag_name(&self) -> Option<&str> {
+        self.tag_name.as_deref()
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    pub fn pseudo_element(&self) -> Option<&str> {
+        self.pseudo_element.as_deref()
+    }
+
+    pub fn specificity(&self) -> Specificity {
+        let pseudo_classes = self
+            .pseudo_classes
             .iter()
-            .map(|d| format!("{d}"))
-            .collect::<Vec<String>>();
-        writeln!(f, "{}{}", prepadding, declarations.join("")).unwrap();
-        writeln!(f, "}}")
+            .map(PseudoClass::specificity)
+            .fold(Specificity::default(), |a, b| a + b);
+        Specificity(
+            self.id.iter().count() as u32,
+            (self.classes.len() + self.attributes.len()) as u32,
+            (self.tag_name.iter().count() + self.pseudo_element.iter().count()) as u32,
+        ) + pseudo_classes
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct StyleSheet {
-    rules: Vec<Rule>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AttrOperator {
+    /// `[attr=value]`
+    Equals,
+    /// `[attr~=value]`, one of the whitespace-separated words.
+    Includes,
+    /// `[attr|=value]`, exactly `value` or starting with `value-`.
+    DashMatch,
+    /// `[attr^=value]`
+    Prefix,
+    /// `[attr$=value]`
+    Suffix,
+    /// `[attr*=value]`
+    Substring,
 }
 
-impl fmt::Display for StyleSheet {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.rules.is_empty() {
-            let rules = self
-                .rules
+impl AttrOperator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Equals => "=",
+            Self::Includes => "~=",
+            Self::DashMatch => "|=",
+            Self::Prefix => "^=",
+            Self::Suffix => "$=",
+            Self::Substring => "*=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AttributeSelector {
+    name: String,
+    matcher: Option<(AttrOperator, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PseudoArgument {
+    Selectors(Vec<Selector>),
+    /// Anything else, such as the `2n+1` of `:nth-child(2n+1)`.
+    Raw(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PseudoClass {
+    name: String,
+    argument: Option<PseudoArgument>,
+}
+
+impl PseudoClass {
+    /// `:where()` adds nothing and `:not()`, `:is()` and `:has()` take the
+    /// specificity of their most specific argument.
+    fn specificity(&self) -> Specificity {
+        match &self.argument {
+            Some(PseudoArgument::Selectors(_)) if self.name == "where" => Specificity::default(),
+            Some(PseudoArgument::Selectors(selectors)) => selectors
+                .iter()
+                .map(Selector::specificity)
+                .max()
+                .unwrap_or_default(),
+            _ => Specificity(0, 1, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    Single(SingleSelector),
+    /// The left-hand side, the combinator, and the compound it leads to.
+    Combined(Box<Selector>, Combinator, SingleSelector),
+}
+
+impl Selector {
+    pub fn specificity(&self) -> Specificity {
+        match self {
+            Self::Single(selector) => selector.specificity(),
+            Self::Combined(left, _, right) => left.specificity() + right.specificity(),
+        }
+    }
+
+    /// A rough estimate of how costly the selector is to match: 1 per type,
+    /// class, id or pseudo-element, 3 per attribute selector, 5 per
+    /// pseudo-class, 5 per child or next-sibling combinator and 10 per
+    /// descendant or subsequent-sibling combinator, which may scan many nodes.
+    pub fn complexity_score(&self) -> u32 {
+        match self {
+            Self::Single(selector) => selector.complexity_score(),
+            Self::Combined(left, combinator, right) => {
+                let combinator = match combinator {
+                    Combinator::Child | Combinator::NextSibling => 5,
+                    Combinator::Descendant | Combinator::SubsequentSibling => 10,
+                };
+                left.complexity_score() + combinator + right.complexity_score()
+            }
+        }
+    }
+}
+
+impl SingleSelector {
+    fn complexity_score(&self) -> u32 {
+        let simple = self.tag_name.iter().count()
+            + self.id.iter().count()
+            + self.classes.len()
+            + self.pseudo_element.iter().count();
+        (simple + 3 * self.attributes.len() + 5 * self.pseudo_classes.len()) as u32
+    }
+}
+
+/// Selector specificity as an `(id, class, type)` triple, compared lexicographically.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+
+impl ops::Add for Specificity {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+impl SingleSelector {
+    fn write(&self, out: &mut String, minified: bool) {
+        let start = out.len();
+        if let Some(tag_name) = &self.tag_name {
+            out.push_str(tag_name);
+        }
+        if let Some(id) = &self.id {
+            out.push('#');
+            out.push_str(id);
+        }
+        for class in &self.classes {
+            out.push('.');
+            out.push_str(class);
+        }
+        for attribute in &self.attributes {
+            out.push('[');
+            out.push_str(&attribute.name);
+            if let Some((operator, value)) = &attribute.matcher {
+                out.push_str(operator.as_str());
+                let quote = if value.contains('"') { '\'' } else { '"' };
+                out.push(quote);
+                out.push_str(value);
+                out.push(quote);
+            }
+            out.push(']');
+        }
+        for pseudo in &self.pseudo_classes {
+            out.push(':');
+            out.push_str(&pseudo.name);
+            match &pseudo.argument {
+                Some(PseudoArgument::Selectors(selectors)) => {
+                    out.push('(');
+                    write_selectors(selectors, out, minified);
+                    out.push(')');
+                }
+                Some(PseudoArgument::Raw(raw)) => {
+                    out.push('(');
+                    out.push_str(raw);
+                    out.push(')');
+                }
+                None => {}
+            }
+        }
+        if out.len() == start {
+            out.push('*');
+        }
+        if let Some(pseudo_element) = &self.pseudo_element {
+            out.push_str("::");
+            out.push_str(pseudo_element);
+        }
+    }
+}
+
+impl Selector {
+    fn write(&self, out: &mut String, minified: bool) {
+        match self {
+            Self::Single(selector) => selector.write(out, minified),
+            Self::Combined(left, combinator, right) => {
+                left.write(out, minified);
+                let combinator = match combinator {
+                    Combinator::Descendant => " ",
+                    Combinator::Child => ">",
+                    Combinator::NextSibling => "+",
+                    Combinator::SubsequentSibling => "~",
+                };
+                if minified || combinator == " " {
+                    out.push_str(combinator);
+                } else {
+                    out.push_str(&format!(" {} ", combinator));
+                }
+                right.write(out, minified);
+            }
+        }
+    }
+
+    fn to_minified_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, true);
+        out
+    }
+}
+
+fn write_selectors(selectors: &[Selector], out: &mut String, minified: bool) {
+    for (i, selector) in selectors.iter().enumerate() {
+        if i > 0 {
+            out.push_str(if minified { "," } else { ", " });
+        }
+        selector.write(out, minified);
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, false);
+        write!(f, "{}", out)
+    }
+}
+
+/// The index of the child of `parent` holding `element`, found by identity.
+fn child_index(element: &ElementData, parent: &Node) -> Option<usize> {
+    parent.children().iter().position(
+        |child| matches!(&child.node_type, NodeType::Element(data) if std::ptr::eq(data, element)),
+    )
+}
+
+/// The element children of `parent` that come before `element`.
+fn preceding_elements<'a>(element: &ElementData, parent: Option<&'a Node>) -> Vec<&'a Node> {
+    let Some(parent) = parent else {
+        return vec![];
+    };
+    let siblings = parent.children();
+    let index = child_index(element, parent).unwrap_or(siblings.len());
+    siblings[..index]
+        .iter()
+        .filter(|c| c.tag_name().is_some())
+        .collect()
+}
+
+/// The element children of `parent` that come after `element`.
+fn following_elements<'a>(element: &ElementData, parent: Option<&'a Node>) -> Vec<&'a Node> {
+    let Some(parent) = parent else {
+        return vec![];
+    };
+    let siblings = parent.children();
+    let Some(index) = child_index(element, parent) else {
+        return vec![];
+    };
+    siblings[index + 1..]
+        .iter()
+        .filter(|c| c.tag_name().is_some())
+        .collect()
+}
+
+/// Parses the `an+b` of `:nth-child()` and friends, including `odd` and `even`.
+fn parse_nth(argument: &str) -> Option<(i32, i32)> {
+    let argument: String = argument
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match &*argument {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    let Some((a, b)) = argument.split_once('n') else {
+        return Some((0, argument.parse().ok()?));
+    };
+    let a = match a {
+        "" | "+" => 1,
+        "-" => -1,
+        a => a.parse().ok()?,
+    };
+    let b = match b {
+        "" => 0,
+        b if b.starts_with(['+', '-']) => b.strip_prefix('+').unwrap_or(b).parse().ok()?,
+        _ => return None,
+    };
+    Some((a, b))
+}
+
+/// Whether the 1-based `index` is `a*n + b` for some `n >= 0`.
+fn nth_matches((a, b): (i32, i32), index: i32) -> bool {
+    match a {
+        0 => index == b,
+        a => (index - b) % a == 0 && (index - b) / a >= 0,
+    }
+}
+
+impl AttributeSelector {
+    fn matches(&self, element: &ElementData) -> bool {
+        let Some(actual) = element.attr(&self.name) else {
+            return false;
+        };
+        let Some((operator, expected)) = &self.matcher else {
+            return true;
+        };
+        let expected = expected.as_str();
+        match operator {
+            AttrOperator::Equals => actual == expected,
+            AttrOperator::Includes => actual.split_ascii_whitespace().any(|w| w == expected),
+            AttrOperator::DashMatch => {
+                actual == expected
+                    || actual
+                        .strip_prefix(expected)
+                        .is_some_and(|rest| rest.starts_with('-'))
+            }
+            AttrOperator::Prefix => !expected.is_empty() && actual.starts_with(expected),
+            AttrOperator::Suffix => !expected.is_empty() && actual.ends_with(expected),
+            AttrOperator::Substring => !expected.is_empty() && actual.contains(expected),
+        }
+    }
+}
+
+impl PseudoClass {
+    /// Structural and logical pseudo-classes. Dynamic ones such as `:hover`
+    /// never match since there's no user interaction to track.
+    fn matches(&self, element: &ElementData, ancestors: &[&Node]) -> bool {
+        let parent = ancestors.last().copied();
+        let same_type = |nodes: Vec<&Node>| {
+            nodes
+                .into_iter()
+                .filter(|n| n.tag_name() == Some(element.tag_name()))
+                .count() as i32
+        };
+        let nth = |count: i32| match &self.argument {
+            Some(PseudoArgument::Raw(raw)) => {
+                parse_nth(raw).is_some_and(|nth| nth_matches(nth, count + 1))
+            }
+            _ => false,
+        };
+        match (self.name.as_str(), &self.argument) {
+            ("not", Some(PseudoArgument::Selectors(selectors))) => !selectors
+                .iter()
+                .any(|s| s.matches_element(element, ancestors)),
+            ("is" | "where", Some(PseudoArgument::Selectors(selectors))) => selectors
+                .iter()
+                .any(|s| s.matches_element(element, ancestors)),
+            ("has", Some(PseudoArgument::Selectors(selectors))) => {
+                // Descendants are matched with this element among their
+                // ancestors, which takes a copy if it isn't in a tree.
+                let detached;
+                let in_tree = parent.and_then(|p| Some(&p.children()[child_index(element, p)?]));
+                let node = match in_tree {
+                    Some(node) => node,
+                    None => {
+                        detached = Node {
+                            node_type: NodeType::Element(element.clone()),
+                        };
+                        &detached
+                    }
+                };
+                let mut chain = ancestors.to_vec();
+                chain.push(node);
+                has_matching_descendant(node, &mut chain, selectors)
+            }
+            ("root", None) => ancestors.iter().all(|a| a.tag_name().is_none()),
+            ("empty", None) => element
+                .children()
+                .iter()
+                .all(|c| matches!(&c.node_type, NodeType::Comment(_))),
+            ("first-child", None) => preceding_elements(element, parent).is_empty(),
+            ("last-child", None) => following_elements(element, parent).is_empty(),
+            ("only-child", None) => {
+                preceding_elements(element, parent).is_empty()
+                    && following_elements(element, parent).is_empty()
+            }
+            ("first-of-type", None) => same_type(preceding_elements(element, parent)) == 0,
+            ("last-of-type", None) => same_type(following_elements(element, parent)) == 0,
+            ("only-of-type", None) => {
+                same_type(preceding_elements(element, parent)) == 0
+                    && same_type(following_elements(element, parent)) == 0
+            }
+            ("nth-child", _) => nth(preceding_elements(element, parent).len() as i32),
+            ("nth-last-child", _) => nth(following_elements(element, parent).len() as i32),
+            ("nth-of-type", _) => nth(same_type(preceding_elements(element, parent))),
+            ("nth-last-of-type", _) => nth(same_type(following_elements(element, parent))),
+            _ => false,
+        }
+    }
+}
+
+fn has_matching_descendant<'a>(
+    node: &'a Node,
+    chain: &mut Vec<&'a Node>,
+    selectors: &[Selector],
+) -> bool {
+    node.children().iter().any(|child| {
+        if child.tag_name().is_none() {
+            return false;
+        }
+        if selectors.iter().any(|s| s.matches(child, chain)) {
+            return true;
+        }
+        chain.push(child);
+        let found = has_matching_descendant(child, chain, selectors);
+        chain.pop();
+        found
+    })
+}
+
+impl SingleSelector {
+    /// Whether this compound matches `element`, given its ancestors from the
+    /// root down to its parent. Pseudo-elements never match an element.
+    pub fn matches(&self, element: &Node, ancestors: &[&Node]) -> bool {
+        match &element.node_type {
+            NodeType::Element(data) => self.matches_element(data, ancestors),
+            _ => false,
+        }
+    }
+
+    /// Like [`SingleSelector::matches`] for a borrowed element.
+    pub fn matches_element(&self, element: &ElementData, ancestors: &[&Node]) -> bool {
+        let tag_name = element.tag_name();
+        self.pseudo_element.is_none()
+            && self
+                .tag_name
+                .as_ref()
+                .is_none_or(|tag| tag.eq_ignore_ascii_case(tag_name))
+            && self
+                .id
+                .as_ref()
+                .is_none_or(|id| element.attr("id") == Some(id))
+            && self.classes.iter().all(|class| element.has_class(class))
+            && self.attributes.iter().all(|a| a.matches(element))
+            && self
+                .pseudo_classes
+                .iter()
+                .all(|p| p.matches(element, ancestors))
+    }
+}
+
+impl Selector {
+    /// The rightmost compound, which the matched element itself must match.
+    fn subject(&self) -> &SingleSelector {
+        match self {
+            Self::Single(selector) | Self::Combined(_, _, selector) => selector,
+        }
+    }
+
+    /// Whether the selector matches `element`, given its ancestors from the
+    /// root down to its parent (see [`crate::dom::collect_ancestor_chain`]).
+    pub fn matches(&self, element: &Node, ancestors: &[&Node]) -> bool {
+        match &element.node_type {
+            NodeType::Element(data) => self.matches_element(data, ancestors),
+            _ => false,
+        }
+    }
+
+    /// Like [`Selector::matches`] for a borrowed element. Siblings are found
+    /// by looking `element` up among the children of its parent.
+    pub fn matches_element(&self, element: &ElementData, ancestors: &[&Node]) -> bool {
+        let (left, combinator, right) = match self {
+            Self::Single(selector) => return selector.matches_element(element, ancestors),
+            Self::Combined(left, combinator, right) => (left, combinator, right),
+        };
+        if !right.matches_element(element, ancestors) {
+            return false;
+        }
+        let parent = ancestors.last().copied();
+        match combinator {
+            Combinator::Descendant => (0..ancestors.len())
+                .rev()
+                .any(|i| left.matches(ancestors[i], &ancestors[..i])),
+            Combinator::Child => match ancestors.split_last() {
+                Some((parent, rest)) => left.matches(parent, rest),
+                None => false,
+            },
+            Combinator::NextSibling => preceding_elements(element, parent)
+                .last()
+                .is_some_and(|sibling| left.matches(sibling, ancestors)),
+            Combinator::SubsequentSibling => preceding_elements(element, parent)
+                .iter()
+                .any(|sibling| left.matches(sibling, ancestors)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CssValue {
+    Keyword(String),
+    Number(f32),
+    Length(CssLength),
+    Color(CssColor),
+    Gradient(Gradient),
+    Url(String),
+    /// A duration in seconds.
+    Time(f32),
+    Angle(Angle),
+    Function(String, Vec<CssValue>),
+    Counter {
+        name: String,
+        style: Option<ListStyleType>,
+    },
+    /// Space-separated component values, e.g. `1 1 auto`.
+    List(Vec<CssValue>),
+    /// Comma-separated values, e.g. `a 1s, b 2s`.
+    CommaList(Vec<CssValue>),
+    /// Cursor images in order of preference, ending with a keyword.
+    Cursor(Vec<CursorValue>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorValue {
+    Url(String),
+    Keyword(CursorKeyword),
+}
+
+impl fmt::Display for CursorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Url(url) => write!(f, "url(\"{}\")", url),
+            Self::Keyword(keyword) => write!(f, "{}", keyword.as_keyword()),
+        }
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T], separator: &str) -> String {
+    items
+        .iter()
+        .map(|item| format!("{item}"))
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+impl fmt::Display for CssValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::Keyword(keyword) => {
+                write!(f, "{}", keyword)
+            }
+            Self::Number(number) => {
+                write!(f, "{}", number)
+            }
+            Self::Color(color) => {
+                write!(f, "{}", color)
+            }
+            Self::Length(length) => {
+                write!(f, "{}", length)
+            }
+            Self::Gradient(gradient) => {
+                write!(f, "{}", gradient)
+            }
+            Self::Url(url) => {
+                write!(f, "url(\"{}\")", url)
+            }
+            Self::Time(seconds) => {
+                write!(f, "{}s", seconds)
+            }
+            Self::Angle(angle) => {
+                write!(f, "{}", angle)
+            }
+            Self::Function(name, args) => {
+                write!(f, "{}({})", name, join(args, ", "))
+            }
+            Self::Counter { name, style } => match style {
+                Some(style) => write!(f, "counter({}, {})", name, style.as_keyword()),
+                None => write!(f, "counter({})", name),
+            },
+            Self::List(values) => {
+                write!(f, "{}", join(values, " "))
+            }
+            Self::CommaList(values) => {
+                write!(f, "{}", join(values, ", "))
+            }
+            Self::Cursor(values) => {
+                write!(f, "{}", join(values, ", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CssColor {
+    Rgba(u8, u8, u8, u8),
+}
+
+impl CssColor {
+    /// The shortest `#hex` spelling of the color.
+    pub fn to_hex(&self) -> String {
+        let Self::Rgba(r, g, b, a) = *self;
+        let channels: &[u8] = if a == 255 { &[r, g, b] } else { &[r, g, b, a] };
+        if channels.iter().all(|c| c % 17 == 0) {
+            let digits: String = channels.iter().map(|c| format!("{:x}", c / 17)).collect();
+            format!("#{}", digits)
+        } else {
+            let digits: String = channels.iter().map(|c| format!("{:02x}", c)).collect();
+            format!("#{}", digits)
+        }
+    }
+}
+
+impl fmt::Display for CssColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::Rgba(..) => write!(f, "{}", self.to_hex()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CssLength {
+    pub value: f32,
+    pub unit: Unit,
+}
+
+impl fmt::Display for CssLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.value, self.unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Px,
+    Em,
+    Rem,
+    Percent,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::Px => write!(f, "px"),
+            Self::Em => write!(f, "em"),
+            Self::Rem => write!(f, "rem"),
+            Self::Percent => write!(f, "%"),
+        }
+    }
+}
+
+/// An angle in degrees. Side keywords such as `to right` are converted on parse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Angle(pub f32);
+
+impl fmt::Display for Angle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}deg", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadialShape {
+    Circle,
+    Ellipse,
+}
+
+impl fmt::Display for RadialShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::Circle => write!(f, "circle"),
+            Self::Ellipse => write!(f, "ellipse"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorStop {
+    pub color: CssColor,
+    pub position: Option<CssLength>,
+}
+
+impl fmt::Display for ColorStop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.position {
+            Some(position) => write!(f, "{} {}", self.color, position),
+            None => write!(f, "{}", self.color),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gradient {
+    Linear {
+        angle: Angle,
+        stops: Vec<ColorStop>,
+    },
+    Radial {
+        shape: RadialShape,
+        stops: Vec<ColorStop>,
+    },
+}
+
+impl fmt::Display for Gradient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, first, stops) = match &self {
+            Self::Linear { angle, stops } => ("linear-gradient", angle.to_string(), stops),
+            Self::Radial { shape, stops } => ("radial-gradient", shape.to_string(), stops),
+        };
+        let stops = stops
+            .iter()
+            .map(|s| format!("{s}"))
+            .collect::<Vec<String>>();
+        write!(f, "{}({}, {})", name, first, stops.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub value: CssValue,
+    pub important: bool,
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.important {
+            write!(f, "{}: {} !important;", self.name, self.value)
+        } else {
+            write!(f, "{}: {};", self.name, self.value)
+        }
+    }
+}
+
+const SHORTHAND_PROPERTIES: &[&str] = &[
+    "margin",
+    "padding",
+    "border",
+    "background",
+    "font",
+    "flex",
+    "animation",
+    "transition",
+    "list-style",
+    "outline",
+];
+
+/// Whether `property` is one of the shorthands [`Declaration::expand_shorthand`] expands.
+pub fn is_shorthand(property: &str) -> bool {
+    SHORTHAND_PROPERTIES
+        .iter()
+        .any(|shorthand| shorthand.eq_ignore_ascii_case(property))
+}
+
+impl Declaration {
+    pub fn is_shorthand(&self) -> bool {
+        is_shorthand(&self.name)
+    }
+}
+
+/// Replaces every shorthand declaration in `sheet` with its longhands, in
+/// place. Shorthands with invalid values are left as they are.
+pub fn expand_all_shorthands(sheet: &mut StyleSheet) {
+    for rule in &mut sheet.rules {
+        rule.declarations = rule
+            .declarations
+            .iter()
+            .flat_map(|declaration| {
+                declaration
+                    .expand_shorthand()
+                    .unwrap_or_else(|_| vec![declaration.clone()])
+            })
+            .collect();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    selectors: Vec<Selector>,
+    declarations: Vec<Declaration>,
+    media: Option<String>,
+}
+
+impl Rule {
+    pub fn selectors(&self) -> &[Selector] {
+        &self.selectors
+    }
+
+    pub fn declarations(&self) -> &[Declaration] {
+        &self.declarations
+    }
+
+    /// The query of the `@media` block the rule is in, e.g. `screen and (min-width: 600px)`.
+    pub fn media(&self) -> Option<&str> {
+        self.media.as_deref()
+    }
+
+    /// Whether the rule's `@media` query list applies to `media_type`, e.g.
+    /// `"print"`. Media features are assumed to match, so only the media
+    /// type and `not` are considered. Rules outside `@media` always apply.
+    pub fn applies_to_media(&self, media_type: &str) -> bool {
+        let Some(media) = &self.media else {
+            return true;
+        };
+        media.split(',').any(|query| {
+            let mut words = query.split_whitespace().peekable();
+            let negated = words.next_if(|w| w.eq_ignore_ascii_case("not")).is_some();
+            words.next_if(|w| w.eq_ignore_ascii_case("only"));
+            let matches = match words.next() {
+                None => false,
+                Some(word) if word.starts_with('(') => true,
+                Some(word) => {
+                    word.eq_ignore_ascii_case("all") || word.eq_ignore_ascii_case(media_type)
+                }
+            };
+            matches != negated
+        })
+    }
+
+    /// The specificity of the most specific selector matching `element`, if any.
+    pub fn matching_specificity(&self, element: &Node, ancestors: &[&Node]) -> Option<Specificity> {
+        self.selectors
+            .iter()
+            .filter(|selector| selector.matches(element, ancestors))
+            .map(Selector::specificity)
+            .max()
+    }
+
+    /// Whether a selector matching `element` does so through one of the
+    /// element's classes, e.g. `p.note` for `<p class="note">`.
+    pub fn matches_by_class(&self, element: &Node, ancestors: &[&Node]) -> bool {
+        self.selectors.iter().any(|selector| {
+            selector
+                .subject()
+                .classes
+                .iter()
+                .any(|class| element.has_class(class))
+                && selector.matches(element, ancestors)
+        })
+    }
+
+    /// Whether any of the selectors matches `element` on its own, without
+    /// ancestors or siblings. Use [`Rule::matching_specificity`] for an
+    /// element in a tree.
+    pub fn applies_to(&self, element: &ElementData) -> bool {
+        self.selectors
+            .iter()
+            .any(|selector| selector.matches_element(element, &[]))
+    }
+
+    /// [`Rule::applies_to`] for each of `elements`. The work that doesn't
+    /// depend on the element is done once: selectors with combinators or a
+    /// pseudo-element, which can't match an element on its own, are dropped,
+    /// type selectors are lowercased, and compounds that only test the tag,
+    /// id and classes go first. Each element's `id` and `class` attributes are
+    /// then looked up once for all compounds.
+    pub fn matches_any_of(&self, elements: &[&ElementData]) -> Vec<bool> {
+        let mut compounds: Vec<CompiledCompound> = self
+            .selectors
+            .iter()
+            .filter_map(|selector| match selector {
+                Selector::Single(compound) if compound.pseudo_element.is_none() => {
+                    Some(CompiledCompound::new(compound))
+                }
+                _ => None,
+            })
+            .collect();
+        compounds.sort_by_key(|compound| compound.rest.is_some());
+        elements
+            .iter()
+            .map(|element| {
+                let id = element.attr("id");
+                let classes = element.attr("class").unwrap_or_default();
+                compounds
+                    .iter()
+                    .any(|compound| compound.matches(element, id, classes))
+            })
+            .collect()
+    }
+}
+
+/// A compound selector prepared for [`Rule::matches_any_of`].
+struct CompiledCompound<'a> {
+    /// The type selector in lowercase.
+    tag_name: Option<String>,
+    id: Option<&'a str>,
+    classes: &'a [String],
+    /// The compound itself, if it has attribute selectors or pseudo-classes.
+    rest: Option<&'a SingleSelector>,
+}
+
+impl<'a> CompiledCompound<'a> {
+    fn new(compound: &'a SingleSelector) -> Self {
+        let rest = !compound.attributes.is_empty() || !compound.pseudo_classes.is_empty();
+        Self {
+            tag_name: compound
+                .tag_name
+                .as_ref()
+                .map(|tag| tag.to_ascii_lowercase()),
+            id: compound.id.as_deref(),
+            classes: &compound.classes,
+            rest: rest.then_some(compound),
+        }
+    }
+
+    /// `id` and `classes` are the element's attributes of those names.
+    fn matches(&self, element: &ElementData, id: Option<&str>, classes: &str) -> bool {
+        self.tag_name.as_ref().is_none_or(|tag| {
+            let name = element.tag_name();
+            name == tag || name.eq_ignore_ascii_case(tag)
+        }) && self.id.is_none_or(|expected| id == Some(expected))
+            && self
+                .classes
+                .iter()
+                .all(|class| classes.split_ascii_whitespace().any(|c| c == class))
+            && self.rest.is_none_or(|compound| {
+                compound.attributes.iter().all(|a| a.matches(element))
+                    && compound
+                        .pseudo_classes
+                        .iter()
+                        .all(|p| p.matches(element, &[]))
+            })
+    }
+}
+
+impl Rule {
+    pub fn to_css_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = if self.media.is_some() { "  " } else { "" };
+        if let Some(media) = &self.media {
+            writeln!(f, "@media {} {{", media)?;
+        }
+        writeln!(f, "{}{} {{", indent, join(&self.selectors, ", "))?;
+        for declaration in &self.declarations {
+            writeln!(f, "{}  {}", indent, declaration)?;
+        }
+        writeln!(f, "{}}}", indent)?;
+        if self.media.is_some() {
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CssParseError {
+    UnexpectedEof,
+    UnexpectedChar {
+        pos: usize,
+        found: char,
+    },
+    /// A well-formed but unsupported token, such as an unknown unit or function.
+    Unrecognized {
+        pos: usize,
+        token: String,
+    },
+    InvalidValue {
+        property: String,
+        value: String,
+    },
+    /// The stylesheet couldn't be read or fetched.
+    Load(String),
+    /// Selectors nested more than [`MAX_SELECTOR_DEPTH`] deep, or function
+    /// calls more than [`MAX_FUNCTION_DEPTH`] deep.
+    TooDeep {
+        pos: usize,
+    },
+}
+
+impl fmt::Display for CssParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::UnexpectedEof => write!(f, "unexpected end of input"),
+            Self::UnexpectedChar { pos, found } => {
+                write!(f, "unexpected character {:?} at {}", found, pos)
+            }
+            Self::Unrecognized { pos, token } => {
+                write!(f, "unrecognized token \"{}\" at {}", token, pos)
+            }
+            Self::InvalidValue { property, value } => {
+                write!(f, "invalid value \"{}\" for property {}", value, property)
+            }
+            Self::Load(reason) => write!(f, "could not load stylesheet: {}", reason),
+            Self::TooDeep { pos } => write!(f, "nested too deeply at {}", pos),
+        }
+    }
+}
+
+impl std::error::Error for CssParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleSheet {
+    rules: Vec<Rule>,
+}
+
+impl CssValue {
+    fn to_minified_string(&self) -> String {
+        let join = |values: &[CssValue], separator: &str| {
+            values
+                .iter()
+                .map(CssValue::to_minified_string)
+                .collect::<Vec<String>>()
+                .join(separator)
+        };
+        match self {
+            Self::Color(color) => color.to_hex(),
+            Self::Gradient(gradient) => {
+                let (name, first, stops) = match gradient {
+                    Gradient::Linear { angle, stops } => {
+                        ("linear-gradient", angle.to_string(), stops)
+                    }
+                    Gradient::Radial { shape, stops } => {
+                        ("radial-gradient", shape.to_string(), stops)
+                    }
+                };
+                let stops = stops
+                    .iter()
+                    .map(|stop| match &stop.position {
+                        Some(position) => format!("{} {}", stop.color.to_hex(), position),
+                        None => stop.color.to_hex(),
+                    })
+                    .collect::<Vec<String>>();
+                format!("{}({},{})", name, first, stops.join(","))
+            }
+            Self::Function(name, args) => format!("{}({})", name, join(args, ",")),
+            Self::Counter {
+                name,
+                style: Some(style),
+            } => format!("counter({},{})", name, style.as_keyword()),
+            Self::List(values) => join(values, " "),
+            Self::CommaList(values) => join(values, ","),
+            value => value.to_string(),
+        }
+    }
+}
+
+impl Rule {
+    fn to_minified_string(&self) -> String {
+        let selectors = self
+            .selectors
+            .iter()
+            .map(Selector::to_minified_string)
+            .collect::<Vec<String>>();
+        let declarations = self
+            .declarations
+            .iter()
+            .map(|d| {
+                let important = if d.important { "!important" } else { "" };
+                format!("{}:{}{}", d.name, d.value.to_minified_string(), important)
+            })
+            .collect::<Vec<String>>();
+        let rule = format!("{}{{{}}}", selectors.join(","), declarations.join(";"));
+        match &self.media {
+            Some(media) => format!("@media {}{{{}}}", media, rule),
+            None => rule,
+        }
+    }
+}
+
+impl StyleSheet {
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The rules outside any `@media` block.
+    pub fn unconditional_rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().filter(|rule| rule.media.is_none())
+    }
+
+    /// The rules that apply to `media_type`, e.g. `"screen"` or `"print"`:
+    /// those outside `@media` and those in a matching `@media` block.
+    pub fn rules_for_media(&self, media_type: &str) -> Vec<&Rule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.applies_to_media(media_type))
+            .collect()
+    }
+
+    /// Appends the rules of `other`, so they win over this sheet's rules of
+    /// the same specificity.
+    pub fn merge(&mut self, other: StyleSheet) {
+        self.rules.extend(other.rules);
+    }
+
+    /// Drops declarations that are overridden later in the same rule: for
+    /// each property, only the last `!important` declaration survives, or
+    /// the last declaration if none is important.
+    pub fn dedup_rules(&mut self) {
+        for rule in &mut self.rules {
+            let declarations = &rule.declarations;
+            let survives = |index: usize| {
+                let declaration = &declarations[index];
+                let same_property =
+                    |other: &&Declaration| other.name.eq_ignore_ascii_case(&declaration.name);
+                let overridden = declarations[index + 1..]
+                    .iter()
+                    .filter(same_property)
+                    .any(|later| later.important || !declaration.important);
+                let outranked = !declaration.important
+                    && declarations
+                        .iter()
+                        .filter(same_property)
+                        .any(|d| d.important);
+                !overridden && !outranked
+            };
+            let keep: Vec<bool> = (0..declarations.len()).map(survives).collect();
+            let mut keep = keep.into_iter();
+            rule.declarations.retain(|_| keep.next().unwrap());
+        }
+    }
+
+    /// One sheet per media type named in an `@media` query, holding the
+    /// rules that apply to it as in [`StyleSheet::rules_for_media`], and an
+    /// `"all"` sheet holding only the [`StyleSheet::unconditional_rules`].
+    pub fn split_by_media(&self) -> HashMap<String, StyleSheet> {
+        let mut split = HashMap::from([(
+            "all".to_string(),
+            StyleSheet {
+                rules: self.unconditional_rules().cloned().collect(),
+            },
+        )]);
+        for query in self
+            .rules
+            .iter()
+            .filter_map(Rule::media)
+            .flat_map(|m| m.split(','))
+        {
+            let media_type = query
+                .split_whitespace()
+                .find(|word| {
+                    !word.eq_ignore_ascii_case("not") && !word.eq_ignore_ascii_case("only")
+                })
+                .filter(|word| !word.starts_with('('))
+                .map(str::to_ascii_lowercase);
+            if let Some(Entry::Vacant(entry)) = media_type.map(|m| split.entry(m)) {
+                let rules = self
+                    .rules_for_media(entry.key())
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                entry.insert(StyleSheet { rules });
+            }
+        }
+        split
+    }
+
+    /// The rules with at least one selector among `used_selectors`, for
+    /// inlining the CSS needed to render a page before the full sheet loads.
+    /// Selectors are compared after parsing, so `a>b` finds `a > b`.
+    pub fn extract_critical_css(&self, used_selectors: &[&str]) -> StyleSheet {
+        let used: Vec<Selector> = used_selectors
+            .iter()
+            .filter_map(|selector| parse_selector_list(selector).ok())
+            .flatten()
+            .collect();
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.selectors
+                    .iter()
+                    .any(|selector| used.contains(selector))
+            })
+            .cloned()
+            .collect();
+        StyleSheet { rules }
+    }
+
+    /// The mean [`Selector::complexity_score`] over every selector in the
+    /// sheet, or 0 if there are none.
+    pub fn average_selector_complexity(&self) -> f64 {
+        let scores: Vec<u32> = self
+            .rules
+            .iter()
+            .flat_map(|rule| &rule.selectors)
+            .map(Selector::complexity_score)
+            .collect();
+        if scores.is_empty() {
+            return 0.0;
+        }
+        scores.iter().sum::<u32>() as f64 / scores.len() as f64
+    }
+
+    /// The rule whose selectors have the highest total complexity score, and
+    /// that total. The first one wins a tie.
+    pub fn most_complex_rule(&self) -> Option<(&Rule, u32)> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let score = rule.selectors.iter().map(Selector::complexity_score).sum();
+                (rule, score)
+            })
+            .rev()
+            .max_by_key(|(_, score)| *score)
+    }
+
+    /// All rules of `sheets`, in order.
+    pub fn concat(sheets: Vec<StyleSheet>) -> StyleSheet {
+        let mut result = StyleSheet { rules: vec![] };
+        for sheet in sheets {
+            result.merge(sheet);
+        }
+        result
+    }
+
+    /// The sheet without any insignificant whitespace, e.g. `a,b{color:#f00;margin:0}`.
+    pub fn to_minified_string(&self) -> String {
+        self.rules.iter().map(Rule::to_minified_string).collect()
+    }
+}
+
+impl fmt::Display for StyleSheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.rules.is_empty() {
+            let rules = self
+                .rules
                 .iter()
                 .map(|r| format!("{r}"))
                 .collect::<Vec<String>>();
-            writeln!(f, "{}", rules.join("\n")).unwrap()
+            write!(f, "{}", rules.join("\n"))?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specificity_orders_lexicographically() {
+        assert!(Specificity(1, 0, 0) > Specificity(0, 255, 255));
+        assert!(Specificity(0, 1, 0) > Specificity(0, 0, 9));
+        assert_eq!(
+            Specificity(0, 1, 0) + Specificity(0, 0, 1),
+            Specificity(0, 1, 1)
+        );
+        let mut specificities = vec![
+            Specificity(0, 1, 0),
+            Specificity(1, 0, 0),
+            Specificity(0, 0, 1),
+            Specificity(0, 1, 1),
+        ];
+        specificities.sort();
+        assert_eq!(
+            specificities,
+            vec![
+                Specificity(0, 0, 1),
+                Specificity(0, 1, 0),
+                Specificity(0, 1, 1),
+                Specificity(1, 0, 0),
+            ]
+        );
+    }
+
+    fn value(css: &str) -> CssValue {
+        parse_inline(css).unwrap().remove(0).value
+    }
+
+    fn gradient(css: &str) -> Gradient {
+        match value(&format!("background: {}", css)) {
+            CssValue::Gradient(gradient) => gradient,
+            other => panic!("not a gradient: {:?}", other),
+        }
+    }
+
+    fn stop(r: u8, g: u8, b: u8, a: u8, position: Option<f32>) -> ColorStop {
+        ColorStop {
+            color: CssColor::Rgba(r, g, b, a),
+            position: position.map(|value| CssLength {
+                value,
+                unit: Unit::Percent,
+            }),
+        }
+    }
+
+    #[test]
+    fn linear_gradient_to_side() {
+        assert_eq!(
+            gradient("linear-gradient(to right, #fff, #000)"),
+            Gradient::Linear {
+                angle: Angle(90.0),
+                stops: vec![stop(255, 255, 255, 255, None), stop(0, 0, 0, 255, None)],
+            }
+        );
+        let Gradient::Linear { angle, .. } = gradient("linear-gradient(to top right, #fff, #000)")
+        else {
+            panic!()
+        };
+        assert_eq!(angle, Angle(45.0));
+    }
+
+    #[test]
+    fn linear_gradient_with_angle_and_three_stops() {
+        assert_eq!(
+            gradient("linear-gradient(45deg, #f00 0%, #0f0 50%, #00f 100%)"),
+            Gradient::Linear {
+                angle: Angle(45.0),
+                stops: vec![
+                    stop(255, 0, 0, 255, Some(0.0)),
+                    stop(0, 255, 0, 255, Some(50.0)),
+                    stop(0, 0, 255, 255, Some(100.0)),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn radial_gradient_with_shape() {
+        assert_eq!(
+            gradient("radial-gradient(circle, #fff 0%, #000 100%)"),
+            Gradient::Radial {
+                shape: RadialShape::Circle,
+                stops: vec![
+                    stop(255, 255, 255, 255, Some(0.0)),
+                    stop(0, 0, 0, 255, Some(100.0))
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn gradient_stops_accept_named_and_rgb_colors() {
+        assert_eq!(
+            gradient("linear-gradient(red, blue)"),
+            Gradient::Linear {
+                angle: Angle(180.0),
+                stops: vec![stop(255, 0, 0, 255, None), stop(0, 0, 255, 255, None)],
+            }
+        );
+        assert_eq!(
+            gradient("radial-gradient(rgba(255, 0, 0, 0.5), rgb(0 128 0 / 100%) 50%)"),
+            Gradient::Radial {
+                shape: RadialShape::Ellipse,
+                stops: vec![stop(255, 0, 0, 128, None), stop(0, 128, 0, 255, Some(50.0))],
+            }
+        );
+        assert!(parse_inline("background: linear-gradient(nocolor, blue)").is_err());
+    }
+
+    #[test]
+    fn stylesheet_from_str() {
+        let sheet: StyleSheet = "h1 { color: red; }".parse().unwrap();
+        let [rule] = sheet.rules() else {
+            panic!("expected one rule");
+        };
+        assert_eq!(rule.selectors()[0].to_string(), "h1");
+        assert_eq!(rule.declarations()[0].name, "color");
+        assert_eq!(
+            rule.declarations()[0].value,
+            CssValue::Keyword("red".into())
+        );
+    }
+
+    #[test]
+    fn parse_declarations_without_selector() {
+        let declarations = parse_declarations("color: red; font-size: 14px").unwrap();
+        let names: Vec<&str> = declarations.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["color", "font-size"]);
+        assert_eq!(
+            declarations[1].value,
+            CssValue::Length(CssLength {
+                value: 14.0,
+                unit: Unit::Px
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_css_is_an_error() {
+        assert_eq!(
+            "h1 { color: red;".parse::<StyleSheet>(),
+            Err(CssParseError::UnexpectedEof)
+        );
+        assert!(matches!(
+            "h1 color: red; }".parse::<StyleSheet>(),
+            Err(CssParseError::UnexpectedChar { .. })
+        ));
+        assert!(matches!(
+            "h1 { width: 3furlongs }".parse::<StyleSheet>(),
+            Err(CssParseError::Unrecognized { .. })
+        ));
+        assert!(parse_declarations("color red").is_err());
+        assert!(parse_declarations("color: red; }").is_err());
+    }
+
+    #[test]
+    fn minified_stylesheet_round_trips() {
+        let css = "/* layout */\n\
+                   div > p.intro, ul li + li {\n  color: #ff0000;\n  margin: 0 auto;\n}\n\
+                   @media print {\n  a:hover { text-decoration: underline !important; }\n}\n\
+                   h1 { background: linear-gradient(to right, red, blue 50%); }";
+        let sheet: StyleSheet = css.parse().unwrap();
+        let minified = sheet.to_minified_string();
+        assert!(minified.len() < sheet.to_string().len());
+        assert!(!minified.contains("/*"));
+        assert!(!minified.contains(": ") && !minified.contains("{ ") && !minified.contains('\n'));
+        assert!(minified.contains("div>p.intro,ul li+li{"));
+        assert_eq!(minified.parse::<StyleSheet>().unwrap(), sheet);
+    }
+
+    #[test]
+    fn parse_inline_declarations() {
+        assert_eq!(parse_inline("").unwrap(), vec![]);
+        assert_eq!(parse_inline("  ").unwrap(), vec![]);
+        let with_semicolon = parse_inline(" color: red ; font-size: 14px ; ").unwrap();
+        assert_eq!(
+            with_semicolon,
+            parse_inline("color: red; font-size: 14px").unwrap()
+        );
+        assert_eq!(with_semicolon.len(), 2);
+        assert!(with_semicolon.iter().all(|d| !d.important));
+        assert_eq!(
+            with_semicolon,
+            parse_declarations(" color: red ; font-size: 14px ; ").unwrap()
+        );
+
+        let important = parse_inline("color: red !important; margin: 0").unwrap();
+        assert!(important[0].important);
+        assert_eq!(important[0].value, CssValue::Keyword("red".into()));
+        assert!(!important[1].important);
+    }
+
+    #[test]
+    fn parse_inline_rejects_invalid_declarations() {
+        assert!(parse_inline("color").is_err());
+        assert!(parse_inline(": red").is_err());
+        assert!(parse_inline("1color: red").is_err());
+        assert!(parse_inline("color: red !importantly").is_err());
+    }
+
+    #[test]
+    fn counter_function() {
+        assert_eq!(
+            value("content: counter(section)"),
+            CssValue::Counter {
+                name: "section".into(),
+                style: None
+            }
+        );
+        assert_eq!(
+            value("content: counter(item, upper-roman)"),
+            CssValue::Counter {
+                name: "item".into(),
+                style: Some(ListStyleType::UpperRoman)
+            }
+        );
+        assert!(parse_inline("content: counter(item, sideways)").is_err());
+    }
+
+    #[test]
+    fn specificity_of_selectors() {
+        for (selector, expected) in [
+            ("#id", Specificity(1, 0, 0)),
+            (".class", Specificity(0, 1, 0)),
+            ("div", Specificity(0, 0, 1)),
+            ("div.class#id", Specificity(1, 1, 1)),
+            ("*", Specificity(0, 0, 0)),
+            (":nth-child(2n+1)", Specificity(0, 1, 0)),
+            ("ul li:hover > a.active", Specificity(0, 2, 3)),
+        ] {
+            assert_eq!(specificity_of(selector), Ok(expected), "{}", selector);
+        }
+        for selector in ["", "div {", "a,", "#", "a >"] {
+            assert!(specificity_of(selector).is_err(), "{}", selector);
+        }
+    }
+
+    #[test]
+    fn parse_selector_lists() {
+        let strings = |s: &str| -> Vec<String> {
+            parse_selector_list(s)
+                .unwrap()
+                .iter()
+                .map(Selector::to_string)
+                .collect()
+        };
+        assert_eq!(strings("div.a"), ["div.a"]);
+        assert_eq!(strings("h1,h2 , ul > li"), ["h1", "h2", "ul > li"]);
+        assert_eq!(strings("  a ,\n\tb  "), ["a", "b"]);
+        assert_eq!(parse_selector_list("a,"), Err(CssParseError::UnexpectedEof));
+        assert!(parse_selector_list("a, #, b").is_err());
+        assert!(parse_selector_list("a, b {").is_err());
+        assert!(parse_selector_list("").is_err());
+    }
+
+    #[test]
+    fn rule_display() {
+        let sheet: StyleSheet = "div > p.a, #b { color: #ff0000; margin: 0 auto !important; }"
+            .parse()
+            .unwrap();
+        let rule = &sheet.rules()[0];
+        let css = "div > p.a, #b {\n  color: #f00;\n  margin: 0 auto !important;\n}\n";
+        assert_eq!(format!("{}", rule), css);
+        assert_eq!(rule.to_css_string(), css);
+        assert_eq!(rule.selectors()[1].to_string(), "#b");
+        assert_eq!(rule.declarations()[0].to_string(), "color: #f00;");
+    }
+
+    #[test]
+    fn display_round_trips_through_the_parser() {
+        let css = "a:hover, ul li + li { color: rgba(1, 2, 3, 0.5); width: 1.5em; }\n\
+                   h1 { background: radial-gradient(circle, #fff, red 20%); flex: 1 1 auto; }\n\
+                   @media print { p::before { content: counter(item, lower-roman); } }";
+        let sheet: StyleSheet = css.parse().unwrap();
+        assert_eq!(sheet.to_string().parse::<StyleSheet>().unwrap(), sheet);
+        for declaration in sheet.rules().iter().flat_map(Rule::declarations) {
+            let value = declaration.value.to_string();
+            let reparsed = parse_inline(&format!("x: {}", value)).unwrap();
+            assert_eq!(reparsed[0].value, declaration.value, "{}", value);
+        }
+    }
+
+    #[test]
+    fn css_parser_yields_rules_one_at_a_time() {
+        let css =
+            "h1 { color: red; } /* c */ p, a { margin: 0; }\n@media print { p { color: black; } }";
+        let mut parser = CssParser::new(css);
+        let first = parser.parse_next_rule().unwrap().unwrap();
+        assert_eq!(first.selectors()[0].to_string(), "h1");
+        let rest: Vec<Rule> = parser.collect::<Result<_, _>>().unwrap();
+        let mut rules = vec![first];
+        rules.extend(rest);
+        assert_eq!(rules, parse(css.into()).unwrap().rules());
+    }
+
+    #[test]
+    fn css_parser_stops_after_an_error() {
+        let mut parser = CssParser::new("h1 { color: red; } p { color }");
+        assert!(matches!(parser.next(), Some(Ok(_))));
+        assert!(matches!(parser.next(), Some(Err(_))));
+        assert!(parser.next().is_none());
+        assert_eq!(CssParser::new("  ").parse_next_rule(), Ok(None));
+    }
+
+    /// The value of `property` that wins the cascade for `element`: the one
+    /// from the most specific matching rule, and the latest of those.
+    fn cascaded(sheet: &StyleSheet, element: &Node, property: &str) -> Option<CssValue> {
+        sheet
+            .rules()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, rule)| {
+                let specificity = rule.matching_specificity(element, &[])?;
+                let declaration = rule.declarations().iter().rfind(|d| d.name == property)?;
+                Some(((specificity, index), declaration.value.clone()))
+            })
+            .max_by_key(|(key, _)| *key)
+            .map(|(_, value)| value)
+    }
+
+    #[test]
+    fn merged_rules_come_after_existing_ones() {
+        let mut base: StyleSheet = "p { color: red; } .note { margin: 0; }".parse().unwrap();
+        let theme: StyleSheet = "p { color: blue; }".parse().unwrap();
+        base.merge(theme);
+        let selectors: Vec<String> = base
+            .rules()
+            .iter()
+            .map(|rule| rule.selectors()[0].to_string())
+            .collect();
+        assert_eq!(selectors, ["p", ".note", "p"]);
+
+        let p = crate::html::parse_fragment("<p class=\"note\"></p>".into())
+            .unwrap()
+            .children()[0]
+            .clone();
+        assert_eq!(
+            cascaded(&base, &p, "color"),
+            Some(CssValue::Keyword("blue".into()))
+        );
+    }
+
+    #[test]
+    fn concat_keeps_sheet_order() {
+        let sheets = [
+            "p { color: red; }",
+            "",
+            "p { color: green; } a { color: blue; }",
+        ]
+        .iter()
+        .map(|css| css.parse().unwrap())
+        .collect();
+        let sheet = StyleSheet::concat(sheets);
+        assert_eq!(
+            sheet.to_minified_string(),
+            "p{color:red}p{color:green}a{color:blue}"
+        );
+        assert!(StyleSheet::concat(vec![]).rules().is_empty());
+    }
+
+    fn at(line: u32, col: u32) -> Option<SourceLocation> {
+        Some(SourceLocation { line, col })
+    }
+
+    #[test]
+    fn source_map_tracks_rule_lines() {
+        let css = "h1 {\n  color: red;\n}\n\n  p, a { margin: 0;\n    padding: 1px; }\ndiv{}";
+        let (sheet, map) = parse_with_source_map(css).unwrap();
+        assert_eq!(sheet.rules().len(), 3);
+        assert_eq!(map.rule(0), at(1, 1));
+        assert_eq!(map.rule(1), at(5, 3));
+        assert_eq!(map.rule(2), at(7, 1));
+        assert_eq!(map.rule(3), None);
+    }
+
+    #[test]
+    fn source_map_tracks_declarations() {
+        let css = "h1 {\n  color: red;\n}\n\n  p, a { margin: 0;\n    padding: 1px; }";
+        let (_, map) = parse_with_source_map(css).unwrap();
+        assert_eq!(map.declaration(0, 0), at(2, 3));
+        assert_eq!(map.declaration(0, 1), None);
+        assert_eq!(map.declaration(1, 0), at(5, 10));
+        assert_eq!(map.declaration(1, 1), at(6, 5));
+    }
+
+    #[test]
+    fn source_map_of_a_single_line() {
+        let css = "a { color: red } b { color: blue; margin: 0 }";
+        let (sheet, map) = parse_with_source_map(css).unwrap();
+        for rule in 0..sheet.rules().len() {
+            assert_eq!(map.rule(rule).unwrap().line, 1);
+            for declaration in 0..sheet.rules()[rule].declarations().len() {
+                assert_eq!(map.declaration(rule, declaration).unwrap().line, 1);
+            }
+        }
+        assert_eq!(map.rule(1), at(1, 18));
+        assert_eq!(map.declaration(1, 1), at(1, 35));
+        assert_eq!(parse(css.to_string()).unwrap(), sheet);
+    }
+
+    #[test]
+    fn preprocess_css_replaces_placeholders() {
+        let vars = HashMap::from([("brand", "#336699"), ("gap", "4px")]);
+        assert_eq!(
+            preprocess_css("a { color: {{brand}}; margin: $(gap) {{ gap }}; }", &vars),
+            "a { color: #336699; margin: 4px 4px; }"
+        );
+    }
+
+    #[test]
+    fn preprocess_css_keeps_unknown_placeholders() {
+        let vars = HashMap::from([("gap", "4px")]);
+        assert_eq!(
+            preprocess_css("a { margin: {{size}} $(size); } b { x: $(gap", &vars),
+            "a { margin: {{size}} $(size); } b { x: $(gap"
+        );
+        assert_eq!(
+            preprocess_css("a { b: c } $x {{", &vars),
+            "a { b: c } $x {{"
+        );
+    }
+
+    #[test]
+    fn preprocess_and_parse_parses_the_result() {
+        let vars = HashMap::from([("tag", "h1"), ("align", "center")]);
+        let sheet = preprocess_and_parse("{{tag}} { justify-content: $(align); }", &vars).unwrap();
+        assert_eq!(sheet.to_minified_string(), "h1{justify-content:center}");
+        assert!(preprocess_and_parse("a { color: {{missing}}; }", &vars).is_err());
+    }
+
+    fn fragment(html: &str) -> Node {
+        crate::html::parse_fragment(html.into()).unwrap()
+    }
+
+    fn element_data(node: &Node) -> &ElementData {
+        match &node.node_type {
+            NodeType::Element(data) => data,
+            _ => panic!("expected an element"),
+        }
+    }
+
+    fn rule(css: &str) -> Rule {
+        css.parse::<StyleSheet>().unwrap().rules()[0].clone()
+    }
+
+    #[test]
+    fn rule_applies_to_elements() {
+        let nodes = fragment("<p class=\"note warn\" id=\"x\">a</p>");
+        let p = element_data(&nodes.children()[0]);
+        assert!(rule("p { order: 1 }").applies_to(p));
+        assert!(rule(".warn { order: 1 }").applies_to(p));
+        assert!(rule("p.note#x[id] { order: 1 }").applies_to(p));
+        assert!(rule("h1, .note { order: 1 }").applies_to(p));
+        assert!(!rule("h1, p.other, div p { order: 1 }").applies_to(p));
+        assert!(!rule("p::before { order: 1 }").applies_to(p));
+    }
+
+    #[test]
+    fn rule_applies_to_ignores_context() {
+        let nodes = fragment("<div><p>a</p><p>b</p></div>");
+        let div = &nodes.children()[0];
+        let second = element_data(&div.children()[1]);
+        // On its own the element has no parent or siblings.
+        assert!(!rule("div p { order: 1 }").applies_to(second));
+        assert!(!rule("p + p { order: 1 }").applies_to(second));
+        assert!(rule("p:first-child { order: 1 }").applies_to(second));
+        assert!(rule("div:has(p) { order: 1 }").applies_to(element_data(div)));
+        assert!(!rule("div:has(span) { order: 1 }").applies_to(element_data(div)));
+    }
+
+    #[test]
+    fn matches_element_finds_siblings_by_identity() {
+        let nodes = fragment("<div><p>a</p><p>a</p></div>");
+        let div = &nodes.children()[0];
+        let [first, second] = div.children() else {
+            panic!("expected two children");
+        };
+        let selector = &parse_selector_list("p + p").unwrap()[0];
+        assert!(!selector.matches_element(element_data(first), &[div]));
+        assert!(selector.matches_element(element_data(second), &[div]));
+        assert_eq!(
+            selector.matches_element(element_data(second), &[div]),
+            selector.matches(second, &[div])
+        );
+        let last = &parse_selector_list("p:last-child").unwrap()[0];
+        assert!(!last.matches_element(element_data(first), &[div]));
+        assert!(last.matches_element(element_data(second), &[div]));
+    }
+
+    fn deduped(css: &str) -> String {
+        let mut sheet: StyleSheet = css.parse().unwrap();
+        sheet.dedup_rules();
+        sheet.to_minified_string()
+    }
+
+    #[test]
+    fn dedup_rules_keeps_the_last_declaration() {
+        assert_eq!(
+            deduped("p { order: 1; flex-grow: 2; order: 3; ORDER: 4 }"),
+            "p{flex-grow:2;ORDER:4}"
+        );
+    }
+
+    #[test]
+    fn dedup_rules_respects_important() {
+        assert_eq!(
+            deduped("p { order: 1 !important; order: 2 }"),
+            "p{order:1!important}"
+        );
+        assert_eq!(
+            deduped("p { order: 1 !important; order: 2; order: 3 !important }"),
+            "p{order:3!important}"
+        );
+    }
+
+    #[test]
+    fn dedup_rules_leaves_distinct_properties() {
+        let css = "p{order:1;flex-grow:2}a{order:3}";
+        assert_eq!(deduped(css), css);
+    }
+
+    #[test]
+    fn cursor_keywords() {
+        let keywords = [
+            // General
+            "auto",
+            "default",
+            "none",
+            // Links and status
+            "context-menu",
+            "help",
+            "pointer",
+            "progress",
+            "wait",
+            // Selection
+            "cell",
+            "crosshair",
+            "text",
+            "vertical-text",
+            // Drag and drop
+            "alias",
+            "copy",
+            "move",
+            "no-drop",
+            "not-allowed",
+            "grab",
+            "grabbing",
+            // Resizing and scrolling
+            "all-scroll",
+            "col-resize",
+            "row-resize",
+            "n-resize",
+            "e-resize",
+            "s-resize",
+            "w-resize",
+            "ne-resize",
+            "nw-resize",
+            "se-resize",
+            "sw-resize",
+            "ew-resize",
+            "ns-resize",
+            "nesw-resize",
+            "nwse-resize",
+            // Zooming
+            "zoom-in",
+            "zoom-out",
+        ];
+        for keyword in keywords {
+            let expected = CursorKeyword::from_keyword(keyword).unwrap();
+            assert_eq!(expected.as_keyword(), keyword);
+            assert_eq!(
+                value(&format!("cursor: {}", keyword)),
+                CssValue::Cursor(vec![CursorValue::Keyword(expected)])
+            );
+        }
+    }
+
+    #[test]
+    fn cursor_urls_with_fallback() {
+        let cursor = value("cursor: url(\"custom.cur\"), url(hand.png) , pointer");
+        assert_eq!(
+            cursor,
+            CssValue::Cursor(vec![
+                CursorValue::Url("custom.cur".into()),
+                CursorValue::Url("hand.png".into()),
+                CursorValue::Keyword(CursorKeyword::Pointer),
+            ])
+        );
+        assert_eq!(
+            cursor.to_string(),
+            "url(\"custom.cur\"), url(\"hand.png\"), pointer"
+        );
+    }
+
+    #[test]
+    fn invalid_cursors() {
+        for css in [
+            "cursor: hand",
+            "cursor: url(a.cur)",
+            "cursor: url(a.cur) auto",
+            "cursor: image(a.cur), auto",
+            "cursor: pointer auto",
+        ] {
+            assert!(parse_inline(css).is_err(), "{}", css);
+        }
+    }
+
+    #[test]
+    fn is_shorthand_properties() {
+        for property in [
+            "margin",
+            "padding",
+            "border",
+            "background",
+            "font",
+            "flex",
+            "animation",
+            "transition",
+            "list-style",
+            "outline",
+            "MARGIN",
+        ] {
+            assert!(is_shorthand(property), "{}", property);
+        }
+        for property in ["margin-top", "flex-grow", "color", "list-style-type"] {
+            assert!(!is_shorthand(property), "{}", property);
+        }
+        assert!(parse_inline("flex: 1").unwrap()[0].is_shorthand());
+    }
+
+    #[test]
+    fn expand_all_shorthands_expands_every_rule() {
+        let mut sheet: StyleSheet = "#main p { margin: 1px 2px; flex: 1 } \
+                                     .a, b { padding: 0 !important; list-style: square inside }"
+            .parse()
+            .unwrap();
+        let selectors: Vec<_> = sheet
+            .rules()
+            .iter()
+            .map(|r| r.selectors().to_vec())
+            .collect();
+        expand_all_shorthands(&mut sheet);
+        assert_eq!(
+            sheet.to_minified_string(),
+            "#main p{margin-top:1px;margin-right:2px;margin-bottom:1px;margin-left:2px;\
+             flex-grow:1;flex-shrink:1;flex-basis:0%}\
+             .a,b{padding-top:0!important;padding-right:0!important;\
+             padding-bottom:0!important;padding-left:0!important;\
+             list-style-type:square;list-style-position:inside;list-style-image:none}"
+        );
+        assert!(sheet
+            .rules()
+            .iter()
+            .flat_map(Rule::declarations)
+            .all(|declaration| !declaration.is_shorthand()));
+        let after: Vec<_> = sheet
+            .rules()
+            .iter()
+            .map(|r| r.selectors().to_vec())
+            .collect();
+        assert_eq!(after, selectors);
+        assert_eq!(after[0][0].specificity(), Specificity(1, 0, 1));
+    }
+
+    #[test]
+    fn expand_all_shorthands_leaves_longhands() {
+        let css = "p{margin-top:1px;order:2}a{flex:nonsense}";
+        let mut sheet: StyleSheet = css.parse().unwrap();
+        expand_all_shorthands(&mut sheet);
+        assert_eq!(sheet.to_minified_string(), css);
+    }
+
+    fn selectors_of<'a>(rules: impl IntoIterator<Item = &'a Rule>) -> Vec<String> {
+        rules
+            .into_iter()
+            .map(|rule| rule.selectors()[0].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn rules_for_media_types() {
+        let sheet: StyleSheet = "a { order: 1 } \
+             @media screen { .screen { order: 2 } } \
+             @media print { .print { order: 3 } } \
+             b { order: 4 } \
+             @media only screen and (min-width: 100px), print { .both { order: 5 } } \
+             @media not print { .not-print { order: 6 } } \
+             @media all { .all { order: 7 } }"
+            .parse()
+            .unwrap();
+        assert_eq!(selectors_of(sheet.unconditional_rules()), ["a", "b"]);
+        assert_eq!(
+            selectors_of(sheet.rules_for_media("screen")),
+            ["a", ".screen", "b", ".both", ".not-print", ".all"]
+        );
+        assert_eq!(
+            selectors_of(sheet.rules_for_media("print")),
+            ["a", ".print", "b", ".both", ".all"]
+        );
+        assert_eq!(
+            selectors_of(sheet.rules_for_media("PRINT")),
+            selectors_of(sheet.rules_for_media("print"))
+        );
+    }
+
+    #[test]
+    fn split_by_media_types() {
+        let sheet: StyleSheet = "a { order: 1 } \
+             @media screen { .screen { order: 2 } } \
+             @media print { .print { order: 3 } } \
+             @media not print, (min-width: 10px) { .query { order: 4 } } \
+             @media all { .all { order: 5 } } \
+             b { order: 6 }"
+            .parse()
+            .unwrap();
+        let split = sheet.split_by_media();
+        let mut media_types: Vec<&str> = split.keys().map(String::as_str).collect();
+        media_types.sort();
+        assert_eq!(media_types, ["all", "print", "screen"]);
+        assert_eq!(selectors_of(split["all"].rules()), ["a", "b"]);
+        assert_eq!(
+            selectors_of(split["screen"].rules()),
+            ["a", ".screen", ".query", ".all", "b"]
+        );
+        assert_eq!(
+            selectors_of(split["print"].rules()),
+            ["a", ".print", ".query", ".all", "b"]
+        );
+        assert_eq!(split["screen"].rules()[1].media(), Some("screen"));
+    }
+
+    #[test]
+    fn split_by_media_without_media_rules() {
+        let sheet: StyleSheet = "a { order: 1 }".parse().unwrap();
+        let split = sheet.split_by_media();
+        assert_eq!(split.len(), 1);
+        assert_eq!(split["all"], sheet);
+    }
+
+    #[test]
+    fn extract_critical_css_keeps_used_rules() {
+        let sheet: StyleSheet = "header > nav { order: 1 } \
+             .hero, footer { order: 2 } \
+             .unused { order: 3 } \
+             @media print { .hero { order: 4 } }"
+            .parse()
+            .unwrap();
+        let critical = sheet.extract_critical_css(&["header>nav", ".hero", "main"]);
+        assert_eq!(
+            critical.to_minified_string(),
+            "header>nav{order:1}.hero,footer{order:2}@media print{.hero{order:4}}"
+        );
+        assert!(sheet.extract_critical_css(&[]).rules().is_empty());
+        assert!(sheet.extract_critical_css(&["p["]).rules().is_empty());
+    }
+
+    #[test]
+    fn matches_any_of_agrees_with_applies_to() {
+        let nodes = fragment(
+            "<div><p class=\"a\">1</p><p class=\"a b\" id=\"x\">2</p><span lang=\"en-US\">3</span>\
+             <section><P>4</P></section><p></p></div>",
+        );
+        let elements: Vec<&ElementData> = nodes
+            .iter()
+            .filter(|node| node.tag_name().is_some())
+            .map(element_data)
+            .collect();
+        for css in [
+            "p { order: 1 }",
+            ".a.b { order: 1 }",
+            "#x, span { order: 1 }",
+            "[lang|=en] { order: 1 }",
+            "div p, section { order: 1 }",
+            "p:empty, *:not(p) { order: 1 }",
+            "div:has(.b) { order: 1 }",
+            "p::after { order: 1 }",
+            "* { order: 1 }",
+            "P, SECTION { order: 1 }",
+            "p.b.a#x { order: 1 }",
+            "p.a:not(.b), p::before, #y { order: 1 }",
+            "[lang]:empty, .a.c, div > p { order: 1 }",
+        ] {
+            let rule = rule(css);
+            let expected: Vec<bool> = elements.iter().map(|e| rule.applies_to(e)).collect();
+            assert_eq!(rule.matches_any_of(&elements), expected, "{}", css);
+        }
+        assert_eq!(rule("p { order: 1 }").matches_any_of(&[]), []);
+    }
+
+    #[test]
+    fn selector_complexity_scores() {
+        let score = |selector: &str| {
+            rule(&format!("{} {{ order: 1 }}", selector)).selectors()[0].complexity_score()
+        };
+        assert_eq!(score("p"), 1);
+        assert_eq!(score("#main"), 1);
+        assert_eq!(score("p.note.warn#x"), 4);
+        assert_eq!(score("a[href]"), 4);
+        assert_eq!(score("li:first-child"), 6);
+        assert_eq!(score("p::before"), 2);
+        assert_eq!(score("ul li"), 12);
+        assert_eq!(score("ul > li"), 7);
+        assert_eq!(score("h1 + p"), 7);
+        assert_eq!(score("h1 ~ p"), 12);
+        assert_eq!(
+            score("nav ul > li a[href]:hover"),
+            1 + 10 + 1 + 5 + 1 + 10 + 1 + 3 + 5
+        );
+    }
+
+    #[test]
+    fn stylesheet_complexity_aggregates() {
+        let sheet: StyleSheet = "p { order: 1 }\n\
+                                 ul > li, a[href] { order: 2 }\n\
+                                 nav a:hover { order: 3 }\n\
+                                 div p { order: 4 }"
+            .parse()
+            .unwrap();
+        // p = 1, ul > li = 7, a[href] = 4, nav a:hover = 17, div p = 12.
+        assert_eq!(sheet.average_selector_complexity(), 41.0 / 5.0);
+        let (rule, score) = sheet.most_complex_rule().unwrap();
+        assert_eq!(score, 17);
+        assert_eq!(rule.selectors()[0].to_string(), "nav a:hover");
+
+        let tied: StyleSheet = "ul li { order: 1 } ol li { order: 2 }".parse().unwrap();
+        let (rule, score) = tied.most_complex_rule().unwrap();
+        assert_eq!(score, 12);
+        assert_eq!(rule.selectors()[0].to_string(), "ul li");
+
+        let empty: StyleSheet = "".parse().unwrap();
+        assert_eq!(empty.average_selector_complexity(), 0.0);
+        assert_eq!(empty.most_complex_rule(), None);
+    }
+
+    #[test]
+    fn selector_nesting_is_limited() {
+        let nested = |depth: usize| {
+            format!(
+                "{}a{} {{ order: 1 }}",
+                ":not(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        assert!(nested(MAX_SELECTOR_DEPTH).parse::<StyleSheet>().is_ok());
+        assert_eq!(
+            nested(MAX_SELECTOR_DEPTH + 1).parse::<StyleSheet>(),
+            Err(CssParseError::TooDeep {
+                pos: 5 * (MAX_SELECTOR_DEPTH + 1)
+            })
+        );
+        assert!(matches!(
+            nested(100_000).parse::<StyleSheet>(),
+            Err(CssParseError::TooDeep { .. })
+        ));
+        let siblings = format!(
+            ":is({}a{}, {}b{}) {{ order: 1 }}",
+            ":has(".repeat(MAX_SELECTOR_DEPTH - 1),
+            ")".repeat(MAX_SELECTOR_DEPTH - 1),
+            ":not(".repeat(MAX_SELECTOR_DEPTH - 1),
+            ")".repeat(MAX_SELECTOR_DEPTH - 1)
+        );
+        assert!(siblings.parse::<StyleSheet>().is_ok());
+        assert!(matches!(
+            parse_selector_list(&format!("{}a", ":is(".repeat(100_000))),
+            Err(CssParseError::TooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn function_nesting_is_limited() {
+        let nested = |depth: usize| {
+            format!(
+                "a {{ width: {}1px{} }}",
+                "f(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        assert!(nested(MAX_FUNCTION_DEPTH).parse::<StyleSheet>().is_ok());
+        assert_eq!(
+            nested(MAX_FUNCTION_DEPTH + 1).parse::<StyleSheet>(),
+            Err(CssParseError::TooDeep {
+                pos: 12 + 2 * MAX_FUNCTION_DEPTH
+            })
+        );
+        assert!(matches!(
+            format!("a {{ b: {} }}", "f(".repeat(100_000)).parse::<StyleSheet>(),
+            Err(CssParseError::TooDeep { .. })
+        ));
+        assert!(matches!(
+            parse_inline(&format!("b: {}", "rgb(".repeat(100_000))),
+            Err(CssParseError::TooDeep { .. })
+        ));
+    }
+}
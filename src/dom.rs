@@ -1,20 +1,48 @@
 use core::fmt;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
 
 use crate::{
-    css::{self, StyleSheet},
-    html,
+    css::{self, CssParseError, StyleSheet},
+    html::{self, ParseError},
+    intern::{intern, InternedStr},
+    json::{self, JsonError, JsonValue},
+    style::ComputedStyle,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AttrValue {
     Text(String),
     Implicit,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct AttrMap(pub HashMap<String, AttrValue>);
 
+impl<'a> FromIterator<(&'a str, &'a str)> for AttrMap {
+    fn from_iter<I: IntoIterator<Item = (&'a str, &'a str)>>(iter: I) -> Self {
+        AttrMap(
+            iter.into_iter()
+                .map(|(name, value)| (name.to_string(), AttrValue::Text(value.to_string())))
+                .collect(),
+        )
+    }
+}
+
+impl FromStr for AttrMap {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        html::parse_attr_list(s)
+    }
+}
+
 impl fmt::Display for AttrMap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let i = self
@@ -29,34 +57,69 @@ impl fmt::Display for AttrMap {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ElementData {
-    tag_name: String,
+    tag_name: InternedStr,
     attributes: AttrMap,
     child_nodes: Vec<Node>,
 }
 
-#[derive(Debug, Clone)]
+impl ElementData {
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    pub fn attributes(&self) -> &AttrMap {
+        &self.attributes
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.child_nodes
+    }
+
+    /// The textual value of an attribute. Implicit attributes yield an empty string.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        match self.attributes.0.get(name)? {
+            AttrValue::Text(text) => Some(text),
+            AttrValue::Implicit => Some(""),
+        }
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attr("class")
+            .is_some_and(|classes| classes.split_ascii_whitespace().any(|c| c == class))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum NodeType {
     Element(ElementData),
     Text(String),
     Comment(String),
+    ProcessingInstruction {
+        target: String,
+        data: String,
+    },
+    /// Raw text from a `<![CDATA[ ... ]]>` section.
+    CDATASection(String),
     Document(DocumentData),
+    /// A group of nodes that isn't attached to a document.
+    DocumentFragment(Vec<Node>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Node {
     pub node_type: NodeType,
 }
 
 impl Node {
-    fn pretty_print(&self, f: &mut fmt::Formatter<'_>, indent: usize) {
-        let prepadding = "  ".repeat(indent);
+    fn pretty_print<W: fmt::Write>(&self, f: &mut W, indent: usize, indent_size: usize) {
+        let prepadding = " ".repeat(indent * indent_size);
         match &self.node_type {
             NodeType::Element(data) => {
                 write!(f, "{}<{}", prepadding, data.tag_name).unwrap();
 
-                if data.attributes.0.is_empty() {
+                if !data.attributes.0.is_empty() {
                     write!(f, " {}", data.attributes).unwrap();
                 }
 
@@ -70,7 +133,7 @@ impl Node {
                 let _ = &data
                     .child_nodes
                     .iter()
-                    .for_each(|node| node.pretty_print(f, indent + 1));
+                    .for_each(|node| node.pretty_print(f, indent + 1, indent_size));
 
                 writeln!(f, "{}</{}>", prepadding, data.tag_name).unwrap();
             }
@@ -78,68 +141,4152 @@ impl Node {
                 writeln!(f, "{}{}", prepadding, text).unwrap();
             }
             NodeType::Comment(text) => writeln!(f, "{}<!-- {} -->", prepadding, text).unwrap(),
-            NodeType::Document(_) => {}
+            NodeType::ProcessingInstruction { target, data } => {
+                writeln!(f, "{}<?{} {}?>", prepadding, target, data).unwrap()
+            }
+            NodeType::CDATASection(text) => {
+                writeln!(f, "{}<![CDATA[{}]]>", prepadding, text).unwrap()
+            }
+            NodeType::Document(_) => self
+                .children()
+                .iter()
+                .for_each(|node| node.pretty_print(f, indent, indent_size)),
+            NodeType::DocumentFragment(children) => children
+                .iter()
+                .for_each(|node| node.pretty_print(f, indent, indent_size)),
         }
     }
 }
 
-impl fmt::Display for Node {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.pretty_print(f, 0);
+impl Node {
+    pub fn tag_name(&self) -> Option<&str> {
+        match &self.node_type {
+            NodeType::Element(data) => Some(&data.tag_name),
+            _ => None,
+        }
+    }
+
+    pub fn attributes(&self) -> Option<&AttrMap> {
+        match &self.node_type {
+            NodeType::Element(data) => Some(&data.attributes),
+            _ => None,
+        }
+    }
+
+    pub fn attributes_mut(&mut self) -> Option<&mut AttrMap> {
+        match &mut self.node_type {
+            NodeType::Element(data) => Some(&mut data.attributes),
+            _ => None,
+        }
+    }
+
+    /// The textual value of an attribute. Implicit attributes yield an empty string.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        match &self.node_type {
+            NodeType::Element(data) => data.attr(name),
+            _ => None,
+        }
+    }
+
+    pub fn as_processing_instruction(&self) -> Option<(&str, &str)> {
+        match &self.node_type {
+            NodeType::ProcessingInstruction { target, data } => Some((target, data)),
+            _ => None,
+        }
+    }
+
+    pub fn as_cdata_section(&self) -> Option<&str> {
+        match &self.node_type {
+            NodeType::CDATASection(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn children(&self) -> &[Node] {
+        match &self.node_type {
+            NodeType::Element(data) => &data.child_nodes,
+            NodeType::Document(data) => data.root.as_deref().map_or(&[], std::slice::from_ref),
+            NodeType::DocumentFragment(children) => children,
+            _ => &[],
+        }
+    }
+
+    /// The index of this node among the children of `parent`, or `None` if
+    /// it isn't one of them.
+    pub fn position_in_parent(&self, parent: &Node) -> Option<usize> {
+        parent.children().iter().position(|c| std::ptr::eq(c, self))
+    }
+
+    /// All other children of `parent`, or `None` if this node isn't one of them.
+    pub fn siblings<'a>(&self, parent: &'a Node) -> Option<Vec<&'a Node>> {
+        let index = self.position_in_parent(parent)?;
+        Some(
+            parent
+                .children()
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != index)
+                .map(|(_, c)| c)
+                .collect(),
+        )
+    }
+
+    pub fn next_sibling<'a>(&self, parent: &'a Node) -> Option<&'a Node> {
+        let index = self.position_in_parent(parent)?;
+        parent.children().get(index + 1)
+    }
+
+    pub fn previous_sibling<'a>(&self, parent: &'a Node) -> Option<&'a Node> {
+        let index = self.position_in_parent(parent)?;
+        parent.children().get(index.checked_sub(1)?)
+    }
+
+    pub fn children_mut(&mut self) -> &mut [Node] {
+        match &mut self.node_type {
+            NodeType::Element(data) => &mut data.child_nodes,
+            NodeType::Document(data) => data
+                .root
+                .as_deref_mut()
+                .map_or(&mut [], std::slice::from_mut),
+            NodeType::DocumentFragment(children) => children,
+            _ => &mut [],
+        }
+    }
+
+    /// Pre-order traversal over this node and all of its descendants.
+    pub fn iter(&self) -> NodeIter<'_> {
+        NodeIter { stack: vec![self] }
+    }
+
+    pub fn select_all_by_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a Node> {
+        self.iter().filter(move |node| node.tag_name() == Some(tag))
+    }
+
+    pub fn has_descendant_with_tag(&self, tag: &str) -> bool {
+        self.has_descendant_matching(|node| node.tag_name() == Some(tag))
+    }
+
+    /// Whether `f` holds for any descendant, stopping at the first one it does.
+    pub fn has_descendant_matching<F: Fn(&Node) -> bool>(&self, f: F) -> bool {
+        fn search<F: Fn(&Node) -> bool>(node: &Node, f: &F) -> bool {
+            node.children()
+                .iter()
+                .any(|child| f(child) || search(child, f))
+        }
+        search(self, &f)
+    }
+
+    /// The first element in the subtree, in document order, matching the
+    /// selector list `selector`. `None` if it doesn't parse.
+    pub fn select_first(&self, selector: &str) -> Option<&Node> {
+        let selectors = css::parse_selector_list(selector).ok()?;
+        let mut matches = matcher(&selectors);
+        self.find_matching(&mut vec![], &mut matches, false)
+    }
+
+    /// Like [`Node::select_first`], returning the last match instead.
+    pub fn select_last(&self, selector: &str) -> Option<&Node> {
+        let selectors = css::parse_selector_list(selector).ok()?;
+        let mut matches = matcher(&selectors);
+        self.find_matching(&mut vec![], &mut matches, true)
+    }
+
+    /// Searches in document order, or in reverse document order when `last`,
+    /// stopping at the first node for which `matches` holds.
+    fn find_matching<'a, F>(
+        &'a self,
+        ancestors: &mut Vec<&'a Node>,
+        matches: &mut F,
+        last: bool,
+    ) -> Option<&'a Node>
+    where
+        F: FnMut(&Node, &[&Node]) -> bool,
+    {
+        if !last && matches(self, ancestors) {
+            return Some(self);
+        }
+        ancestors.push(self);
+        let found = if last {
+            self.children()
+                .iter()
+                .rev()
+                .find_map(|child| child.find_matching(ancestors, matches, last))
+        } else {
+            self.children()
+                .iter()
+                .find_map(|child| child.find_matching(ancestors, matches, last))
+        };
+        ancestors.pop();
+        if found.is_none() && last && matches(self, ancestors) {
+            return Some(self);
+        }
+        found
+    }
+
+    pub fn text_content(&self) -> String {
+        self.iter()
+            .filter_map(|node| match &node.node_type {
+                NodeType::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether any single text node in the subtree contains `needle`.
+    pub fn contains_text(&self, needle: &str) -> bool {
+        self.iter()
+            .any(|node| matches!(&node.node_type, NodeType::Text(text) if text.contains(needle)))
+    }
+
+    /// Like [`Node::contains_text`], ignoring ASCII case.
+    pub fn contains_text_ci(&self, needle: &str) -> bool {
+        let needle = needle.to_ascii_lowercase();
+        self.iter().any(|node| {
+            matches!(&node.node_type, NodeType::Text(text) if text.to_ascii_lowercase().contains(&needle))
+        })
+    }
+
+    /// The value of attribute `name` on every element in the subtree that has
+    /// it, in document order.
+    pub fn get_all_attributes_named(&self, name: &str) -> Vec<&AttrValue> {
+        self.iter()
+            .filter_map(|node| node.attributes()?.0.get(name))
+            .collect()
+    }
+
+    /// Like [`Node::get_all_attributes_named`], skipping implicit attributes.
+    pub fn get_all_attribute_values(&self, name: &str) -> Vec<&str> {
+        self.get_all_attributes_named(name)
+            .into_iter()
+            .filter_map(|value| match value {
+                AttrValue::Text(text) => Some(text.as_str()),
+                AttrValue::Implicit => None,
+            })
+            .collect()
+    }
+}
+
+impl Node {
+    pub fn has_class(&self, class: &str) -> bool {
+        match &self.node_type {
+            NodeType::Element(data) => data.has_class(class),
+            _ => false,
+        }
+    }
+
+    /// Appends `class` to the `class` attribute unless it's already present.
+    pub fn add_class(&mut self, class: &str) {
+        if self.has_class(class) {
+            return;
+        }
+        let Some(attributes) = self.attributes_mut() else {
+            return;
+        };
+        let classes = match attributes.0.get("class") {
+            Some(AttrValue::Text(classes)) if !classes.trim().is_empty() => {
+                format!("{} {}", classes.trim_end(), class)
+            }
+            _ => class.to_string(),
+        };
+        attributes
+            .0
+            .insert("class".into(), AttrValue::Text(classes));
+    }
+
+    /// Removes every occurrence of `class` from the `class` attribute.
+    pub fn remove_class(&mut self, class: &str) {
+        if !self.has_class(class) {
+            return;
+        }
+        let Some(attributes) = self.attributes_mut() else {
+            return;
+        };
+        if let Some(AttrValue::Text(classes)) = attributes.0.get_mut("class") {
+            *classes = classes
+                .split_ascii_whitespace()
+                .filter(|c| *c != class)
+                .collect::<Vec<&str>>()
+                .join(" ");
+        }
+    }
+
+    /// Rewrites the value of a textual attribute, returning whether it was updated.
+    /// Implicit attributes are left as they are.
+    pub fn patch_attribute(&mut self, name: &str, f: impl Fn(&str) -> String) -> bool {
+        let Some(attributes) = self.attributes_mut() else {
+            return false;
+        };
+        match attributes.0.get_mut(name) {
+            Some(AttrValue::Text(value)) => {
+                *value = f(value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds or removes `class`, returning whether it's present afterwards.
+    pub fn toggle_class(&mut self, class: &str) -> bool {
+        if self.has_class(class) {
+            self.remove_class(class);
+            false
+        } else {
+            self.add_class(class);
+            self.has_class(class)
+        }
+    }
+}
+
+impl Node {
+    /// The `data-*` attributes keyed like `element.dataset`: without the
+    /// prefix and in camelCase, so `data-user-id` becomes `userId`. `None`
+    /// for nodes that aren't elements.
+    pub fn data_attributes(&self) -> Option<HashMap<String, String>> {
+        let attributes = self.attributes()?;
+        Some(
+            attributes
+                .0
+                .keys()
+                .filter_map(|name| {
+                    let key = name.strip_prefix("data-")?;
+                    Some((kebab_to_camel_case(key), self.attr(name)?.to_string()))
+                })
+                .collect(),
+        )
+    }
+
+    /// Sets the `data-*` attribute for the dataset key `key`, so `userId`
+    /// sets `data-user-id`.
+    pub fn set_data_attribute(&mut self, key: &str, value: &str) -> Result<(), DomError> {
+        let attributes = self.attributes_mut().ok_or(DomError::NotAnElement)?;
+        attributes.0.insert(
+            camel_to_data_attribute(key),
+            AttrValue::Text(value.to_string()),
+        );
         Ok(())
     }
+
+    /// Removes the `data-*` attribute for `key`, returning whether it was set.
+    pub fn remove_data_attribute(&mut self, key: &str) -> Result<bool, DomError> {
+        let attributes = self.attributes_mut().ok_or(DomError::NotAnElement)?;
+        Ok(attributes.0.remove(&camel_to_data_attribute(key)).is_some())
+    }
+
+    pub fn has_data_attribute(&self, key: &str) -> bool {
+        self.attr(&camel_to_data_attribute(key)).is_some()
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct DocumentData {
-    pub root: Box<Option<Node>>,
-    pub stylesheets: Vec<StyleSheet>,
+/// `userId` to `data-user-id`.
+fn camel_to_data_attribute(key: &str) -> String {
+    let mut result = String::from("data-");
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            result.push('-');
+        }
+        result.push(c.to_ascii_lowercase());
+    }
+    result
 }
 
-impl DocumentData {
-    pub fn load_css(&mut self, styling: String) {
-        self.stylesheets.push(css::parse(styling));
+/// `user-id` to `userId`. Only a dash before a lowercase letter is dropped.
+fn kebab_to_camel_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '-' && next.is_ascii_lowercase() => {
+                result.push(next.to_ascii_uppercase());
+                chars.next();
+            }
+            _ => result.push(c),
+        }
     }
+    result
+}
 
-    pub fn load_document(&mut self, document: String) {
-        let node = html::parse(document, self);
-        _ = self.root.insert(node);
+impl Node {
+    /// Replaces the content of every text node, returning how many were changed.
+    pub fn replace_text<F>(&mut self, replacer: F) -> usize
+    where
+        F: Fn(&str) -> String,
+    {
+        self.replace_text_with(&replacer)
     }
 
-    pub fn new() -> Self {
-        Self {
-            root: Box::new(None),
-            stylesheets: vec![],
+    fn replace_text_with<F>(&mut self, replacer: &F) -> usize
+    where
+        F: Fn(&str) -> String,
+    {
+        if let NodeType::Text(text) = &mut self.node_type {
+            let replaced = replacer(text);
+            if replaced == *text {
+                return 0;
+            }
+            *text = replaced;
+            return 1;
+        }
+        self.children_mut()
+            .iter_mut()
+            .map(|node| node.replace_text_with(replacer))
+            .sum()
+    }
+
+    /// Escapes `&`, `<`, `>`, `"` and `'` in every text node, in place.
+    pub fn encode_html_entities(&mut self) {
+        self.replace_text(|text| {
+            escape_text(text)
+                .replace('"', "&quot;")
+                .replace('\'', "&#x27;")
+        });
+    }
+
+    /// Replaces character references in every text node with the characters
+    /// they stand for. Undoes [`Node::encode_html_entities`].
+    pub fn decode_html_entities(&mut self) {
+        self.replace_text(html::decode_entities);
+    }
+
+    /// A copy of the tree with `{{name}}` placeholders in text nodes replaced
+    /// by their value in `vars`. Unknown names are an error when `strict`,
+    /// and are left as written otherwise.
+    pub fn apply_template(
+        &self,
+        vars: &HashMap<&str, &str>,
+        strict: bool,
+    ) -> Result<Node, TemplateError> {
+        let mut result = self.clone();
+        result.substitute_placeholders(vars, strict)?;
+        Ok(result)
+    }
+
+    fn substitute_placeholders(
+        &mut self,
+        vars: &HashMap<&str, &str>,
+        strict: bool,
+    ) -> Result<(), TemplateError> {
+        if let NodeType::Text(content) = &mut self.node_type {
+            *content = substitute_placeholders(content, vars, strict)?;
+        }
+        self.children_mut()
+            .iter_mut()
+            .try_for_each(|child| child.substitute_placeholders(vars, strict))
+    }
+
+    /// A transformed copy of the tree. Children are mapped first, then `f` is
+    /// called with the original node. If `f` returns a node of the same kind as
+    /// the original (for elements, with the same tag), the mapped children
+    /// replace its children; any other node is used as is, so wrapping or
+    /// replacing a node keeps exactly what `f` built.
+    pub fn map<F: Fn(&Node) -> Node>(&self, f: F) -> Node {
+        self.map_with(&f)
+    }
+
+    fn map_with<F: Fn(&Node) -> Node>(&self, f: &F) -> Node {
+        let children: Vec<Node> = self.children().iter().map(|c| c.map_with(f)).collect();
+        f(self).with_children(self, children)
+    }
+
+    /// Like [`Node::map`], but nodes for which `f` returns `None` are dropped
+    /// along with their descendants. `f` is given each node without its
+    /// children, so cloning it is cheap; the filtered children are added back
+    /// as with [`Node::map`].
+    pub fn filter_map<F: Fn(&Node) -> Option<Node>>(&self, f: F) -> Option<Node> {
+        self.filter_map_with(&f)
+    }
+
+    fn filter_map_with<F: Fn(&Node) -> Option<Node>>(&self, f: &F) -> Option<Node> {
+        let children: Vec<Node> = self
+            .children()
+            .iter()
+            .filter_map(|c| c.filter_map_with(f))
+            .collect();
+        Some(f(&self.shallow_clone())?.with_children(self, children))
+    }
+
+    /// A copy of this node without its children.
+    fn shallow_clone(&self) -> Node {
+        let node_type = match &self.node_type {
+            NodeType::Element(data) => NodeType::Element(ElementData {
+                tag_name: data.tag_name.clone(),
+                attributes: data.attributes.clone(),
+                child_nodes: vec![],
+            }),
+            NodeType::Document(data) => NodeType::Document(DocumentData {
+                root: None,
+                stylesheets: data.stylesheets.clone(),
+                doctype: data.doctype.clone(),
+            }),
+            NodeType::DocumentFragment(_) => NodeType::DocumentFragment(vec![]),
+            node_type => node_type.clone(),
+        };
+        Node { node_type }
+    }
+
+    fn with_children(mut self, original: &Node, mut children: Vec<Node>) -> Node {
+        let same_kind = match (&self.node_type, &original.node_type) {
+            (NodeType::Element(a), NodeType::Element(b)) => a.tag_name == b.tag_name,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        };
+        if !same_kind || original.children().is_empty() {
+            return self;
+        }
+        match &mut self.node_type {
+            NodeType::Element(data) => data.child_nodes = children,
+            NodeType::DocumentFragment(nodes) => *nodes = children,
+            NodeType::Document(data) => {
+                data.root = (!children.is_empty()).then(|| Box::new(children.swap_remove(0)))
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Replaces every descendant element in `tags_to_flatten` that has no
+    /// attributes with its children. Adjacent text nodes are left unmerged.
+    pub fn flatten_inline_elements(&mut self, tags_to_flatten: &[&str]) {
+        let children = match &mut self.node_type {
+            NodeType::Element(data) => &mut data.child_nodes,
+            NodeType::DocumentFragment(children) => children,
+            NodeType::Document(data) => {
+                if let Some(root) = data.root.as_mut() {
+                    root.flatten_inline_elements(tags_to_flatten);
+                }
+                return;
+            }
+            _ => return,
+        };
+        let mut flattened = Vec::with_capacity(children.len());
+        for mut child in children.drain(..) {
+            child.flatten_inline_elements(tags_to_flatten);
+            match child.node_type {
+                NodeType::Element(data)
+                    if data.attributes.0.is_empty()
+                        && tags_to_flatten.contains(&data.tag_name.as_str()) =>
+                {
+                    flattened.extend(data.child_nodes)
+                }
+                _ => flattened.push(child),
+            }
         }
+        *children = flattened;
     }
 }
 
-pub fn text(data: String) -> Node {
-    Node {
-        node_type: NodeType::Text(data),
+impl Node {
+    /// This node if it's an element. Without parent pointers, the nearest element
+    /// ancestor of other node types can't be reached from the node itself.
+    pub fn closest_element(&self) -> Option<&Node> {
+        self.tag_name().map(|_| self)
+    }
+
+    /// The nearest node in `ancestor_chain`, ordered from the root down to the
+    /// parent, with the tag `tag`. See [`collect_ancestor_chain`].
+    pub fn closest_by_tag<'a>(&self, tag: &str, ancestor_chain: &[&'a Node]) -> Option<&'a Node> {
+        ancestor_chain
+            .iter()
+            .rev()
+            .find(|node| node.tag_name() == Some(tag))
+            .copied()
+    }
+
+    /// The tag names of the elements from this node down to `target`, inclusive.
+    /// Non-element nodes on the way are skipped, and a text `target` yields an
+    /// empty path. `None` if `target` isn't in the tree.
+    pub fn path_of_elements(&self, target: &Node) -> Option<Vec<String>> {
+        let chain = collect_ancestor_chain(self, target)?;
+        if matches!(target.node_type, NodeType::Text(_)) {
+            return Some(vec![]);
+        }
+        Some(
+            chain
+                .into_iter()
+                .chain([target])
+                .filter_map(|node| node.tag_name().map(str::to_string))
+                .collect(),
+        )
+    }
+
+    pub fn is_in_head(&self, doc: &DocumentData) -> bool {
+        doc.head()
+            .is_some_and(|head| head.iter().any(|node| std::ptr::eq(node, self)))
     }
 }
 
-pub fn element(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
-    Node {
-        node_type: NodeType::Element(ElementData {
-            tag_name: name,
-            attributes: attrs,
-            child_nodes: children,
-        }),
+impl Node {
+    /// The number of whitespace-delimited words in all descendant text nodes.
+    pub fn count_words_in_element(&self) -> usize {
+        self.iter()
+            .map(|node| match &node.node_type {
+                NodeType::Text(text) => text.split_whitespace().count(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Words per direct child element, plus one: high for a paragraph of
+    /// prose, low for navigation made of many small links.
+    pub fn word_density_score(&self) -> f64 {
+        let child_elements = self
+            .children()
+            .iter()
+            .filter(|child| child.tag_name().is_some())
+            .count();
+        self.count_words_in_element() as f64 / (1 + child_elements) as f64
+    }
+
+    /// The length of the text content relative to the serialized HTML, or 0.0
+    /// when there is nothing to serialize.
+    pub fn text_to_html_ratio(&self) -> f64 {
+        let html_length = self.outer_html().chars().count();
+        if html_length == 0 {
+            return 0.0;
+        }
+        self.text_content().chars().count() as f64 / html_length as f64
+    }
+
+    /// The number of elements in the subtree, including this node.
+    pub fn element_count(&self) -> usize {
+        self.iter()
+            .filter(|node| matches!(node.node_type, NodeType::Element(_)))
+            .count()
+    }
+
+    /// The number of nodes of any type in the subtree, including this node.
+    pub fn size(&self) -> usize {
+        self.iter().count()
+    }
+
+    #[deprecated(note = "renamed to `size`")]
+    pub fn node_count(&self) -> usize {
+        self.size()
+    }
+
+    /// The number of edges on the longest path from this node down to a leaf,
+    /// so zero for a leaf.
+    pub fn height(&self) -> usize {
+        self.children()
+            .iter()
+            .map(|child| child.height() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The number of nodes on the longest path from this node down to a leaf.
+    #[deprecated(note = "use `height`, which counts edges: `max_depth() == height() + 1`")]
+    pub fn max_depth(&self) -> usize {
+        self.height() + 1
+    }
+
+    /// The mean number of children per element in the subtree, or zero if
+    /// there are no elements.
+    pub fn average_children(&self) -> f64 {
+        let (elements, children) = self
+            .iter()
+            .filter(|node| matches!(node.node_type, NodeType::Element(_)))
+            .fold((0, 0), |(elements, children), node| {
+                (elements + 1, children + node.children().len())
+            });
+        if elements == 0 {
+            return 0.0;
+        }
+        children as f64 / elements as f64
+    }
+
+    /// How often each tag occurs in the subtree, including this node.
+    pub fn count_by_tag(&self) -> HashMap<String, usize> {
+        self.tag_counts()
+            .into_iter()
+            .map(|(tag, count)| (tag.to_string(), count))
+            .collect()
+    }
+
+    /// The tag that occurs most often. Ties go to the tag seen first.
+    pub fn most_common_tag(&self) -> Option<(&str, usize)> {
+        self.tag_counts()
+            .into_iter()
+            .reduce(|best, entry| if entry.1 > best.1 { entry } else { best })
+    }
+
+    /// Every path from this node down to a leaf, in document order. A leaf is
+    /// any node without children, so a path may end in a text node.
+    pub fn all_element_paths(&self) -> Vec<Vec<&Node>> {
+        let children = self.children();
+        if children.is_empty() {
+            return vec![vec![self]];
+        }
+        children
+            .iter()
+            .flat_map(Node::all_element_paths)
+            .map(|mut path| {
+                path.insert(0, self);
+                path
+            })
+            .collect()
+    }
+
+    /// Every comment node in the subtree, in document order.
+    pub fn all_comments(&self) -> Vec<&Node> {
+        self.iter()
+            .filter(|node| matches!(node.node_type, NodeType::Comment(_)))
+            .collect()
+    }
+
+    /// The `src` of every `<img>` and `<source>` in the subtree, in document
+    /// order. Elements without a `src` are skipped.
+    pub fn image_srcs(&self) -> Vec<String> {
+        self.iter()
+            .filter(|node| matches!(node.tag_name(), Some("img" | "source")))
+            .filter_map(|node| node.attr("src").map(String::from))
+            .collect()
+    }
+
+    /// The number of `<img>` elements in the subtree.
+    pub fn image_count(&self) -> usize {
+        self.select_all_by_tag("img").count()
+    }
+
+    /// Every `<img>` with `loading="lazy"`, in document order.
+    pub fn lazy_images(&self) -> Vec<&Node> {
+        self.select_all_by_tag("img")
+            .filter(|img| {
+                img.attr("loading")
+                    .is_some_and(|loading| loading.eq_ignore_ascii_case("lazy"))
+            })
+            .collect()
+    }
+
+    /// A copy of the tree with all comment nodes removed. Stripping a comment
+    /// itself yields an empty fragment.
+    pub fn strip_comments(&self) -> Node {
+        self.filter_map(|node| match node.node_type {
+            NodeType::Comment(_) => None,
+            _ => Some(node.clone()),
+        })
+        .unwrap_or_else(|| document_fragment(vec![]))
+    }
+
+    /// Tag counts in order of first appearance.
+    fn tag_counts(&self) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(&str, usize)> = vec![];
+        for tag in self.iter().filter_map(Node::tag_name) {
+            match counts.iter_mut().find(|(seen, _)| *seen == tag) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((tag, 1)),
+            }
+        }
+        counts
     }
 }
 
-pub fn comment(text: String) -> Node {
-    Node {
-        node_type: NodeType::Comment(text),
+/// A heading in the outline built by [`DocumentData::structured_headings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadingNode {
+    pub level: u8,
+    pub text: String,
+    pub id: Option<String>,
+    /// The headings of a lower rank up to the next heading of this rank or
+    /// higher.
+    pub children: Vec<HeadingNode>,
+}
+
+impl DocumentData {
+    /// The document's headings nested by rank, so an `<h2>` is a child of the
+    /// `<h1>` before it. A skipped rank doesn't add a level: an `<h3>` right
+    /// after an `<h1>` is its child.
+    pub fn structured_headings(&self) -> Vec<HeadingNode> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        let mut entries = root.table_of_contents(6).into_iter().peekable();
+        nest_headings(&mut entries, 0)
     }
 }
 
-pub fn parse(document: String) -> Node {
-    let mut context = DocumentData::new();
-    context.load_document(document);
-    Node {
-        node_type: NodeType::Document(context),
+/// The headings from `entries` ranked lower than `parent_level`, up to the
+/// first that isn't.
+fn nest_headings(
+    entries: &mut std::iter::Peekable<std::vec::IntoIter<TocEntry>>,
+    parent_level: u8,
+) -> Vec<HeadingNode> {
+    let mut headings = vec![];
+    while let Some(entry) = entries.next_if(|entry| entry.level > parent_level) {
+        headings.push(HeadingNode {
+            level: entry.level,
+            children: nest_headings(entries, entry.level),
+            text: entry.text,
+            id: entry.id,
+        });
+    }
+    headings
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is guaranteed not to change
+/// between Rust releases.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl Node {
+    /// A hash of the subtree's tag names, attributes, text, comments and
+    /// child order that stays the same across runs, for telling whether a
+    /// tree changed without keeping a copy. Attribute order doesn't matter.
+    pub fn compute_checksum(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        self.hash_into(&mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_into(&self, state: &mut FnvHasher) {
+        match &self.node_type {
+            NodeType::Element(data) => {
+                state.write_u8(0);
+                data.tag_name.as_str().hash(state);
+                let mut attributes: Vec<_> = data.attributes.0.iter().collect();
+                attributes.sort_by_key(|(name, _)| *name);
+                state.write_usize(attributes.len());
+                for (name, value) in attributes {
+                    name.hash(state);
+                    match value {
+                        AttrValue::Text(text) => Some(text).hash(state),
+                        AttrValue::Implicit => None::<&String>.hash(state),
+                    }
+                }
+            }
+            NodeType::Text(text) => (1u8, text).hash(state),
+            NodeType::Comment(text) => (2u8, text).hash(state),
+            NodeType::ProcessingInstruction { target, data } => (3u8, target, data).hash(state),
+            NodeType::CDATASection(text) => (4u8, text).hash(state),
+            NodeType::Document(_) => state.write_u8(5),
+            NodeType::DocumentFragment(_) => state.write_u8(6),
+        }
+        let children = self.children();
+        state.write_usize(children.len());
+        children.iter().for_each(|child| child.hash_into(state));
+    }
+}
+
+/// Elements that mark out a region of a page.
+const SECTIONING_ELEMENTS: &[&str] = &[
+    "header", "nav", "main", "aside", "footer", "section", "article",
+];
+
+impl Node {
+    /// The outermost sectioning elements (`<header>`, `<nav>`, `<main>`,
+    /// `<aside>`, `<footer>`, `<section>` and `<article>`) in `<body>`, or in
+    /// the subtree if it has no body, in document order. Other elements such
+    /// as wrapping `<div>`s are looked through; sections nested in a section
+    /// aren't included.
+    pub fn page_sections(&self) -> Vec<&Node> {
+        let body = self.select_all_by_tag("body").next().unwrap_or(self);
+        let mut sections = vec![];
+        body.collect_sections(&mut sections);
+        sections
+    }
+
+    fn collect_sections<'a>(&'a self, sections: &mut Vec<&'a Node>) {
+        for child in self.children() {
+            if child
+                .tag_name()
+                .is_some_and(|tag| SECTIONING_ELEMENTS.contains(&tag))
+            {
+                sections.push(child);
+            } else {
+                child.collect_sections(sections);
+            }
+        }
+    }
+
+    /// Whether the subtree uses at least three different sectioning elements.
+    pub fn has_semantic_structure(&self) -> bool {
+        SECTIONING_ELEMENTS
+            .iter()
+            .filter(|tag| self.has_descendant_with_tag(tag))
+            .count()
+            >= 3
+    }
+}
+
+/// A heading found by [`Node::table_of_contents`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    /// 1 for `<h1>` through 6 for `<h6>`.
+    pub level: u8,
+    pub text: String,
+    pub id: Option<String>,
+}
+
+impl Node {
+    /// The `<h1>` to `<h{max_level}>` headings in the subtree, in document
+    /// order, with their whitespace-collapsed text.
+    pub fn table_of_contents(&self, max_level: u8) -> Vec<TocEntry> {
+        self.iter()
+            .filter_map(|node| {
+                let level = match node.tag_name()?.as_bytes() {
+                    [b'h', digit @ b'1'..=b'6'] => digit - b'0',
+                    _ => return None,
+                };
+                (level <= max_level).then(|| TocEntry {
+                    level,
+                    text: node
+                        .text_content()
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    id: node.attr("id").map(String::from),
+                })
+            })
+            .collect()
+    }
+}
+
+/// An element with `itemscope` and the `itemprop` values within it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MicrodataItem {
+    /// The `itemtype`, e.g. `https://schema.org/Person`.
+    pub item_type: Option<String>,
+    /// The values of each property, in document order.
+    pub properties: HashMap<String, Vec<String>>,
+}
+
+impl Node {
+    /// Every microdata item in the subtree, in document order. Properties
+    /// inside a nested `itemscope` belong to the nested item only, and the
+    /// nested item isn't a value of the outer one.
+    pub fn microdata(&self) -> Vec<MicrodataItem> {
+        self.iter()
+            .filter(|node| node.attr("itemscope").is_some())
+            .map(|scope| {
+                let mut item = MicrodataItem {
+                    item_type: scope.attr("itemtype").map(String::from),
+                    ..Default::default()
+                };
+                scope.collect_itemprops(&mut item.properties);
+                item
+            })
+            .collect()
+    }
+
+    fn collect_itemprops(&self, properties: &mut HashMap<String, Vec<String>>) {
+        for child in self.children() {
+            if child.attr("itemscope").is_some() {
+                continue;
+            }
+            if let Some(names) = child.attr("itemprop") {
+                let value = child.itemprop_value();
+                for name in names.split_ascii_whitespace() {
+                    properties
+                        .entry(name.to_string())
+                        .or_default()
+                        .push(value.clone());
+                }
+            }
+            child.collect_itemprops(properties);
+        }
+    }
+
+    /// The value of an `itemprop`, taken from the attribute its element
+    /// type uses, or else its text.
+    fn itemprop_value(&self) -> String {
+        let attr = match self.tag_name() {
+            Some("meta") => "content",
+            Some("a" | "area" | "link") => "href",
+            Some("audio" | "embed" | "iframe" | "img" | "source" | "track" | "video") => "src",
+            Some("object") => "data",
+            Some("data" | "meter") => "value",
+            Some("time") if self.attr("datetime").is_some() => "datetime",
+            _ => "content",
+        };
+        match self.attr(attr) {
+            Some(value) => value.to_string(),
+            None => self.text_content().trim().to_string(),
+        }
+    }
+}
+
+impl Node {
+    /// The XPath step test selecting nodes like this one, e.g. `p` or `text()`.
+    fn xpath_test(&self) -> &str {
+        match &self.node_type {
+            NodeType::Element(data) => &data.tag_name,
+            NodeType::Text(_) | NodeType::CDATASection(_) => "text()",
+            NodeType::Comment(_) => "comment()",
+            NodeType::ProcessingInstruction { .. } => "processing-instruction()",
+            NodeType::Document(_) | NodeType::DocumentFragment(_) => "node()",
+        }
+    }
+
+    /// An absolute XPath such as `/html[1]/body[1]/div[2]/p[1]` locating this
+    /// node below `root`, which is `/`. Empty if the node isn't in the tree.
+    pub fn compute_xpath(&self, root: &Node) -> String {
+        let Some(mut chain) = collect_ancestor_chain(root, self) else {
+            return String::new();
+        };
+        if chain.is_empty() {
+            return "/".to_string();
+        }
+        chain.push(self);
+        chain
+            .windows(2)
+            .map(|pair| {
+                let (parent, node) = (pair[0], pair[1]);
+                let test = node.xpath_test();
+                let index = parent
+                    .children()
+                    .iter()
+                    .take_while(|c| !std::ptr::eq(*c, node))
+                    .filter(|c| c.xpath_test() == test)
+                    .count();
+                format!("/{}[{}]", test, index + 1)
+            })
+            .collect()
+    }
+
+    /// Resolves an absolute XPath made of `test[index]` steps, as produced by
+    /// [`Node::compute_xpath`], relative to this node.
+    pub fn evaluate_xpath(&self, xpath: &str) -> Option<&Node> {
+        let steps = xpath.strip_prefix('/')?;
+        if steps.is_empty() {
+            return Some(self);
+        }
+        steps.split('/').try_fold(self, |node, step| {
+            let (test, index) = match step.strip_suffix(']') {
+                Some(step) => {
+                    let (test, index) = step.split_once('[')?;
+                    (test, index.parse::<usize>().ok()?)
+                }
+                None => (step, 1),
+            };
+            node.children()
+                .iter()
+                .filter(|c| c.xpath_test() == test)
+                .nth(index.checked_sub(1)?)
+        })
+    }
+}
+
+/// Where [`Node::to_accessible_text`] takes the accessible name from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessibleNameSource {
+    AriaLabel,
+    Alt,
+    TextContent,
+    None,
+}
+
+impl Node {
+    /// Follows a subset of the accessible name computation: `aria-label`, then
+    /// `alt` on images, then the text content. `aria-labelledby` isn't supported
+    /// since the referenced elements can't be reached from the node.
+    pub fn accessible_name_source(&self) -> AccessibleNameSource {
+        let non_blank = |name| {
+            self.attr(name)
+                .is_some_and(|value| !value.trim().is_empty())
+        };
+        if non_blank("aria-label") {
+            AccessibleNameSource::AriaLabel
+        } else if self.has_alt_text() && non_blank("alt") {
+            AccessibleNameSource::Alt
+        } else if !self.text_content().trim().is_empty() {
+            AccessibleNameSource::TextContent
+        } else {
+            AccessibleNameSource::None
+        }
+    }
+
+    pub fn to_accessible_text(&self) -> String {
+        let text = match self.accessible_name_source() {
+            AccessibleNameSource::AriaLabel => self.attr("aria-label").unwrap().to_string(),
+            AccessibleNameSource::Alt => self.attr("alt").unwrap().to_string(),
+            AccessibleNameSource::TextContent => self.text_content(),
+            AccessibleNameSource::None => String::new(),
+        };
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn has_alt_text(&self) -> bool {
+        match self.tag_name() {
+            Some("img" | "area") => true,
+            Some("input") => self.attr("type") == Some("image"),
+            _ => false,
+        }
+    }
+}
+
+/// One attribute difference between two nodes, as found by [`Node::diff_attributes`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrDiff {
+    Added(String, AttrValue),
+    Removed(String),
+    /// The name, the old value and the new value.
+    Changed(String, AttrValue, AttrValue),
+}
+
+impl Node {
+    /// The changes that turn the attributes of `self` into those of `other`,
+    /// ordered by name. Nodes that aren't elements have no attributes, so
+    /// comparing two of them yields nothing.
+    pub fn diff_attributes(&self, other: &Node) -> Vec<AttrDiff> {
+        let empty = AttrMap::default();
+        let old = self.attributes().unwrap_or(&empty);
+        let new = other.attributes().unwrap_or(&empty);
+        let mut names: Vec<&String> = old.0.keys().chain(new.0.keys()).collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .filter_map(|name| match (old.0.get(name), new.0.get(name)) {
+                (None, Some(value)) => Some(AttrDiff::Added(name.clone(), value.clone())),
+                (Some(_), None) => Some(AttrDiff::Removed(name.clone())),
+                (Some(before), Some(after)) if before != after => Some(AttrDiff::Changed(
+                    name.clone(),
+                    before.clone(),
+                    after.clone(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DomError {
+    NotAnElement,
+    IndexOutOfBounds { index: usize, len: usize },
+}
+
+impl fmt::Display for DomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::NotAnElement => write!(f, "node is not an element"),
+            Self::IndexOutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "child index {} out of bounds for {} children",
+                    index, len
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DomError {}
+
+/// A `{{name}}` placeholder without a value, see [`Node::apply_template`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    pub variable: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unresolved template variable \"{}\"", self.variable)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Whether a node is an element matching any of `selectors`.
+fn matcher(selectors: &[css::Selector]) -> impl FnMut(&Node, &[&Node]) -> bool + '_ {
+    |node, ancestors| {
+        node.tag_name().is_some() && selectors.iter().any(|s| s.matches(node, ancestors))
+    }
+}
+
+/// Whitespace around a placeholder name is ignored. An unclosed `{{` is kept.
+fn substitute_placeholders(
+    text: &str,
+    vars: &HashMap<&str, &str>,
+    strict: bool,
+) -> Result<String, TemplateError> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let name = rest[start + 2..end].trim();
+        match vars.get(name) {
+            Some(value) => result.push_str(value),
+            None if strict => {
+                return Err(TemplateError {
+                    variable: name.to_string(),
+                })
+            }
+            None => result.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attr(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+impl Node {
+    fn child_nodes_mut(&mut self) -> Result<&mut Vec<Node>, DomError> {
+        match &mut self.node_type {
+            NodeType::Element(data) => Ok(&mut data.child_nodes),
+            NodeType::DocumentFragment(children) => Ok(children),
+            _ => Err(DomError::NotAnElement),
+        }
+    }
+
+    /// Appends `child` to an element or fragment. A fragment child is inlined,
+    /// moving its children over instead.
+    pub fn append_child(&mut self, child: Node) -> Result<(), DomError> {
+        let children = self.child_nodes_mut()?;
+        match child.node_type {
+            NodeType::DocumentFragment(nodes) => children.extend(nodes),
+            _ => children.push(child),
+        }
+        Ok(())
+    }
+
+    /// Replaces the child at `child_index` with a new `wrapper_tag` element
+    /// holding it as its only child.
+    pub fn wrap_child(
+        &mut self,
+        child_index: usize,
+        wrapper_tag: &str,
+        attrs: AttrMap,
+    ) -> Result<(), DomError> {
+        let children = self.child_nodes_mut()?;
+        let len = children.len();
+        let child = children
+            .get_mut(child_index)
+            .ok_or(DomError::IndexOutOfBounds {
+                index: child_index,
+                len,
+            })?;
+        let wrapped = std::mem::replace(child, element(wrapper_tag.into(), attrs, vec![]));
+        child.append_child(wrapped)
+    }
+
+    /// Replaces the element at `child_index` with its children, returning
+    /// them. The inverse of [`Node::wrap_child`].
+    pub fn unwrap_child(&mut self, child_index: usize) -> Result<Vec<Node>, DomError> {
+        let children = self.child_nodes_mut()?;
+        let len = children.len();
+        let child = children
+            .get_mut(child_index)
+            .ok_or(DomError::IndexOutOfBounds {
+                index: child_index,
+                len,
+            })?;
+        let NodeType::Element(data) = &mut child.node_type else {
+            return Err(DomError::NotAnElement);
+        };
+        let inner = std::mem::take(&mut data.child_nodes);
+        children.splice(child_index..=child_index, inner.iter().cloned());
+        Ok(inner)
+    }
+
+    pub fn swap_children(&mut self, i: usize, j: usize) -> Result<(), DomError> {
+        let children = self.child_nodes_mut()?;
+        let len = children.len();
+        if let Some(index) = [i, j].into_iter().find(|index| *index >= len) {
+            return Err(DomError::IndexOutOfBounds { index, len });
+        }
+        children.swap(i, j);
+        Ok(())
+    }
+
+    /// Sorts the children with `compare`. The sort is stable.
+    pub fn sort_children_by<F>(&mut self, compare: F) -> Result<(), DomError>
+    where
+        F: FnMut(&Node, &Node) -> std::cmp::Ordering,
+    {
+        self.child_nodes_mut()?.sort_by(compare);
+        Ok(())
+    }
+
+    /// Adds `content` as the last child, merging it into a trailing text node.
+    pub fn append_text(&mut self, content: &str) -> Result<(), DomError> {
+        let NodeType::Element(data) = &mut self.node_type else {
+            return Err(DomError::NotAnElement);
+        };
+        match data.child_nodes.last_mut() {
+            Some(Node {
+                node_type: NodeType::Text(existing),
+            }) => existing.push_str(content),
+            _ => data.child_nodes.push(text(content.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Adds `content` as the first child, merging it into a leading text node.
+    pub fn prepend_text(&mut self, content: &str) -> Result<(), DomError> {
+        let NodeType::Element(data) = &mut self.node_type else {
+            return Err(DomError::NotAnElement);
+        };
+        match data.child_nodes.first_mut() {
+            Some(Node {
+                node_type: NodeType::Text(existing),
+            }) => existing.insert_str(0, content),
+            _ => data.child_nodes.insert(0, text(content.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Serializes this node and its descendants as HTML.
+    pub fn outer_html(&self) -> String {
+        let mut result = String::new();
+        self.write_html(&mut result, false);
+        result
+    }
+
+    /// Serializes the children of this node as HTML.
+    pub fn inner_html(&self) -> String {
+        let raw_text = matches!(self.tag_name(), Some("script" | "style"));
+        let mut result = String::new();
+        for child in self.children() {
+            child.write_html(&mut result, raw_text);
+        }
+        result
+    }
+
+    fn write_html(&self, result: &mut String, raw_text: bool) {
+        match &self.node_type {
+            NodeType::Element(data) => {
+                result.push('<');
+                result.push_str(&data.tag_name);
+                let mut attributes = data.attributes.0.iter().collect::<Vec<_>>();
+                attributes.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, value) in attributes {
+                    result.push(' ');
+                    result.push_str(name);
+                    if let AttrValue::Text(value) = value {
+                        result.push_str(&format!("=\"{}\"", escape_attr(value)));
+                    }
+                }
+                result.push('>');
+                if html::VOID_ELEMENTS.contains(&data.tag_name.as_str()) {
+                    return;
+                }
+                result.push_str(&self.inner_html());
+                result.push_str(&format!("</{}>", data.tag_name));
+            }
+            NodeType::Text(text) if raw_text => result.push_str(text),
+            NodeType::Text(text) => result.push_str(&escape_text(text)),
+            NodeType::Comment(text) => result.push_str(&format!("<!--{}-->", text)),
+            NodeType::ProcessingInstruction { target, data } => {
+                result.push_str(&format!("<?{} {}?>", target, data))
+            }
+            NodeType::CDATASection(text) => result.push_str(&format!("<![CDATA[{}]]>", text)),
+            NodeType::Document(_) | NodeType::DocumentFragment(_) => self
+                .children()
+                .iter()
+                .for_each(|node| node.write_html(result, raw_text)),
+        }
+    }
+}
+
+/// Elements that start on a new line when markup is stripped.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct StripOpts {
+    pub block_elements_newline: bool,
+    pub normalize_whitespace: bool,
+}
+
+impl Default for StripOpts {
+    fn default() -> Self {
+        Self {
+            block_elements_newline: true,
+            normalize_whitespace: true,
+        }
+    }
+}
+
+impl Node {
+    pub fn strip_tags(&self) -> String {
+        self.strip_tags_with_opts(StripOpts::default())
+    }
+
+    pub fn strip_tags_with_opts(&self, opts: StripOpts) -> String {
+        let mut result = String::new();
+        self.collect_stripped(&mut result, &opts);
+
+        if opts.normalize_whitespace {
+            result
+                .lines()
+                .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<String>>()
+                .join("\n")
+        } else {
+            result.trim_matches('\n').to_string()
+        }
+    }
+
+    fn collect_stripped(&self, result: &mut String, opts: &StripOpts) {
+        let is_block = opts.block_elements_newline
+            && self
+                .tag_name()
+                .is_some_and(|tag| BLOCK_ELEMENTS.contains(&tag));
+
+        if is_block && !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        match &self.node_type {
+            NodeType::Text(text) => result.push_str(text),
+            _ => self
+                .children()
+                .iter()
+                .for_each(|node| node.collect_stripped(result, opts)),
+        }
+        if is_block && !result.ends_with('\n') {
+            result.push('\n');
+        }
+    }
+}
+
+pub struct NodeIter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for NodeIter<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().iter().rev());
+        Some(node)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.pretty_print(f, 0, 2);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentData {
+    pub root: Option<Box<Node>>,
+    pub stylesheets: Vec<StyleSheet>,
+    /// The contents of the `<!DOCTYPE ...>` declaration, e.g. `html`.
+    pub doctype: Option<String>,
+}
+
+impl DocumentData {
+    pub fn load_css(&mut self, styling: String) -> Result<(), CssParseError> {
+        self.stylesheets.push(css::parse(styling)?);
+        Ok(())
+    }
+
+    pub fn load_css_from_file(&mut self, path: &Path) -> Result<(), CssParseError> {
+        let styling =
+            fs::read_to_string(path).map_err(|err| CssParseError::Load(err.to_string()))?;
+        self.load_css(styling)
+    }
+
+    /// Loads the stylesheet at `url`, using `fetch` to retrieve it.
+    #[cfg(feature = "fetch")]
+    pub fn load_css_from_url(
+        &mut self,
+        url: &str,
+        fetch: fn(&str) -> Result<String, Box<dyn std::error::Error>>,
+    ) -> Result<(), CssParseError> {
+        let styling = fetch(url).map_err(|err| CssParseError::Load(err.to_string()))?;
+        self.load_css(styling)
+    }
+
+    /// Loads the stylesheet of every `<link rel="stylesheet">`, in document
+    /// order, passing each `href` to `loader`. Like `<style>` contents,
+    /// sheets that can't be loaded or fail to parse are skipped.
+    pub fn add_stylesheet_from_link_elements<F: Fn(&str) -> Option<String>>(&mut self, loader: F) {
+        let Some(root) = self.root_node() else {
+            return;
+        };
+        let hrefs: Vec<String> = root
+            .select_all_by_tag("link")
+            .filter(|link| {
+                link.attr("rel").is_some_and(|rels| {
+                    rels.split_ascii_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("stylesheet"))
+                })
+            })
+            .filter_map(|link| link.attr("href"))
+            .map(String::from)
+            .collect();
+        for styling in hrefs.iter().filter_map(|href| loader(href)) {
+            _ = self.load_css(styling);
+        }
+    }
+
+    pub fn load_document(&mut self, document: String) -> Result<(), ParseError> {
+        let node = html::parse(document, self)?;
+        _ = self.root.insert(Box::new(node));
+        Ok(())
+    }
+
+    /// Replaces the document with `new_html`, like `document.write` on a
+    /// reopened document. References into the old tree don't survive this.
+    /// On a parse error the document is left as it was.
+    pub fn reload(&mut self, new_html: &str) -> Result<(), ParseError> {
+        let mut document = DocumentData::new();
+        document.load_document(new_html.to_string())?;
+        *self = document;
+        Ok(())
+    }
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn root_node(&self) -> Option<&Node> {
+        self.root.as_deref()
+    }
+
+    fn doctype_declaration(&self) -> Option<String> {
+        self.doctype
+            .as_ref()
+            .map(|doctype| format!("<!DOCTYPE {}>", doctype))
+    }
+
+    /// The document as HTML, starting with its doctype if it has one.
+    pub fn serialize_to_string(&self) -> String {
+        let mut result = self.doctype_declaration().unwrap_or_default();
+        if let Some(root) = self.root_node() {
+            result.push_str(&root.outer_html());
+        }
+        result
+    }
+
+    /// Like the `Display` output of a document node, indenting each level by
+    /// `indent_size` spaces.
+    pub fn pretty_print_to_string(&self, indent_size: usize) -> String {
+        let mut result = String::new();
+        if let Some(doctype) = self.doctype_declaration() {
+            result.push_str(&doctype);
+            result.push('\n');
+        }
+        if let Some(root) = self.root_node() {
+            root.pretty_print(&mut result, 0, indent_size);
+        }
+        result
+    }
+
+    fn find_element(&self, tag: &str) -> Option<&Node> {
+        self.root_node()?
+            .iter()
+            .find(|node| node.tag_name() == Some(tag))
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.find_element("title").map(|node| node.text_content())
+    }
+
+    pub fn head(&self) -> Option<&Node> {
+        self.find_element("head")
+    }
+
+    pub fn body(&self) -> Option<&Node> {
+        self.find_element("body")
+    }
+
+    /// The `href` of the first icon `<link>` in `<head>`.
+    pub fn favicon(&self) -> Option<String> {
+        self.head()?
+            .select_all_by_tag("link")
+            .find(|link| {
+                link.attr("rel").is_some_and(|rel| {
+                    rel.split_ascii_whitespace().any(|token| {
+                        token.eq_ignore_ascii_case("icon")
+                            || token.eq_ignore_ascii_case("apple-touch-icon")
+                    })
+                })
+            })
+            .and_then(|link| link.attr("href"))
+            .map(String::from)
+    }
+
+    pub fn canonical_url(&self) -> Option<String> {
+        self.head()?
+            .select_all_by_tag("link")
+            .find(|link| {
+                link.attr("rel").is_some_and(|rel| {
+                    rel.split_ascii_whitespace()
+                        .any(|token| token.eq_ignore_ascii_case("canonical"))
+                })
+            })
+            .and_then(|link| link.attr("href"))
+            .map(String::from)
+    }
+
+    /// `<meta property="og:*" content="...">` tags, keyed by property name.
+    /// When a property repeats, the first occurrence wins.
+    pub fn open_graph_tags(&self) -> HashMap<String, String> {
+        self.meta_tags("property", "og:")
+    }
+
+    pub fn open_graph(&self) -> OpenGraphData {
+        let mut tags = self.open_graph_tags();
+        OpenGraphData {
+            title: tags.remove("og:title"),
+            description: tags.remove("og:description"),
+            image: tags.remove("og:image"),
+            url: tags.remove("og:url"),
+            site_name: tags.remove("og:site_name"),
+            og_type: tags.remove("og:type"),
+        }
+    }
+
+    /// The `<meta name="twitter:*">` tags of the page.
+    pub fn twitter_card(&self) -> TwitterCardData {
+        let mut tags = self.meta_tags("name", "twitter:");
+        TwitterCardData {
+            card: tags.remove("twitter:card"),
+            site: tags.remove("twitter:site"),
+            creator: tags.remove("twitter:creator"),
+            title: tags.remove("twitter:title"),
+            description: tags.remove("twitter:description"),
+            image: tags.remove("twitter:image"),
+        }
+    }
+
+    /// The `content` of `<meta>` tags in `<head>` whose `key` attribute starts
+    /// with `prefix`, keyed by that attribute.
+    fn meta_tags(&self, key: &str, prefix: &str) -> HashMap<String, String> {
+        let mut tags = HashMap::new();
+        let Some(head) = self.head() else {
+            return tags;
+        };
+        for meta in head.select_all_by_tag("meta") {
+            let (Some(name), Some(content)) = (meta.attr(key), meta.attr("content")) else {
+                continue;
+            };
+            if name.starts_with(prefix) {
+                tags.entry(name.to_string())
+                    .or_insert_with(|| content.to_string());
+            }
+        }
+        tags
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenGraphData {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub url: Option<String>,
+    pub site_name: Option<String>,
+    pub og_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TwitterCardData {
+    pub card: Option<String>,
+    pub site: Option<String>,
+    pub creator: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkInfo {
+    pub href: String,
+    pub text: String,
+    pub title: Option<String>,
+    pub rel: Option<String>,
+    pub is_external: bool,
+}
+
+impl DocumentData {
+    /// Every `<a href>` in the document, in document order.
+    pub fn links_with_metadata(&self) -> Vec<LinkInfo> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        root.select_all_by_tag("a")
+            .filter_map(|link| {
+                let href = link.attr("href")?.to_string();
+                Some(LinkInfo {
+                    is_external: href.starts_with("http://") || href.starts_with("https://"),
+                    text: link.text_content(),
+                    title: link.attr("title").map(String::from),
+                    rel: link.attr("rel").map(String::from),
+                    href,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptInfo {
+    pub src: Option<String>,
+    pub content: Option<String>,
+    pub script_type: Option<String>,
+    pub is_async: bool,
+    pub is_defer: bool,
+    pub is_module: bool,
+}
+
+impl DocumentData {
+    /// Every `<script>` in the document, in document order. `content` is `None`
+    /// for scripts without any text.
+    pub fn scripts(&self) -> Vec<ScriptInfo> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        root.select_all_by_tag("script")
+            .map(|script| {
+                let content = script.text_content();
+                let script_type = script.attr("type").map(String::from);
+                ScriptInfo {
+                    src: script.attr("src").map(String::from),
+                    content: (!content.is_empty()).then_some(content),
+                    is_async: script.attr("async").is_some(),
+                    is_defer: script.attr("defer").is_some(),
+                    is_module: script_type
+                        .as_deref()
+                        .is_some_and(|t| t.trim().eq_ignore_ascii_case("module")),
+                    script_type,
+                }
+            })
+            .collect()
+    }
+}
+
+impl DocumentData {
+    /// The parsed contents of every `<script type="application/ld+json">`,
+    /// in document order. Blocks that aren't valid JSON are skipped.
+    pub fn json_ld(&self) -> Vec<JsonValue> {
+        self.json_ld_with_errors(&mut vec![])
+    }
+
+    /// Like [`DocumentData::json_ld`], also collecting why each skipped block
+    /// failed to parse.
+    pub fn json_ld_with_errors(&self, errors: &mut Vec<JsonError>) -> Vec<JsonValue> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        root.select_all_by_tag("script")
+            .filter(|script| {
+                script
+                    .attr("type")
+                    .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/ld+json"))
+            })
+            .filter_map(|script| match json::parse(&script.text_content()) {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    errors.push(err);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkElement {
+    pub rel: Option<String>,
+    pub href: Option<String>,
+    pub media: Option<String>,
+    pub link_type: Option<String>,
+    pub as_attr: Option<String>,
+    pub crossorigin: Option<String>,
+}
+
+impl LinkElement {
+    pub fn has_rel(&self, rel: &str) -> bool {
+        self.rel.as_deref().is_some_and(|rels| {
+            rels.split_ascii_whitespace()
+                .any(|token| token.eq_ignore_ascii_case(rel))
+        })
+    }
+
+    /// The links among `links` with `rel="stylesheet"`.
+    pub fn stylesheets(links: &[LinkElement]) -> Vec<&LinkElement> {
+        links
+            .iter()
+            .filter(|link| link.has_rel("stylesheet"))
+            .collect()
+    }
+}
+
+impl DocumentData {
+    /// Every `<link>` in `<head>`, in document order.
+    pub fn head_links(&self) -> Vec<LinkElement> {
+        let Some(head) = self.head() else {
+            return vec![];
+        };
+        head.select_all_by_tag("link")
+            .map(|link| {
+                let attr = |name| link.attr(name).map(String::from);
+                LinkElement {
+                    rel: attr("rel"),
+                    href: attr("href"),
+                    media: attr("media"),
+                    link_type: attr("type"),
+                    as_attr: attr("as"),
+                    crossorigin: attr("crossorigin"),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormInfo {
+    pub action: Option<String>,
+    /// Lowercased, `"get"` when unspecified.
+    pub method: String,
+    pub fields: Vec<FieldInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldInfo {
+    pub name: Option<String>,
+    /// The lowercased `type` of an `<input>` (`"text"` when unspecified), or
+    /// `"textarea"` / `"select"` for those elements.
+    pub field_type: String,
+    pub value: Option<String>,
+    pub is_required: bool,
+}
+
+impl FieldInfo {
+    fn from_node(field: &Node) -> Option<Self> {
+        let (field_type, value) = match field.tag_name()? {
+            "input" => (
+                field.attr("type").unwrap_or("text").to_ascii_lowercase(),
+                field.attr("value").map(String::from),
+            ),
+            "textarea" => {
+                let content = field.text_content();
+                (
+                    "textarea".to_string(),
+                    (!content.is_empty()).then_some(content),
+                )
+            }
+            "select" => {
+                let value = field
+                    .select_all_by_tag("option")
+                    .find(|option| option.attr("selected").is_some())
+                    .map(|option| {
+                        option
+                            .attr("value")
+                            .map_or_else(|| option.text_content(), String::from)
+                    });
+                ("select".to_string(), value)
+            }
+            _ => return None,
+        };
+        Some(FieldInfo {
+            name: field.attr("name").map(String::from),
+            field_type,
+            value,
+            is_required: field.attr("required").is_some(),
+        })
+    }
+}
+
+impl DocumentData {
+    /// Every `<form>` in the document with its `<input>`, `<textarea>` and
+    /// `<select>` fields, in document order. A `<select>` takes the value of
+    /// its selected option.
+    pub fn forms(&self) -> Vec<FormInfo> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        root.select_all_by_tag("form")
+            .map(|form| FormInfo {
+                action: form.attr("action").map(String::from),
+                method: form.attr("method").unwrap_or("get").to_ascii_lowercase(),
+                fields: form.iter().filter_map(FieldInfo::from_node).collect(),
+            })
+            .collect()
+    }
+}
+
+pub const DEFAULT_WORDS_PER_MINUTE: u32 = 200;
+
+impl DocumentData {
+    /// The number of whitespace-delimited words in the text of `<body>`.
+    pub fn word_count(&self) -> usize {
+        let Some(body) = self.body() else {
+            return 0;
+        };
+        body.iter()
+            .map(|node| match &node.node_type {
+                NodeType::Text(text) => text.split_whitespace().count(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Time to read the body, rounded up to a whole minute. A rate of zero
+    /// falls back to [`DEFAULT_WORDS_PER_MINUTE`].
+    pub fn reading_time_estimate(&self, words_per_minute: u32) -> Duration {
+        let words_per_minute = match words_per_minute {
+            0 => DEFAULT_WORDS_PER_MINUTE,
+            wpm => wpm,
+        } as u64;
+        let minutes = (self.word_count() as u64).div_ceil(words_per_minute);
+        Duration::from_secs(minutes * 60)
+    }
+
+    /// Every text node with its length in characters, longest first. Ties keep
+    /// document order.
+    pub fn all_text_nodes_sorted_by_length(&self) -> Vec<(&Node, usize)> {
+        let Some(root) = self.root_node() else {
+            return vec![];
+        };
+        let mut nodes: Vec<(&Node, usize)> = root
+            .iter()
+            .filter_map(|node| match &node.node_type {
+                NodeType::Text(text) => Some((node, text.chars().count())),
+                _ => None,
+            })
+            .collect();
+        nodes.sort_by(|(_, a), (_, b)| b.cmp(a));
+        nodes
+    }
+}
+
+impl DocumentData {
+    /// Inlines the stylesheet rules matching each element that has a `style`
+    /// attribute and is selected by one of its classes, merging them with the
+    /// inline declarations by the cascade: `!important` beats normal
+    /// declarations, then inline beats stylesheet declarations, then higher
+    /// specificity and later rules win. Elements without such a class rule, or
+    /// whose inline style doesn't parse, are left alone.
+    pub fn consolidate_inline_styles(&mut self) {
+        let Some(root) = self.root_node() else {
+            return;
+        };
+        let rules: Vec<&css::Rule> = self.stylesheets.iter().flat_map(|s| s.rules()).collect();
+        let mut updates = vec![];
+        collect_consolidated_styles(root, &mut vec![], &mut vec![], &rules, &mut updates);
+        let Some(root) = self.root.as_mut() else {
+            return;
+        };
+        for (path, style) in updates {
+            let node = path
+                .iter()
+                .fold(&mut **root, |node, &index| &mut node.children_mut()[index]);
+            if let Some(attributes) = node.attributes_mut() {
+                attributes.0.insert("style".into(), AttrValue::Text(style));
+            }
+        }
+    }
+}
+
+/// Child indices leading from a document's root element down to a node. The
+/// root element itself has the empty path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct NodePath(pub Vec<usize>);
+
+impl DocumentData {
+    pub fn node_at(&self, path: &NodePath) -> Option<&Node> {
+        path.0
+            .iter()
+            .try_fold(self.root_node()?, |node, &index| node.children().get(index))
+    }
+
+    /// The computed style of every element, from a single walk over the tree
+    /// that hands each element's style down to its children for inheritance.
+    /// Invalid declarations are skipped.
+    pub fn compute_all_styles(&self) -> HashMap<NodePath, ComputedStyle> {
+        let mut styles = HashMap::new();
+        if let Some(root) = self.root_node() {
+            let rules: Vec<&css::Rule> = self.stylesheets.iter().flat_map(|s| s.rules()).collect();
+            compute_styles(
+                root,
+                &ComputedStyle::default(),
+                &mut vec![],
+                &mut vec![],
+                &rules,
+                &mut styles,
+            );
+        }
+        styles
+    }
+}
+
+fn compute_styles<'a>(
+    node: &'a Node,
+    parent_style: &ComputedStyle,
+    ancestors: &mut Vec<&'a Node>,
+    path: &mut Vec<usize>,
+    rules: &[&css::Rule],
+    styles: &mut HashMap<NodePath, ComputedStyle>,
+) {
+    if node.tag_name().is_none() {
+        return;
+    }
+    let inline = node
+        .attr("style")
+        .and_then(|style| css::parse_inline(style).ok())
+        .unwrap_or_default();
+    let mut style = ComputedStyle::inherited_from(parent_style);
+    for declaration in node.cascaded_declarations(ancestors, rules, &inline) {
+        _ = style.apply_declaration(declaration);
+    }
+    ancestors.push(node);
+    for (index, child) in node.children().iter().enumerate() {
+        path.push(index);
+        compute_styles(child, &style, ancestors, path, rules, styles);
+        path.pop();
+    }
+    ancestors.pop();
+    styles.insert(NodePath(path.clone()), style);
+}
+
+fn collect_consolidated_styles<'a>(
+    node: &'a Node,
+    ancestors: &mut Vec<&'a Node>,
+    path: &mut Vec<usize>,
+    rules: &[&css::Rule],
+    updates: &mut Vec<(Vec<usize>, String)>,
+) {
+    if let Some(style) = node.consolidated_style(ancestors, rules) {
+        updates.push((path.clone(), style));
+    }
+    ancestors.push(node);
+    for (index, child) in node.children().iter().enumerate() {
+        path.push(index);
+        collect_consolidated_styles(child, ancestors, path, rules, updates);
+        path.pop();
+    }
+    ancestors.pop();
+}
+
+impl Node {
+    fn consolidated_style(&self, ancestors: &[&Node], rules: &[&css::Rule]) -> Option<String> {
+        let inline = css::parse_inline(self.attr("style")?).ok()?;
+        if !rules
+            .iter()
+            .any(|rule| rule.matches_by_class(self, ancestors))
+        {
+            return None;
+        }
+        Some(
+            self.cascaded_declarations(ancestors, rules, &inline)
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+
+    /// The declarations that win the cascade for this element, from the
+    /// matching `rules` and the `inline` style.
+    fn cascaded_declarations<'a>(
+        &self,
+        ancestors: &[&Node],
+        rules: &[&'a css::Rule],
+        inline: &'a [css::Declaration],
+    ) -> Vec<&'a css::Declaration> {
+        let mut matching: Vec<(css::Specificity, usize, &css::Rule)> = rules
+            .iter()
+            .enumerate()
+            .filter_map(|(order, rule)| {
+                Some((rule.matching_specificity(self, ancestors)?, order, *rule))
+            })
+            .collect();
+        matching.sort_by_key(|(specificity, order, _)| (*specificity, *order));
+
+        // Declarations in cascade order; a later entry beats an earlier one
+        // unless only the earlier one is `!important`.
+        let cascade = matching
+            .iter()
+            .flat_map(|(_, _, rule)| rule.declarations())
+            .map(|d| (d, false))
+            .chain(inline.iter().map(|d| (d, true)));
+        let mut winners: Vec<(&css::Declaration, bool)> = vec![];
+        for (declaration, is_inline) in cascade {
+            let name = declaration.name.to_ascii_lowercase();
+            match winners
+                .iter_mut()
+                .find(|(d, _)| d.name.eq_ignore_ascii_case(&name))
+            {
+                Some(winner) => {
+                    let beats =
+                        (declaration.important, is_inline) >= (winner.0.important, winner.1);
+                    if beats {
+                        *winner = (declaration, is_inline);
+                    }
+                }
+                None => winners.push((declaration, is_inline)),
+            }
+        }
+        winners.into_iter().map(|(d, _)| d).collect()
+    }
+}
+
+/// Programmatic construction of a complete `<html><head/><body/></html>` document.
+#[derive(Debug, Default)]
+pub struct DocumentBuilder {
+    title: Option<String>,
+    charset: Option<String>,
+    stylesheets: Vec<String>,
+    scripts: Vec<String>,
+    body: Option<Node>,
+}
+
+impl DocumentBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, s: &str) -> Self {
+        self.title = Some(s.to_string());
+        self
+    }
+
+    pub fn charset(mut self, s: &str) -> Self {
+        self.charset = Some(s.to_string());
+        self
+    }
+
+    pub fn add_stylesheet(mut self, css: &str) -> Self {
+        self.stylesheets.push(css.to_string());
+        self
+    }
+
+    pub fn add_script(mut self, js: &str) -> Self {
+        self.scripts.push(js.to_string());
+        self
+    }
+
+    /// Sets the document body. Any other element is turned into the `<body>`,
+    /// keeping its attributes and children, so `<main id="x">` becomes
+    /// `<body id="x">`. A fragment's nodes, or any other node, become the
+    /// children of an empty `<body>`.
+    pub fn body(mut self, node: Node) -> Self {
+        self.body = Some(node);
+        self
+    }
+
+    pub fn build(self) -> DocumentData {
+        let mut document = DocumentData::new();
+        let mut head = vec![];
+
+        if let Some(charset) = self.charset {
+            let mut attrs = AttrMap::default();
+            attrs.0.insert("charset".into(), AttrValue::Text(charset));
+            head.push(element("meta".into(), attrs, vec![]));
+        }
+        if let Some(title) = self.title {
+            head.push(element(
+                "title".into(),
+                AttrMap::default(),
+                vec![text(title)],
+            ));
+        }
+        for styling in self.stylesheets {
+            // Invalid stylesheets are dropped, as they would be when parsing.
+            _ = document.load_css(styling.clone());
+            head.push(element(
+                "style".into(),
+                AttrMap::default(),
+                vec![text(styling)],
+            ));
+        }
+        for script in self.scripts {
+            head.push(element(
+                "script".into(),
+                AttrMap::default(),
+                vec![text(script)],
+            ));
+        }
+
+        let body = match self.body.map(|node| node.node_type) {
+            Some(NodeType::Element(mut data)) => {
+                data.tag_name = intern("body");
+                Node {
+                    node_type: NodeType::Element(data),
+                }
+            }
+            Some(NodeType::DocumentFragment(children)) => {
+                element("body".into(), AttrMap::default(), children)
+            }
+            Some(node_type) => element("body".into(), AttrMap::default(), vec![Node { node_type }]),
+            None => element("body".into(), AttrMap::default(), vec![]),
+        };
+        let html = element(
+            "html".into(),
+            AttrMap::default(),
+            vec![element("head".into(), AttrMap::default(), head), body],
+        );
+        _ = document.root.insert(Box::new(html));
+        document
+    }
+}
+
+pub fn text(data: String) -> Node {
+    Node {
+        node_type: NodeType::Text(data),
+    }
+}
+
+pub fn element(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
+    Node {
+        node_type: NodeType::Element(ElementData {
+            tag_name: intern(&name),
+            attributes: attrs,
+            child_nodes: children,
+        }),
+    }
+}
+
+pub fn comment(text: String) -> Node {
+    Node {
+        node_type: NodeType::Comment(text),
+    }
+}
+
+pub fn processing_instruction(target: String, data: String) -> Node {
+    Node {
+        node_type: NodeType::ProcessingInstruction { target, data },
+    }
+}
+
+pub fn cdata(content: String) -> Node {
+    Node {
+        node_type: NodeType::CDATASection(content),
+    }
+}
+
+pub fn document_fragment(children: Vec<Node>) -> Node {
+    Node {
+        node_type: NodeType::DocumentFragment(children),
+    }
+}
+
+/// The nodes from `root` down to the parent of `target`, or `None` if `target`
+/// isn't in the tree.
+pub fn collect_ancestor_chain<'a>(root: &'a Node, target: &Node) -> Option<Vec<&'a Node>> {
+    if std::ptr::eq(root, target) {
+        return Some(vec![]);
+    }
+    root.children().iter().find_map(|child| {
+        let mut chain = collect_ancestor_chain(child, target)?;
+        chain.insert(0, root);
+        Some(chain)
+    })
+}
+
+pub fn parse(document: String) -> Result<Node, ParseError> {
+    let mut context = DocumentData::new();
+    context.load_document(document)?;
+    Ok(Node {
+        node_type: NodeType::Document(context),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::ListStyleType;
+
+    fn document(html: &str) -> DocumentData {
+        let mut document = DocumentData::new();
+        document.load_document(html.to_string()).unwrap();
+        document
+    }
+
+    fn root(html: &str) -> Node {
+        *document(html).root.unwrap()
+    }
+
+    #[test]
+    fn document_builder_builds_head_and_body() {
+        let body = root("<body><p>Hello</p></body>");
+        let document = DocumentBuilder::new()
+            .title("Greeting")
+            .charset("utf-8")
+            .add_stylesheet("p { color: red }")
+            .add_stylesheet("a { color: blue }")
+            .add_script("console.log(1)")
+            .body(body.clone())
+            .build();
+        assert_eq!(document.title().as_deref(), Some("Greeting"));
+        assert_eq!(document.stylesheets.len(), 2);
+        assert_eq!(document.body(), Some(&body));
+        let head = document.head().unwrap();
+        assert_eq!(head.select_all_by_tag("script").count(), 1);
+        assert_eq!(
+            head.select_first("meta").unwrap().attr("charset"),
+            Some("utf-8")
+        );
+    }
+
+    #[test]
+    fn document_builder_turns_other_nodes_into_the_body() {
+        let document = DocumentBuilder::new()
+            .body(root("<main id=\"x\"><p>a</p></main>"))
+            .build();
+        let body = document.body().unwrap();
+        assert_eq!(body.attr("id"), Some("x"));
+        assert_eq!(body.children()[0].tag_name(), Some("p"));
+
+        let document = DocumentBuilder::new().body(text("plain".into())).build();
+        assert_eq!(document.body().unwrap().text_content(), "plain");
+    }
+
+    #[test]
+    fn strip_tags_puts_blocks_on_their_own_lines() {
+        let node = root("<div><p>Hello</p><p>World</p></div>");
+        assert_eq!(node.strip_tags(), "Hello\nWorld");
+        let opts = StripOpts {
+            block_elements_newline: true,
+            normalize_whitespace: false,
+        };
+        assert_eq!(node.strip_tags_with_opts(opts), "Hello\nWorld");
+        let opts = StripOpts {
+            block_elements_newline: false,
+            normalize_whitespace: true,
+        };
+        assert_eq!(node.strip_tags_with_opts(opts), "HelloWorld");
+    }
+
+    #[test]
+    fn strip_tags_normalizes_whitespace() {
+        let node = root("<div><span>  foo  </span></div>");
+        assert_eq!(node.strip_tags(), "foo");
+        let node = root("<p>a   b\t c</p>");
+        assert_eq!(node.strip_tags(), "a b c");
+    }
+
+    #[test]
+    fn favicon_accepts_each_icon_rel() {
+        for rel in ["icon", "shortcut icon", "apple-touch-icon", "ICON"] {
+            let html = format!(
+                "<html><head><link rel=\"{}\" href=\"/i.png\"></head><body></body></html>",
+                rel
+            );
+            assert_eq!(
+                document(&html).favicon().as_deref(),
+                Some("/i.png"),
+                "{}",
+                rel
+            );
+        }
+    }
+
+    #[test]
+    fn favicon_takes_the_first_icon() {
+        let document = document(
+            "<html><head><link rel=\"stylesheet\" href=\"a.css\">\
+             <link rel=\"icon\" href=\"first.ico\"><link rel=\"icon\" href=\"second.ico\">\
+             </head><body></body></html>",
+        );
+        assert_eq!(document.favicon().as_deref(), Some("first.ico"));
+    }
+
+    #[test]
+    fn favicon_is_none_without_links() {
+        let document = document("<html><head><title>t</title></head><body></body></html>");
+        assert_eq!(document.favicon(), None);
+    }
+
+    #[test]
+    fn sibling_lookups() {
+        let list = root("<ul><li>a</li><li>b</li><li>c</li></ul>");
+        let [first, middle, last] = list.children() else {
+            panic!()
+        };
+        assert_eq!(first.previous_sibling(&list), None);
+        assert_eq!(first.next_sibling(&list), Some(middle));
+        assert_eq!(middle.previous_sibling(&list), Some(first));
+        assert_eq!(middle.next_sibling(&list), Some(last));
+        assert_eq!(last.previous_sibling(&list), Some(middle));
+        assert_eq!(last.next_sibling(&list), None);
+        let siblings = middle.siblings(&list).unwrap();
+        assert!(std::ptr::eq(siblings[0], first) && std::ptr::eq(siblings[1], last));
+        assert_eq!(list.siblings(&list), None);
+    }
+
+    #[test]
+    fn class_token_helpers() {
+        let mut node = root("<p class=\" intro  lead\tintro \"></p>");
+        assert!(node.has_class("intro"));
+        assert!(node.has_class("lead"));
+        assert!(!node.has_class("int"));
+        assert!(!node.has_class("intro lead"));
+
+        node.add_class("lead");
+        assert_eq!(node.attr("class"), Some(" intro  lead\tintro "));
+        node.add_class("wide");
+        assert_eq!(node.attr("class"), Some(" intro  lead\tintro wide"));
+
+        node.remove_class("intro");
+        assert_eq!(node.attr("class"), Some("lead wide"));
+        node.remove_class("missing");
+        assert_eq!(node.attr("class"), Some("lead wide"));
+
+        assert!(!node.toggle_class("lead"));
+        assert!(node.toggle_class("lead"));
+        assert_eq!(node.attr("class"), Some("wide lead"));
+    }
+
+    #[test]
+    fn add_class_creates_the_attribute() {
+        let mut node = root("<p></p>");
+        assert!(!node.has_class("new"));
+        node.add_class("new");
+        assert_eq!(node.attr("class"), Some("new"));
+        let mut node = root("<p class></p>");
+        node.add_class("new");
+        assert_eq!(node.attr("class"), Some("new"));
+    }
+
+    #[test]
+    fn links_with_metadata() {
+        let links = document(
+            "<div><a href=\"https://example.com\" title=\"Example\" rel=\"noopener\">Ex<b>ample</b></a>\
+             <a href=\"/about\">About</a><a name=\"anchor\">no href</a>\
+             <a href=\"http://example.org\" rel=\"nofollow\"></a></div>",
+        )
+        .links_with_metadata();
+        assert_eq!(
+            links,
+            vec![
+                LinkInfo {
+                    href: "https://example.com".into(),
+                    text: "Example".into(),
+                    title: Some("Example".into()),
+                    rel: Some("noopener".into()),
+                    is_external: true,
+                },
+                LinkInfo {
+                    href: "/about".into(),
+                    text: "About".into(),
+                    title: None,
+                    rel: None,
+                    is_external: false,
+                },
+                LinkInfo {
+                    href: "http://example.org".into(),
+                    text: "".into(),
+                    title: None,
+                    rel: Some("nofollow".into()),
+                    is_external: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_text_uppercases_text_nodes() {
+        let mut node =
+            root("<div id=\"x\"><p class=\"y\">hello</p><p>WORLD</p><!--note--><em>mixed Case</em></div>");
+        let original = node.clone();
+        assert_eq!(node.replace_text(|text| text.to_uppercase()), 2);
+        assert_eq!(node.text_content(), "HELLOWORLDMIXED CASE");
+        assert_eq!(node.children()[2], original.children()[2]);
+        assert_eq!(node.children()[1], original.children()[1]);
+
+        let tags = |node: &Node| {
+            node.iter()
+                .filter_map(|n| Some((n.tag_name()?.to_string(), n.attributes()?.clone())))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tags(&node), tags(&original));
+        assert_eq!(node.replace_text(|text| text.to_uppercase()), 0);
+    }
+
+    #[test]
+    fn closest_element_of_each_node_type() {
+        let element = root("<p>a</p>");
+        assert!(std::ptr::eq(element.closest_element().unwrap(), &element));
+        assert_eq!(text("a".into()).closest_element(), None);
+        assert_eq!(comment("a".into()).closest_element(), None);
+        let document = parse("<p>a</p>".into()).unwrap();
+        assert_eq!(document.closest_element(), None);
+    }
+
+    #[test]
+    fn is_in_head() {
+        let document = document("<html><head><title>T</title></head><body><p>a</p></body></html>");
+        let title = document.head().unwrap().select_first("title").unwrap();
+        assert!(title.is_in_head(&document));
+        assert!(title.children()[0].is_in_head(&document));
+        assert!(!title.clone().is_in_head(&document));
+        let paragraph = document.body().unwrap().select_first("p").unwrap();
+        assert!(!paragraph.is_in_head(&document));
+    }
+
+    #[test]
+    fn word_count_and_reading_time() {
+        let words = "word ".repeat(401);
+        let document = document(&format!(
+            "<html><head><title>Not counted</title></head>\
+             <body><p>{}</p><ul><li>two words</li></ul></body></html>",
+            words
+        ));
+        assert_eq!(document.word_count(), 403);
+        assert_eq!(
+            document.reading_time_estimate(200),
+            Duration::from_secs(180)
+        );
+        assert_eq!(document.reading_time_estimate(0), Duration::from_secs(180));
+        assert_eq!(document.reading_time_estimate(403), Duration::from_secs(60));
+        assert_eq!(
+            document.reading_time_estimate(402),
+            Duration::from_secs(120)
+        );
+    }
+
+    #[test]
+    fn empty_document_takes_no_time_to_read() {
+        let document = DocumentData::new();
+        assert_eq!(document.word_count(), 0);
+        assert_eq!(document.reading_time_estimate(200), Duration::ZERO);
+        let document = self::document("<html><body></body></html>");
+        assert_eq!(document.reading_time_estimate(200), Duration::ZERO);
+    }
+
+    #[test]
+    fn patch_attribute() {
+        let mut link = root("<a href=\"/about\" download>About</a>");
+        assert!(link.patch_attribute("href", |href| format!("https://example.com{}", href)));
+        assert_eq!(link.attr("href"), Some("https://example.com/about"));
+
+        let before = link.attributes().unwrap().clone();
+        assert!(!link.patch_attribute("download", |_| "file".into()));
+        assert!(!link.patch_attribute("title", |_| "About".into()));
+        assert_eq!(link.attributes(), Some(&before));
+
+        let mut node = text("/about".into());
+        assert!(!node.patch_attribute("href", |href| href.to_uppercase()));
+    }
+
+    #[test]
+    fn document_fragments() {
+        let mut fragment = document_fragment(vec![root("<b>a</b>")]);
+        fragment.append_child(text("b".into())).unwrap();
+        assert_eq!(fragment.children().len(), 2);
+        assert_eq!(fragment.outer_html(), "<b>a</b>b");
+        assert_eq!(fragment.tag_name(), None);
+
+        let parsed = crate::html::parse_fragment("<i>x</i><i>y</i>".into()).unwrap();
+        assert!(matches!(parsed.node_type, NodeType::DocumentFragment(_)));
+        assert_eq!(parsed.children().len(), 2);
+
+        let mut list = root("<p>c</p>");
+        list.append_child(parsed).unwrap();
+        assert_eq!(list.outer_html(), "<p>c<i>x</i><i>y</i></p>");
+        assert_eq!(list.children().len(), 3);
+    }
+
+    #[test]
+    fn attr_map_from_iter() {
+        let attributes = AttrMap::from_iter([("id", "foo"), ("class", "bar")]);
+        assert_eq!(attributes.0.len(), 2);
+        assert_eq!(attributes.0["id"], AttrValue::Text("foo".into()));
+        assert_eq!(attributes.0["class"], AttrValue::Text("bar".into()));
+    }
+
+    #[test]
+    fn attr_map_from_str() {
+        let attributes: AttrMap = "id=\"foo\" class='bar baz' disabled".parse().unwrap();
+        assert_eq!(attributes.0.len(), 3);
+        assert_eq!(attributes.0["id"], AttrValue::Text("foo".into()));
+        assert_eq!(attributes.0["class"], AttrValue::Text("bar baz".into()));
+        assert_eq!(attributes.0["disabled"], AttrValue::Implicit);
+        assert!("id=\"foo\" >".parse::<AttrMap>().is_err());
+
+        let round_trip: AttrMap = attributes.to_string().parse().unwrap();
+        assert_eq!(round_trip, attributes);
+    }
+
+    #[test]
+    fn canonical_url() {
+        let page = document(
+            "<html><head><link rel=\"stylesheet\" href=\"a.css\">\
+             <link rel=\"canonical\" href=\"https://example.com/post\"></head><body></body></html>",
+        );
+        assert_eq!(
+            page.canonical_url().as_deref(),
+            Some("https://example.com/post")
+        );
+        let page = document("<html><head><title>T</title></head><body></body></html>");
+        assert_eq!(page.canonical_url(), None);
+    }
+
+    #[test]
+    fn open_graph_tags() {
+        let page = document(
+            "<html><head><meta property=\"og:title\" content=\"Post\">\
+             <meta property=\"og:image\" content=\"a.png\">\
+             <meta property=\"og:image\" content=\"b.png\">\
+             <meta name=\"description\" content=\"Not OG\"></head><body></body></html>",
+        );
+        let tags = page.open_graph_tags();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["og:title"], "Post");
+        assert_eq!(tags["og:image"], "a.png");
+    }
+
+    #[test]
+    fn append_and_prepend_text() {
+        let mut node = root("<p></p>");
+        node.append_text("a").unwrap();
+        assert_eq!(node.children(), &[text("a".into())]);
+        node.append_text("b").unwrap();
+        assert_eq!(node.children(), &[text("ab".into())]);
+        node.prepend_text("0").unwrap();
+        assert_eq!(node.children(), &[text("0ab".into())]);
+
+        let mut node = root("<p><b>x</b></p>");
+        node.append_text("after").unwrap();
+        node.prepend_text("before").unwrap();
+        assert_eq!(node.outer_html(), "<p>before<b>x</b>after</p>");
+        assert_eq!(node.children().len(), 3);
+
+        let mut node = comment("c".into());
+        assert_eq!(node.append_text("a"), Err(DomError::NotAnElement));
+        assert_eq!(node.prepend_text("a"), Err(DomError::NotAnElement));
+    }
+
+    #[test]
+    fn flatten_inline_elements() {
+        let mut node =
+            root("<p>a<span>b<span><span>c</span></span></span>d<span class=\"x\">e</span></p>");
+        node.flatten_inline_elements(&["span"]);
+        assert_eq!(node.outer_html(), "<p>abcd<span class=\"x\">e</span></p>");
+        let texts: Vec<String> = node.children().iter().map(Node::text_content).collect();
+        assert_eq!(texts, ["a", "b", "c", "d", "e"]);
+
+        let mut node = root("<div><span>kept</span><font>gone</font></div>");
+        node.flatten_inline_elements(&["font"]);
+        assert_eq!(node.outer_html(), "<div><span>kept</span>gone</div>");
+    }
+
+    #[test]
+    fn text_nodes_sorted_by_length() {
+        let page = DocumentData {
+            root: Some(Box::new(element(
+                "body".into(),
+                AttrMap::default(),
+                vec![
+                    text(String::new()),
+                    text("medium".into()),
+                    comment("a very long comment".into()),
+                    element(
+                        "p".into(),
+                        AttrMap::default(),
+                        vec![text("longest text".into())],
+                    ),
+                    text("sixsix".into()),
+                ],
+            ))),
+            ..DocumentData::new()
+        };
+        let sorted = page.all_text_nodes_sorted_by_length();
+        let texts: Vec<(String, usize)> = sorted
+            .iter()
+            .map(|(node, len)| (node.text_content(), *len))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                ("longest text".to_string(), 12),
+                ("medium".to_string(), 6),
+                ("sixsix".to_string(), 6),
+                ("".to_string(), 0),
+            ]
+        );
+        let root = page.root_node().unwrap();
+        assert!(std::ptr::eq(sorted[1].0, &root.children()[1]));
+        assert!(std::ptr::eq(sorted[0].0, &root.children()[3].children()[0]));
+    }
+
+    fn wrap_text_in_span(node: &Node) -> Node {
+        match &node.node_type {
+            NodeType::Text(_) => element("span".into(), AttrMap::default(), vec![node.clone()]),
+            _ => node.clone(),
+        }
+    }
+
+    #[test]
+    fn map_wraps_text_in_spans() {
+        let original = root("<div><p>a<b>b</b></p>c</div>");
+        let mapped = original.map(wrap_text_in_span);
+        assert_eq!(
+            mapped.outer_html(),
+            "<div><p><span>a</span><b><span>b</span></b></p><span>c</span></div>"
+        );
+        assert_eq!(original.outer_html(), "<div><p>a<b>b</b></p>c</div>");
+    }
+
+    #[test]
+    fn map_keeps_nodes_of_another_kind_as_returned() {
+        let original = root("<div><p>a</p><ul><li>b</li></ul></div>");
+        let mapped = original.map(|node| match node.tag_name() {
+            // A wrapper around the original keeps the original children.
+            Some("p") => element("section".into(), AttrMap::default(), vec![node.clone()]),
+            Some("ul") => text("list".into()),
+            _ => wrap_text_in_span(node),
+        });
+        assert_eq!(
+            mapped.outer_html(),
+            "<div><section><p>a</p></section>list</div>"
+        );
+
+        let mapped = root("<ul><li>b</li></ul>").map(|node| match node.tag_name() {
+            Some("li") => element("li".into(), AttrMap::from_iter([("class", "x")]), vec![]),
+            _ => wrap_text_in_span(node),
+        });
+        assert_eq!(
+            mapped.outer_html(),
+            "<ul><li class=\"x\"><span>b</span></li></ul>"
+        );
+    }
+
+    #[test]
+    fn filter_map_removes_scripts() {
+        let html = "<html><head><script>alert(1)</script><title>T</title></head>\
+                    <body><p>a<script src=\"x.js\"></script>b</p><div><script>c</script></div></body></html>";
+        let original = parse(html.into()).unwrap();
+        let filtered = original
+            .filter_map(|node| {
+                assert!(node.children().is_empty());
+                match node.tag_name() {
+                    Some("script") => None,
+                    _ => Some(node.clone()),
+                }
+            })
+            .unwrap();
+        assert_eq!(filtered.select_all_by_tag("script").count(), 0);
+        assert_eq!(original.select_all_by_tag("script").count(), 3);
+        assert_eq!(
+            filtered.outer_html(),
+            "<html><head><title>T</title></head><body><p>ab</p><div></div></body></html>"
+        );
+        assert_eq!(filtered.filter_map(|_| None), None);
+    }
+
+    #[test]
+    fn open_graph_and_twitter_card() {
+        let page = document(
+            "<html><head><meta property=\"og:title\" content=\"Title\">\
+             <meta property=\"og:description\" content=\"Desc\">\
+             <meta property=\"og:image\" content=\"a.png\">\
+             <meta property=\"og:url\" content=\"https://example.com\">\
+             <meta property=\"og:site_name\" content=\"Site\">\
+             <meta property=\"og:type\" content=\"article\">\
+             <meta name=\"twitter:card\" content=\"summary\">\
+             <meta name=\"twitter:creator\" content=\"@me\"></head><body></body></html>",
+        );
+        assert_eq!(
+            page.open_graph(),
+            OpenGraphData {
+                title: Some("Title".into()),
+                description: Some("Desc".into()),
+                image: Some("a.png".into()),
+                url: Some("https://example.com".into()),
+                site_name: Some("Site".into()),
+                og_type: Some("article".into()),
+            }
+        );
+        assert_eq!(
+            page.twitter_card(),
+            TwitterCardData {
+                card: Some("summary".into()),
+                creator: Some("@me".into()),
+                ..TwitterCardData::default()
+            }
+        );
+    }
+
+    #[test]
+    fn open_graph_without_tags_or_head() {
+        let page = document("<html><head><title>T</title></head><body></body></html>");
+        assert_eq!(page.open_graph(), OpenGraphData::default());
+        let page = document("<p><meta property=\"og:title\" content=\"Body\"></p>");
+        assert_eq!(page.open_graph(), OpenGraphData::default());
+        assert_eq!(page.twitter_card(), TwitterCardData::default());
+    }
+
+    #[test]
+    fn accessible_name_sources_in_priority_order() {
+        let cases = [
+            (
+                "<button aria-label=\"Close  dialog\">X</button>",
+                AccessibleNameSource::AriaLabel,
+                "Close dialog",
+            ),
+            (
+                "<img aria-label=\"Label\" alt=\"Alt\">",
+                AccessibleNameSource::AriaLabel,
+                "Label",
+            ),
+            ("<img alt=\"A cat\">", AccessibleNameSource::Alt, "A cat"),
+            (
+                "<input type=\"image\" alt=\"Go\">",
+                AccessibleNameSource::Alt,
+                "Go",
+            ),
+            (
+                "<div alt=\"ignored\">Some\n <b>text</b></div>",
+                AccessibleNameSource::TextContent,
+                "Some text",
+            ),
+            (
+                "<a aria-label=\" \">Home</a>",
+                AccessibleNameSource::TextContent,
+                "Home",
+            ),
+            ("<img alt=\"\">", AccessibleNameSource::None, ""),
+            ("<span> </span>", AccessibleNameSource::None, ""),
+        ];
+        for (html, source, name) in cases {
+            let node = root(html);
+            assert_eq!(node.accessible_name_source(), source, "{}", html);
+            assert_eq!(node.to_accessible_text(), name, "{}", html);
+        }
+    }
+
+    #[test]
+    fn closest_by_tag_finds_the_nearest_ancestor() {
+        let page = root(
+            "<div><form id=\"outer\"><fieldset><form id=\"inner\"><label><input name=\"q\"></label></form></fieldset></form><p>x</p></div>",
+        );
+        let input = page.select_first("input").unwrap();
+        let chain = collect_ancestor_chain(&page, input).unwrap();
+        let tags: Vec<&str> = chain.iter().filter_map(|node| node.tag_name()).collect();
+        assert_eq!(tags, ["div", "form", "fieldset", "form", "label"]);
+        let form = input.closest_by_tag("form", &chain).unwrap();
+        assert_eq!(form.attr("id"), Some("inner"));
+        assert_eq!(input.closest_by_tag("table", &chain), None);
+
+        let paragraph = page.select_first("p").unwrap();
+        let chain = collect_ancestor_chain(&page, paragraph).unwrap();
+        assert_eq!(paragraph.closest_by_tag("form", &chain), None);
+
+        assert_eq!(collect_ancestor_chain(&page, &paragraph.clone()), None);
+    }
+
+    #[test]
+    fn consolidate_inline_styles_follows_the_cascade() {
+        let mut page = document(
+            "<html><head><style>\
+             .note { color: red !important; margin: 2px; padding: 3px; }\
+             p.note { padding: 4px; }\
+             p { border: none; }\
+             </style></head><body>\
+             <p class=\"note\" style=\"color: blue; margin: 1px !important\">a</p>\
+             <p style=\"color: blue\">b</p>\
+             <p class=\"other\" style=\"color: blue\">c</p>\
+             <p class=\"note\">d</p>\
+             </body></html>",
+        );
+        page.consolidate_inline_styles();
+        let styles: Vec<Option<&str>> = page
+            .body()
+            .unwrap()
+            .children()
+            .iter()
+            .map(|p| p.attr("style"))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                Some("border: none; color: red !important; margin: 1px !important; padding: 4px;"),
+                Some("color: blue"),
+                Some("color: blue"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn content_metrics() {
+        let node = root("<div>one two <a>three</a><p>four five six</p></div>");
+        assert_eq!(node.count_words_in_element(), 6);
+        assert_eq!(node.word_density_score(), 2.0);
+        let html = node.outer_html();
+        assert_eq!(
+            node.text_to_html_ratio(),
+            "one two threefour five six".len() as f64 / html.len() as f64
+        );
+
+        let empty = root("<div></div>");
+        assert_eq!(empty.count_words_in_element(), 0);
+        assert_eq!(empty.word_density_score(), 0.0);
+        assert_eq!(empty.text_to_html_ratio(), 0.0);
+        assert_eq!(text(String::new()).text_to_html_ratio(), 0.0);
+    }
+
+    #[test]
+    fn xpath_round_trips() {
+        let page = parse(
+            "<html><head></head><body><div>a</div><div><p>b</p><!--c--><p>d<b>e</b>f</p></div></body></html>"
+                .into(),
+        )
+        .unwrap();
+        for node in page.iter() {
+            let xpath = node.compute_xpath(&page);
+            assert!(
+                std::ptr::eq(page.evaluate_xpath(&xpath).unwrap(), node),
+                "{}",
+                xpath
+            );
+        }
+
+        let paragraph = page.select_all_by_tag("p").nth(1).unwrap();
+        assert_eq!(
+            paragraph.compute_xpath(&page),
+            "/html[1]/body[1]/div[2]/p[2]"
+        );
+        assert_eq!(
+            paragraph.children()[2].compute_xpath(&page),
+            "/html[1]/body[1]/div[2]/p[2]/text()[2]"
+        );
+        assert_eq!(page.compute_xpath(&page), "/");
+        assert_eq!(paragraph.clone().compute_xpath(&page), "");
+    }
+
+    #[test]
+    fn has_descendant_with_tag() {
+        let form = root("<form><div><label>a</label><textarea></textarea></div></form>");
+        assert!(form.has_descendant_with_tag("textarea"));
+        assert!(form.has_descendant_with_tag("label"));
+        assert!(!form.has_descendant_with_tag("form"));
+        assert!(!form.has_descendant_with_tag("img"));
+    }
+
+    #[test]
+    fn has_descendant_matching_stops_at_the_first_match() {
+        let node = root("<div><p>a</p><p>b</p><p>c</p></div>");
+        let calls = std::cell::Cell::new(0);
+        assert!(node.has_descendant_matching(|node| {
+            calls.set(calls.get() + 1);
+            assert!(calls.get() < 2, "called after the first match");
+            node.tag_name() == Some("p")
+        }));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn load_document_sets_the_root() {
+        let mut document = DocumentData::new();
+        assert_eq!(document.root, None);
+        assert_eq!(document.root_node(), None);
+        document.load_document("<p>a</p>".into()).unwrap();
+        assert_eq!(document.root_node().and_then(Node::tag_name), Some("p"));
+    }
+
+    #[test]
+    fn path_of_elements() {
+        let page = parse("<html><body><div><p>a</p></div></body></html>".into()).unwrap();
+        let paragraph = page.select_first("p").unwrap();
+        assert_eq!(
+            page.path_of_elements(paragraph),
+            Some(vec!["html".into(), "body".into(), "div".into(), "p".into()])
+        );
+        let html = page.select_first("html").unwrap();
+        assert_eq!(html.path_of_elements(html), Some(vec!["html".into()]));
+        assert_eq!(
+            paragraph.path_of_elements(&paragraph.children()[0]),
+            Some(vec![])
+        );
+        let other = root("<p>a</p>");
+        assert_eq!(page.path_of_elements(&other), None);
+    }
+
+    #[test]
+    fn scripts() {
+        let page = document(
+            "<html><head><script src=\"a.js\" async></script>\
+             <script type=\"module\" src=\"m.js\" defer></script></head>\
+             <body><script>var x = 1;</script><script type=\"application/ld+json\" async defer>{}</script></body></html>",
+        );
+        let info =
+            |src: Option<&str>, content: Option<&str>, script_type: Option<&str>| ScriptInfo {
+                src: src.map(String::from),
+                content: content.map(String::from),
+                script_type: script_type.map(String::from),
+                is_async: false,
+                is_defer: false,
+                is_module: false,
+            };
+        assert_eq!(
+            page.scripts(),
+            [
+                ScriptInfo {
+                    is_async: true,
+                    ..info(Some("a.js"), None, None)
+                },
+                ScriptInfo {
+                    is_defer: true,
+                    is_module: true,
+                    ..info(Some("m.js"), None, Some("module"))
+                },
+                info(None, Some("var x = 1;"), None),
+                ScriptInfo {
+                    is_async: true,
+                    is_defer: true,
+                    ..info(None, Some("{}"), Some("application/ld+json"))
+                },
+            ]
+        );
+        assert_eq!(document("<p>no scripts</p>").scripts(), []);
+    }
+
+    #[test]
+    fn encode_and_decode_html_entities() {
+        let mut node = element(
+            "p".into(),
+            AttrMap::from_iter([("title", "a&b")]),
+            vec![
+                text("a & b < c > d \"e\" 'f'".into()),
+                element("b".into(), AttrMap::default(), vec![text("<&>".into())]),
+            ],
+        );
+        let original = node.clone();
+        node.encode_html_entities();
+        assert_eq!(
+            node.children()[0],
+            text("a &amp; b &lt; c &gt; d &quot;e&quot; &#x27;f&#x27;".into())
+        );
+        assert_eq!(
+            node.children()[1].children()[0],
+            text("&lt;&amp;&gt;".into())
+        );
+        assert_eq!(node.attr("title"), Some("a&b"));
+        assert_eq!(node.children()[1].tag_name(), Some("b"));
+
+        node.decode_html_entities();
+        assert_eq!(node, original);
+    }
+
+    #[test]
+    fn contains_text() {
+        let node = root("<div>Direct text<section><p><b>Deeply Nested</b></p></section><!--hidden comment--></div>");
+        assert!(node.contains_text("Direct"));
+        assert!(node.contains_text("Deeply Nested"));
+        assert!(!node.contains_text("deeply nested"));
+        assert!(node.contains_text_ci("deeply nested"));
+        assert!(node.contains_text_ci("DIRECT TEXT"));
+        assert!(!node.contains_text("missing"));
+        assert!(!node.contains_text_ci("MISSING"));
+        assert!(!node.contains_text("hidden"));
+        assert!(!node.contains_text_ci("HIDDEN"));
+    }
+
+    #[test]
+    fn get_all_attributes_named() {
+        let node = root(
+            "<div><a href=\"/a\">a</a><img src=\"x.png\"><p><a href=\"/b\"><img src=\"y.png\"></a></p>\
+             <a href>empty</a><a>none</a></div>",
+        );
+        assert_eq!(
+            node.get_all_attributes_named("href"),
+            [
+                &AttrValue::Text("/a".into()),
+                &AttrValue::Text("/b".into()),
+                &AttrValue::Implicit
+            ]
+        );
+        assert_eq!(node.get_all_attribute_values("href"), ["/a", "/b"]);
+        assert_eq!(node.get_all_attribute_values("src"), ["x.png", "y.png"]);
+        assert!(node.get_all_attributes_named("data-missing").is_empty());
+        assert!(node.get_all_attribute_values("data-missing").is_empty());
+    }
+
+    #[test]
+    fn load_css_from_file() {
+        let path = std::env::temp_dir().join(format!("roxy-test-{}.css", std::process::id()));
+        fs::write(&path, "h1 { color: red; } p { margin: 0; }").unwrap();
+        let mut document = DocumentData::new();
+        let result = document.load_css_from_file(&path);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(document.stylesheets.len(), 1);
+        assert_eq!(document.stylesheets[0].rules().len(), 2);
+
+        let missing = document.load_css_from_file(Path::new("/nonexistent/roxy.css"));
+        assert!(matches!(missing, Err(CssParseError::Load(_))));
+        assert_eq!(document.stylesheets.len(), 1);
+    }
+
+    #[cfg(feature = "fetch")]
+    #[test]
+    fn load_css_from_url() {
+        let mut document = DocumentData::new();
+        document
+            .load_css_from_url("https://example.com/a.css", |url| {
+                assert_eq!(url, "https://example.com/a.css");
+                Ok("a { color: blue; }".into())
+            })
+            .unwrap();
+        assert_eq!(document.stylesheets[0].rules().len(), 1);
+        let failed = document.load_css_from_url("x", |_| Err("offline".into()));
+        assert_eq!(failed, Err(CssParseError::Load("offline".into())));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn tree_statistics() {
+        // div
+        // ├── p ── "a"
+        // ├── ul
+        // │   ├── li ── "b"
+        // │   └── li
+        // └── <!-- c -->
+        let item = |children| element("li".into(), AttrMap::default(), children);
+        let tree = element(
+            "div".into(),
+            AttrMap::default(),
+            vec![
+                element("p".into(), AttrMap::default(), vec![text("a".into())]),
+                element(
+                    "ul".into(),
+                    AttrMap::default(),
+                    vec![item(vec![text("b".into())]), item(vec![])],
+                ),
+                comment("c".into()),
+            ],
+        );
+        assert_eq!(tree.element_count(), 5);
+        assert_eq!(tree.size(), 8);
+        assert_eq!(tree.node_count(), 8);
+        assert_eq!(tree.height(), 3);
+        assert_eq!(tree.max_depth(), 4);
+        assert_eq!(tree.average_children(), 7.0 / 5.0);
+
+        let leaf = text("x".into());
+        assert_eq!(leaf.element_count(), 0);
+        assert_eq!(leaf.size(), 1);
+        assert_eq!(leaf.height(), 0);
+        assert_eq!(leaf.average_children(), 0.0);
+    }
+
+    #[test]
+    fn head_links_reads_link_attributes() {
+        let document = document(
+            "<html><head>\
+             <link rel=\"stylesheet\" href=\"a.css\" media=\"print\" type=\"text/css\">\
+             <link rel=\"preload\" href=\"font.woff2\" as=\"font\" crossorigin=\"anonymous\">\
+             <link rel=\"icon\" href=\"favicon.ico\">\
+             <link rel=\"alternate stylesheet\">\
+             </head><body><link rel=\"stylesheet\" href=\"body.css\"></body></html>",
+        );
+        let links = document.head_links();
+        assert_eq!(links.len(), 4);
+        assert_eq!(
+            links[0],
+            LinkElement {
+                rel: Some("stylesheet".into()),
+                href: Some("a.css".into()),
+                media: Some("print".into()),
+                link_type: Some("text/css".into()),
+                as_attr: None,
+                crossorigin: None,
+            }
+        );
+        assert_eq!(links[1].as_attr.as_deref(), Some("font"));
+        assert_eq!(links[1].crossorigin.as_deref(), Some("anonymous"));
+        assert!(links[2].has_rel("icon"));
+        assert_eq!(links[3].href, None);
+
+        let stylesheets = LinkElement::stylesheets(&links);
+        assert_eq!(stylesheets, vec![&links[0], &links[3]]);
+    }
+
+    #[test]
+    fn head_links_without_head() {
+        assert_eq!(document("<div><p>x</p></div>").head_links(), vec![]);
+    }
+
+    #[test]
+    fn wrap_child_moves_the_child_into_the_wrapper() {
+        let mut div = root("<div><p>a</p><img src=\"x.png\"><p>b</p></div>");
+        div.wrap_child(1, "figure", AttrMap::from_iter([("class", "photo")]))
+            .unwrap();
+        let tags: Vec<_> = div.children().iter().map(|c| c.tag_name()).collect();
+        assert_eq!(tags, [Some("p"), Some("figure"), Some("p")]);
+        let figure = &div.children()[1];
+        assert_eq!(figure.attr("class"), Some("photo"));
+        assert_eq!(figure.children().len(), 1);
+        assert_eq!(figure.children()[0].attr("src"), Some("x.png"));
+        assert_eq!(
+            div.outer_html(),
+            "<div><p>a</p><figure class=\"photo\"><img src=\"x.png\"></figure><p>b</p></div>"
+        );
+    }
+
+    #[test]
+    fn wrap_child_errors() {
+        let mut div = root("<div><p>a</p></div>");
+        assert_eq!(
+            div.wrap_child(1, "span", AttrMap::default()),
+            Err(DomError::IndexOutOfBounds { index: 1, len: 1 })
+        );
+        let mut leaf = text("a".into());
+        assert_eq!(
+            leaf.wrap_child(0, "span", AttrMap::default()),
+            Err(DomError::NotAnElement)
+        );
+        assert_eq!(div, root("<div><p>a</p></div>"));
+    }
+
+    #[test]
+    fn unwrap_child_inlines_the_children() {
+        let mut div = root("<div><p>a</p><span><b>b</b><i>c</i></span><p>d</p></div>");
+        let inner = div.unwrap_child(1).unwrap();
+        let tags: Vec<_> = inner.iter().map(|c| c.tag_name()).collect();
+        assert_eq!(tags, [Some("b"), Some("i")]);
+        assert_eq!(
+            div.outer_html(),
+            "<div><p>a</p><b>b</b><i>c</i><p>d</p></div>"
+        );
+        assert_eq!(div.children()[1..3], inner[..]);
+    }
+
+    #[test]
+    fn unwrap_child_deletes_an_empty_element() {
+        let mut div = root("<div><p>a</p><span></span><p>b</p></div>");
+        assert_eq!(div.unwrap_child(1), Ok(vec![]));
+        assert_eq!(div.outer_html(), "<div><p>a</p><p>b</p></div>");
+    }
+
+    #[test]
+    fn unwrap_child_errors() {
+        let mut div = element("div".into(), AttrMap::default(), vec![text("a".into())]);
+        assert_eq!(div.unwrap_child(0), Err(DomError::NotAnElement));
+        assert_eq!(
+            div.unwrap_child(3),
+            Err(DomError::IndexOutOfBounds { index: 3, len: 1 })
+        );
+        assert_eq!(div.children(), [text("a".into())]);
+    }
+
+    #[test]
+    fn wrap_and_unwrap_are_inverses() {
+        let original = root("<ul><li>a</li><li>b</li></ul>");
+        let mut list = original.clone();
+        list.wrap_child(1, "div", AttrMap::default()).unwrap();
+        list.unwrap_child(1).unwrap();
+        assert_eq!(list, original);
+    }
+
+    #[test]
+    fn swap_children_reorders() {
+        let mut row = root("<tr><td>a</td><th>b</th><td>c</td></tr>");
+        row.swap_children(0, 2).unwrap();
+        assert_eq!(row.outer_html(), "<tr><td>c</td><th>b</th><td>a</td></tr>");
+        row.swap_children(1, 1).unwrap();
+        assert_eq!(row.outer_html(), "<tr><td>c</td><th>b</th><td>a</td></tr>");
+        assert_eq!(
+            row.swap_children(0, 3),
+            Err(DomError::IndexOutOfBounds { index: 3, len: 3 })
+        );
+        assert_eq!(
+            text("a".into()).swap_children(0, 0),
+            Err(DomError::NotAnElement)
+        );
+    }
+
+    #[test]
+    fn sort_children_by_tag_name() {
+        let mut div = root("<div><p>1</p><b>2</b><i>3</i><b>4</b></div>");
+        div.sort_children_by(|a, b| a.tag_name().cmp(&b.tag_name()))
+            .unwrap();
+        assert_eq!(
+            div.outer_html(),
+            "<div><b>2</b><b>4</b><i>3</i><p>1</p></div>"
+        );
+    }
+
+    #[test]
+    fn serialize_to_string_starts_with_the_doctype() {
+        let html = "<!DOCTYPE html><html><head><title>T</title></head>\
+                    <body><p class=\"x\">a<br>b</p></body></html>";
+        let document = document(html);
+        let serialized = document.serialize_to_string();
+        assert_eq!(serialized, html);
+        let reparsed = self::document(&serialized);
+        assert_eq!(reparsed.doctype, document.doctype);
+        assert_eq!(reparsed.root, document.root);
+    }
+
+    #[test]
+    fn serialize_to_string_without_doctype() {
+        let document = document("<div><p>a</p></div>");
+        assert_eq!(document.serialize_to_string(), "<div><p>a</p></div>");
+        assert_eq!(DocumentData::new().serialize_to_string(), "");
+    }
+
+    #[test]
+    fn pretty_print_to_string_indents() {
+        let document = document("<!DOCTYPE html><div><p>a</p></div>");
+        assert_eq!(
+            document.pretty_print_to_string(4),
+            "<!DOCTYPE html>\n<div>\n    <p>\n        a\n    </p>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn position_in_parent_uses_identity() {
+        let list = root("<ul><li>a</li><li>b</li><li>a</li></ul>");
+        let items = list.children();
+        assert_eq!(items[0].position_in_parent(&list), Some(0));
+        assert_eq!(items[1].position_in_parent(&list), Some(1));
+        assert_eq!(items[2].position_in_parent(&list), Some(2));
+        // An equal node that isn't one of the children.
+        let copy = items[0].clone();
+        assert_eq!(copy.position_in_parent(&list), None);
+        assert_eq!(list.position_in_parent(&list), None);
+        assert_eq!(items[0].children()[0].position_in_parent(&list), None);
+    }
+
+    #[test]
+    fn apply_template_substitutes_placeholders() {
+        let vars = HashMap::from([("name", "Ada"), ("count", "3"), ("site", "roxy")]);
+        let template =
+            root("<div><h1>Hi {{name}}!</h1><p>{{ count }} new on <b>{{site}}</b>.</p></div>");
+        let result = template.apply_template(&vars, true).unwrap();
+        assert_eq!(
+            result.outer_html(),
+            "<div><h1>Hi Ada!</h1><p>3 new on <b>roxy</b>.</p></div>"
+        );
+        // The template itself is untouched and can be reused.
+        assert!(template.outer_html().contains("{{name}}"));
+        let vars = HashMap::from([("name", "Bo"), ("count", "0"), ("site", "x")]);
+        assert_eq!(
+            template.apply_template(&vars, true).unwrap().text_content(),
+            "Hi Bo!0 new on x."
+        );
+    }
+
+    #[test]
+    fn apply_template_with_missing_variables() {
+        let vars = HashMap::from([("a", "1")]);
+        let template = root("<p>{{a}} {{b}} {{a</p>");
+        assert_eq!(
+            template.apply_template(&vars, false).unwrap().outer_html(),
+            "<p>1 {{b}} {{a</p>"
+        );
+        assert_eq!(
+            template.apply_template(&vars, true),
+            Err(TemplateError {
+                variable: "b".into()
+            })
+        );
+    }
+
+    #[test]
+    fn compute_all_styles_inherits_down_the_tree() {
+        let document = document(
+            "<html><head><style>html { font-size: 20px; list-style-type: square; } \
+             .flat { flex-grow: 2; }</style></head>\
+             <body><div><ul><li><p class=\"flat\">deep</p></li></ul></div>\
+             <div style=\"display: none; font-size: 1.5em; list-style-type: decimal\">\
+             <p>hidden</p><p style=\"font-size: 50%\">small</p></div>\
+             </body></html>",
+        );
+        let styles = document.compute_all_styles();
+        let at = |path: &[usize]| &styles[&NodePath(path.to_vec())];
+        assert_eq!(at(&[]).font_size, 20.0);
+        assert_eq!(at(&[]).list_style_type, ListStyleType::Square);
+        let deep = at(&[1, 0, 0, 0, 0]);
+        assert_eq!(deep.font_size, 20.0);
+        assert_eq!(deep.list_style_type, ListStyleType::Square);
+        assert_eq!(deep.flex_grow, 2.0);
+        // Only inherited properties are handed down.
+        assert_eq!(at(&[1, 0, 0, 0]).flex_grow, 0.0);
+
+        // The `display: none` element and its children are still computed.
+        let hidden = at(&[1, 1]);
+        assert_eq!(hidden.font_size, 30.0);
+        assert_eq!(hidden.list_style_type, ListStyleType::Decimal);
+        assert_eq!(at(&[1, 1, 0]).font_size, 30.0);
+        assert_eq!(at(&[1, 1, 0]).list_style_type, ListStyleType::Decimal);
+        assert_eq!(at(&[1, 1, 1]).font_size, 15.0);
+        // Every element has an entry, text nodes don't.
+        let elements = document.root_node().unwrap().element_count();
+        assert_eq!(styles.len(), elements);
+        assert!(!styles.contains_key(&NodePath(vec![1, 0, 0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn count_by_tag_counts_every_element() {
+        let list = root("<ul><li><b>a</b></li><li>b</li><li><b>c</b><i>d</i></li></ul>");
+        let counts = list.count_by_tag();
+        let expected = HashMap::from([
+            ("ul".to_string(), 1),
+            ("li".to_string(), 3),
+            ("b".to_string(), 2),
+            ("i".to_string(), 1),
+        ]);
+        assert_eq!(counts, expected);
+        assert_eq!(list.most_common_tag(), Some(("li", 3)));
+    }
+
+    #[test]
+    fn most_common_tag_ties_go_to_the_first() {
+        let div = root("<div><p>a</p><span>b</span><span>c</span><p>d</p></div>");
+        assert_eq!(div.most_common_tag(), Some(("p", 2)));
+    }
+
+    #[test]
+    fn count_by_tag_without_elements() {
+        assert!(document_fragment(vec![]).count_by_tag().is_empty());
+        assert!(text("a".into()).count_by_tag().is_empty());
+        assert_eq!(document_fragment(vec![]).most_common_tag(), None);
+    }
+
+    /// A `<div>` holding `n` paragraphs, the first of them with class `hit`.
+    fn paragraphs(n: usize) -> Node {
+        let p = |class| {
+            element(
+                "p".into(),
+                AttrMap::from_iter([("class", class)]),
+                vec![text("x".into())],
+            )
+        };
+        let children = (0..n).map(|i| p(if i == 0 { "hit" } else { "miss" }));
+        element("div".into(), AttrMap::default(), children.collect())
+    }
+
+    #[test]
+    fn find_matching_stops_at_the_first_match() {
+        let tree = paragraphs(1000);
+        let selectors = css::parse_selector_list(".hit").unwrap();
+        let mut matches = matcher(&selectors);
+        let mut visited = 0;
+        let mut counting = |node: &Node, ancestors: &[&Node]| {
+            visited += 1;
+            matches(node, ancestors)
+        };
+        let found = tree.find_matching(&mut vec![], &mut counting, false);
+        assert_eq!(found, Some(&tree.children()[0]));
+        assert_eq!(visited, 2);
+
+        let mut visited = 0;
+        let found = tree.find_matching(
+            &mut vec![],
+            &mut |node: &Node, _: &[&Node]| {
+                visited += 1;
+                node.attr("class") == Some("hit")
+            },
+            true,
+        );
+        assert_eq!(found, Some(&tree.children()[0]));
+        assert_eq!(visited, tree.size() - 1);
+    }
+
+    #[test]
+    fn select_first_and_last() {
+        let div = root(
+            "<div><p id=\"a\">1</p><section><p id=\"b\">2</p>\
+             <p id=\"c\">3</p></section><span id=\"d\">4</span></div>",
+        );
+        assert_eq!(div.select_first("p").unwrap().attr("id"), Some("a"));
+        assert_eq!(div.select_last("p").unwrap().attr("id"), Some("c"));
+        assert_eq!(div.select_first("section p").unwrap().attr("id"), Some("b"));
+        assert_eq!(div.select_last("p, span").unwrap().attr("id"), Some("d"));
+        assert_eq!(div.select_first("div"), Some(&div));
+        assert_eq!(div.select_last("div"), Some(&div));
+        assert_eq!(div.select_first("table"), None);
+        assert_eq!(div.select_last("table"), None);
+        assert_eq!(div.select_first("p["), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn height_and_size() {
+        let leaf = text("a".into());
+        assert_eq!(leaf.height(), 0);
+        assert_eq!(leaf.size(), 1);
+        assert_eq!(element("br".into(), AttrMap::default(), vec![]).height(), 0);
+
+        let single_level = root("<ul><li></li><li></li><li></li></ul>");
+        assert_eq!(single_level.height(), 1);
+        assert_eq!(single_level.size(), 4);
+
+        let nested = root("<div><p>a<b>b</b></p><!-- c --></div>");
+        // div, p, "a", b, "b", comment
+        assert_eq!(nested.size(), 6);
+        assert_eq!(nested.size(), nested.iter().count());
+        assert_eq!(nested.height(), 3);
+        assert_eq!(nested.node_count(), nested.size());
+        assert_eq!(nested.max_depth(), nested.height() + 1);
+    }
+
+    #[test]
+    fn all_element_paths_ends_at_each_leaf() {
+        let tree = root("<div><p>a<b>b</b></p><ul><li></li><li>c</li></ul></div>");
+        let paths = tree.all_element_paths();
+        let leaves = tree.iter().filter(|n| n.children().is_empty()).count();
+        assert_eq!(paths.len(), leaves);
+        assert_eq!(paths.len(), 4);
+        for path in &paths {
+            assert!(std::ptr::eq(path[0], &tree));
+            assert!(path.last().unwrap().children().is_empty());
+            for pair in path.windows(2) {
+                assert!(pair[1].position_in_parent(pair[0]).is_some());
+            }
+        }
+        let describe = |path: &Vec<&Node>| {
+            path.iter()
+                .map(|n| n.tag_name().map_or_else(|| n.text_content(), String::from))
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+        let described: Vec<String> = paths.iter().map(describe).collect();
+        assert_eq!(
+            described,
+            ["div/p/a", "div/p/b/b", "div/ul/li", "div/ul/li/c"]
+        );
+    }
+
+    #[test]
+    fn all_element_paths_of_a_leaf() {
+        let leaf = text("a".into());
+        assert_eq!(leaf.all_element_paths(), vec![vec![&leaf]]);
+    }
+
+    #[test]
+    fn reload_replaces_root_and_stylesheets() {
+        let mut document = document(
+            "<!DOCTYPE html><html><head><style>p { order: 1; }</style>\
+             <style>a { order: 2; }</style></head><body><p>old</p></body></html>",
+        );
+        assert_eq!(document.stylesheets.len(), 2);
+        document
+            .reload(
+                "<html><head><style>b { order: 3; }</style></head><body><b>new</b></body></html>",
+            )
+            .unwrap();
+        assert_eq!(document.stylesheets.len(), 1);
+        assert_eq!(document.doctype, None);
+        let root = document.root_node().unwrap();
+        assert_eq!(root.select_first("body").unwrap().text_content(), "new");
+        assert!(root.select_first("p").is_none());
+    }
+
+    #[test]
+    fn reload_keeps_the_document_on_error() {
+        let mut document = document("<html><head><style>p { order: 1; }</style></head></html>");
+        let before = document.clone();
+        assert!(document.reload("<div><p>unclosed</div>").is_err());
+        assert_eq!(document, before);
+    }
+
+    #[test]
+    fn all_comments_in_document_order() {
+        let tree = root(
+            "<div><!--one--><p>text<!--two--><b><!--three--></b></p>\
+             <!--four--></div>",
+        );
+        let comments: Vec<&Node> = tree.all_comments();
+        let contents: Vec<&str> = comments
+            .iter()
+            .map(|node| match &node.node_type {
+                NodeType::Comment(content) => content.as_str(),
+                _ => panic!("expected a comment"),
+            })
+            .collect();
+        assert_eq!(contents, ["one", "two", "three", "four"]);
+        assert!(root("<p>no comments</p>").all_comments().is_empty());
+    }
+
+    #[test]
+    fn strip_comments_removes_every_comment() {
+        let tree = root("<div><!--one--><p>a<!--two--><b><!--three--></b></p></div>");
+        assert_eq!(
+            tree.strip_comments().outer_html(),
+            "<div><p>a<b></b></p></div>"
+        );
+        let clean = root("<div><p>a<b>b</b></p></div>");
+        assert_eq!(clean.strip_comments(), clean);
+        assert_eq!(
+            comment("x".into()).strip_comments(),
+            document_fragment(vec![])
+        );
+    }
+
+    fn field(name: &str, field_type: &str, value: Option<&str>, is_required: bool) -> FieldInfo {
+        FieldInfo {
+            name: Some(name.into()),
+            field_type: field_type.into(),
+            value: value.map(String::from),
+            is_required,
+        }
+    }
+
+    #[test]
+    fn forms_of_a_login_page() {
+        let document = document(
+            "<body><form action=\"/login\" method=\"POST\">\
+             <label>User <input name=\"user\"></label>\
+             <input type=\"Password\" name=\"pass\" required>\
+             <input type=\"submit\" name=\"go\" value=\"Log in\">\
+             </form></body>",
+        );
+        assert_eq!(
+            document.forms(),
+            vec![FormInfo {
+                action: Some("/login".into()),
+                method: "post".into(),
+                fields: vec![
+                    field("user", "text", None, false),
+                    field("pass", "password", None, true),
+                    field("go", "submit", Some("Log in"), false),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn forms_with_textarea_and_select() {
+        let document = document(
+            "<body><form><textarea name=\"bio\" required>hi</textarea>\
+             <select name=\"size\"><option>S</option><option selected>M</option></select>\
+             <select name=\"color\"><option value=\"r\" selected>Red</option></select>\
+             </form><form action=\"/search\"><input name=\"q\"></form></body>",
+        );
+        let forms = document.forms();
+        assert_eq!(forms.len(), 2);
+        assert_eq!(forms[0].action, None);
+        assert_eq!(forms[0].method, "get");
+        assert_eq!(
+            forms[0].fields,
+            [
+                field("bio", "textarea", Some("hi"), true),
+                field("size", "select", Some("M"), false),
+                field("color", "select", Some("r"), false),
+            ]
+        );
+        assert_eq!(forms[1].action.as_deref(), Some("/search"));
+        assert_eq!(forms[1].fields, [field("q", "text", None, false)]);
+        assert!(self::document("<p>no forms</p>").forms().is_empty());
+    }
+
+    #[test]
+    fn image_extractors() {
+        let tree = root(
+            "<div><img src=\"a.png\" loading=\"lazy\">\
+             <picture><source src=\"b.webp\"><source srcset=\"c.avif\">\
+             <img src=\"b.png\" loading=\"LAZY\"></picture>\
+             <img alt=\"no src\" loading=\"eager\"></div>",
+        );
+        assert_eq!(tree.image_srcs(), ["a.png", "b.webp", "b.png"]);
+        assert_eq!(tree.image_count(), 3);
+        let lazy: Vec<_> = tree
+            .lazy_images()
+            .iter()
+            .map(|img| img.attr("src"))
+            .collect();
+        assert_eq!(lazy, [Some("a.png"), Some("b.png")]);
+    }
+
+    #[test]
+    fn image_extractors_without_images() {
+        let tree = root("<div><p>text</p></div>");
+        assert!(tree.image_srcs().is_empty());
+        assert_eq!(tree.image_count(), 0);
+        assert!(tree.lazy_images().is_empty());
+    }
+
+    #[test]
+    fn diff_attributes_finds_each_kind_of_change() {
+        let before = root("<input id=\"a\" name=\"q\" disabled>");
+        let after = root("<input id=\"b\" name=\"q\" type=\"search\">");
+        assert_eq!(
+            before.diff_attributes(&after),
+            [
+                AttrDiff::Removed("disabled".into()),
+                AttrDiff::Changed(
+                    "id".into(),
+                    AttrValue::Text("a".into()),
+                    AttrValue::Text("b".into())
+                ),
+                AttrDiff::Added("type".into(), AttrValue::Text("search".into())),
+            ]
+        );
+        assert_eq!(
+            after.diff_attributes(&root("<input>")).len(),
+            after.attributes().unwrap().0.len()
+        );
+    }
+
+    #[test]
+    fn diff_attributes_without_changes() {
+        let node = root("<p class=\"x\" hidden>a</p>");
+        assert_eq!(node.diff_attributes(&node.clone()), []);
+        assert_eq!(
+            root("<p hidden></p>").diff_attributes(&root("<p hidden=\"\"></p>")),
+            [AttrDiff::Changed(
+                "hidden".into(),
+                AttrValue::Implicit,
+                AttrValue::Text("".into())
+            )]
+        );
+        assert_eq!(text("a".into()).diff_attributes(&comment("b".into())), []);
+        assert_eq!(
+            text("a".into()).diff_attributes(&root("<p id=\"x\"></p>")),
+            [AttrDiff::Added("id".into(), AttrValue::Text("x".into()))]
+        );
+    }
+
+    #[test]
+    fn add_stylesheet_from_link_elements_uses_the_loader() {
+        let mut document = document(
+            "<html><head><style>a { order: 0; }</style>\
+             <link rel=\"stylesheet\" href=\"base.css\">\
+             <link rel=\"icon\" href=\"favicon.ico\">\
+             <link rel=\"Alternate StyleSheet\" href=\"theme.css\">\
+             <link rel=\"stylesheet\" href=\"missing.css\">\
+             <link rel=\"stylesheet\" href=\"broken.css\">\
+             <link rel=\"stylesheet\">\
+             </head><body></body></html>",
+        );
+        let requested = std::cell::RefCell::new(vec![]);
+        document.add_stylesheet_from_link_elements(|href| {
+            requested.borrow_mut().push(href.to_string());
+            match href {
+                "base.css" => Some("p { order: 1; }".into()),
+                "theme.css" => Some("p { order: 2; } b { order: 3; }".into()),
+                "broken.css" => Some("p { order: ".into()),
+                _ => None,
+            }
+        });
+        assert_eq!(
+            requested.into_inner(),
+            ["base.css", "theme.css", "missing.css", "broken.css"]
+        );
+        let sheets: Vec<String> = document
+            .stylesheets
+            .iter()
+            .map(css::StyleSheet::to_minified_string)
+            .collect();
+        assert_eq!(sheets, ["a{order:0}", "p{order:1}", "p{order:2}b{order:3}"]);
+    }
+
+    fn toc(level: u8, text: &str, id: Option<&str>) -> TocEntry {
+        TocEntry {
+            level,
+            text: text.into(),
+            id: id.map(String::from),
+        }
+    }
+
+    #[test]
+    fn table_of_contents_in_document_order() {
+        let page = root(
+            "<article><h1 id=\"top\">Guide</h1><p>intro</p>\
+             <section><h2 id=\"setup\">Setting\n   <em>up</em></h2>\
+             <h3>Details</h3></section><h2>Usage</h2></article>",
+        );
+        assert_eq!(
+            page.table_of_contents(6),
+            [
+                toc(1, "Guide", Some("top")),
+                toc(2, "Setting up", Some("setup")),
+                toc(3, "Details", None),
+                toc(2, "Usage", None),
+            ]
+        );
+        assert_eq!(
+            page.table_of_contents(2),
+            [
+                toc(1, "Guide", Some("top")),
+                toc(2, "Setting up", Some("setup")),
+                toc(2, "Usage", None),
+            ]
+        );
+        assert_eq!(page.table_of_contents(0), []);
+    }
+
+    #[test]
+    fn table_of_contents_without_headings() {
+        assert_eq!(
+            root("<div><p>a</p><h7>b</h7></div>").table_of_contents(6),
+            []
+        );
+    }
+
+    fn properties(entries: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(name, values)| {
+                let values = values.iter().map(|v| v.to_string()).collect();
+                (name.to_string(), values)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn microdata_person() {
+        let page = root(
+            "<div itemscope itemtype=\"https://schema.org/Person\">\
+             <span itemprop=\"name\">Jane Doe</span>\
+             <a itemprop=\"email\" href=\"mailto:jane@example.com\">email</a>\
+             <a itemprop=\"url sameAs\" href=\"https://jane.example\">site</a>\
+             <img itemprop=\"image\" src=\"jane.jpg\">\
+             <meta itemprop=\"jobTitle\" content=\"Engineer\">\
+             <p><span itemprop=\"name\">J. Doe</span></p></div>",
+        );
+        assert_eq!(
+            page.microdata(),
+            [MicrodataItem {
+                item_type: Some("https://schema.org/Person".into()),
+                properties: properties(&[
+                    ("name", &["Jane Doe", "J. Doe"]),
+                    ("email", &["mailto:jane@example.com"]),
+                    ("url", &["https://jane.example"]),
+                    ("sameAs", &["https://jane.example"]),
+                    ("image", &["jane.jpg"]),
+                    ("jobTitle", &["Engineer"]),
+                ]),
+            }]
+        );
+    }
+
+    #[test]
+    fn microdata_nested_items() {
+        let page = root(
+            "<div itemscope itemtype=\"https://schema.org/Book\">\
+             <span itemprop=\"name\">Roxy</span>\
+             <div itemprop=\"author\" itemscope>\
+             <span itemprop=\"name\">Ada</span></div>\
+             <time itemprop=\"datePublished\" datetime=\"2024-01-01\">New Year</time></div>",
+        );
+        assert_eq!(
+            page.microdata(),
+            [
+                MicrodataItem {
+                    item_type: Some("https://schema.org/Book".into()),
+                    properties: properties(&[
+                        ("name", &["Roxy"]),
+                        ("datePublished", &["2024-01-01"]),
+                    ]),
+                },
+                MicrodataItem {
+                    item_type: None,
+                    properties: properties(&[("name", &["Ada"])]),
+                },
+            ]
+        );
+        assert_eq!(root("<p itemprop=\"name\">x</p>").microdata(), []);
+    }
+
+    #[test]
+    fn checksum_of_equal_trees() {
+        let html = "<div id=\"a\"><p>text<!--note--></p><br></div>";
+        assert_eq!(root(html).compute_checksum(), root(html).compute_checksum());
+        assert_eq!(
+            root("<p id=\"a\" class=\"b\" hidden>x</p>").compute_checksum(),
+            root("<p hidden class=\"b\" id=\"a\">x</p>").compute_checksum()
+        );
+        // A fixed hash function, so checksums can be stored between runs.
+        assert_eq!(text("a".into()).compute_checksum(), 18403204921753403128);
+    }
+
+    #[test]
+    fn checksum_changes_with_content() {
+        let base = root("<div id=\"a\"><p>text<!--note--></p><br></div>").compute_checksum();
+        for changed in [
+            "<div id=\"a\"><p>test<!--note--></p><br></div>",
+            "<div id=\"b\"><p>text<!--note--></p><br></div>",
+            "<div id><p>text<!--note--></p><br></div>",
+            "<div><p>text<!--note--></p><br></div>",
+            "<section id=\"a\"><p>text<!--note--></p><br></section>",
+            "<div id=\"a\"><p>text<!--notes--></p><br></div>",
+            "<div id=\"a\"><p>text</p><br></div>",
+            "<div id=\"a\"><br><p>text<!--note--></p></div>",
+            "<div id=\"a\"><p>text<!--note--><br></p></div>",
+        ] {
+            assert_ne!(root(changed).compute_checksum(), base, "{}", changed);
+        }
+        assert_ne!(
+            text("note".into()).compute_checksum(),
+            comment("note".into()).compute_checksum()
+        );
+    }
+
+    #[test]
+    fn json_ld_blocks() {
+        let doc = document(
+            "<html><head>\
+             <script type=\"application/ld+json\">{\"@type\": \"Article\", \"name\": \"One\"}</script>\
+             <script type=\" Application/LD+JSON \">[1, 2]</script>\
+             <script type=\"application/ld+json\">{\"broken\": }</script>\
+             <script type=\"application/json\">{\"other\": true}</script>\
+             <script>var x = 1;</script>\
+             </head><body></body></html>",
+        );
+        let mut errors = vec![];
+        let blocks = doc.json_ld_with_errors(&mut errors);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            blocks[0].get("@type").and_then(JsonValue::as_str),
+            Some("Article")
+        );
+        assert_eq!(
+            blocks[0].get("name").and_then(JsonValue::as_str),
+            Some("One")
+        );
+        assert_eq!(
+            blocks[1],
+            JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Number(2.0)])
+        );
+        assert_eq!(
+            errors,
+            vec![JsonError::UnexpectedChar {
+                pos: 11,
+                found: '}'
+            }]
+        );
+        assert_eq!(doc.json_ld(), blocks);
+
+        let hostile = document(&format!(
+            "<html><head><script type=\"application/ld+json\">{}</script>\
+             <script type=\"application/ld+json\">{{}}</script></head></html>",
+            "[".repeat(100_000)
+        ));
+        let mut errors = vec![];
+        assert_eq!(
+            hostile.json_ld_with_errors(&mut errors),
+            vec![JsonValue::Object(vec![])]
+        );
+        assert!(matches!(errors[..], [JsonError::TooDeep { .. }]));
+        assert!(document("<html><body><p>none</p></body></html>")
+            .json_ld()
+            .is_empty());
+    }
+
+    #[test]
+    fn data_attributes_are_keyed_like_the_dataset() {
+        let node = element(
+            "div".into(),
+            [
+                ("data-user-id", "5"),
+                ("data-role", "admin"),
+                ("data-x-", "trailing"),
+                ("id", "main"),
+                ("class", "card"),
+            ]
+            .into_iter()
+            .collect(),
+            vec![],
+        );
+        let data = node.data_attributes().unwrap();
+        assert_eq!(data.len(), 3);
+        assert_eq!(data.get("userId").map(String::as_str), Some("5"));
+        assert_eq!(data.get("role").map(String::as_str), Some("admin"));
+        assert_eq!(data.get("x-").map(String::as_str), Some("trailing"));
+        assert!(!data.contains_key("id"));
+
+        let plain = element("p".into(), [("title", "t")].into_iter().collect(), vec![]);
+        assert_eq!(plain.data_attributes(), Some(HashMap::new()));
+        assert_eq!(text("data-a".into()).data_attributes(), None);
+    }
+
+    #[test]
+    fn data_attributes_can_be_set_and_removed() {
+        let mut node = element("div".into(), AttrMap::default(), vec![]);
+        assert!(!node.has_data_attribute("userId"));
+        node.set_data_attribute("userId", "5").unwrap();
+        assert_eq!(node.attr("data-user-id"), Some("5"));
+        assert!(node.has_data_attribute("userId"));
+        assert_eq!(
+            node.data_attributes()
+                .unwrap()
+                .get("userId")
+                .map(String::as_str),
+            Some("5")
+        );
+
+        node.set_data_attribute("userId", "6").unwrap();
+        node.set_data_attribute("role", "admin").unwrap();
+        assert_eq!(node.attr("data-user-id"), Some("6"));
+        assert_eq!(node.attr("data-role"), Some("admin"));
+
+        assert_eq!(node.remove_data_attribute("userId"), Ok(true));
+        assert_eq!(node.remove_data_attribute("userId"), Ok(false));
+        assert!(!node.has_data_attribute("userId"));
+        assert_eq!(node.attr("data-user-id"), None);
+        assert!(node.has_data_attribute("role"));
+
+        let mut text_node = text("t".into());
+        assert_eq!(
+            text_node.set_data_attribute("userId", "5"),
+            Err(DomError::NotAnElement)
+        );
+        assert_eq!(
+            text_node.remove_data_attribute("userId"),
+            Err(DomError::NotAnElement)
+        );
+        assert!(!text_node.has_data_attribute("userId"));
+    }
+
+    #[test]
+    fn structured_headings_nest_by_rank() {
+        fn heading(level: u8, text: &str, children: Vec<HeadingNode>) -> HeadingNode {
+            HeadingNode {
+                level,
+                text: text.into(),
+                id: None,
+                children,
+            }
+        }
+        let outline = document(
+            "<html><body>\
+             <h1 id=\"top\">Guide</h1>\
+             <h2>Install</h2><h3>Linux</h3><h3>macOS</h3>\
+             <h2>Usage</h2><p>text</p>\
+             <h1>Appendix</h1>\
+             </body></html>",
+        )
+        .structured_headings();
+        assert_eq!(
+            outline,
+            vec![
+                HeadingNode {
+                    id: Some("top".into()),
+                    ..heading(
+                        1,
+                        "Guide",
+                        vec![
+                            heading(
+                                2,
+                                "Install",
+                                vec![heading(3, "Linux", vec![]), heading(3, "macOS", vec![])]
+                            ),
+                            heading(2, "Usage", vec![]),
+                        ]
+                    )
+                },
+                heading(1, "Appendix", vec![]),
+            ]
+        );
+
+        let skipped =
+            document("<html><body><h1>A</h1><h3>B</h3><h2>C</h2><h4>D</h4></body></html>")
+                .structured_headings();
+        assert_eq!(
+            skipped,
+            vec![heading(
+                1,
+                "A",
+                vec![
+                    heading(3, "B", vec![]),
+                    heading(2, "C", vec![heading(4, "D", vec![])]),
+                ]
+            )]
+        );
+
+        let no_h1 = document("<html><body><h2>A</h2><h3>B</h3><h2>C</h2></body></html>")
+            .structured_headings();
+        assert_eq!(
+            no_h1,
+            vec![
+                heading(2, "A", vec![heading(3, "B", vec![])]),
+                heading(2, "C", vec![]),
+            ]
+        );
+
+        assert!(document("<html><body><p>none</p></body></html>")
+            .structured_headings()
+            .is_empty());
+        assert!(DocumentData::new().structured_headings().is_empty());
+    }
+
+    #[test]
+    fn page_sections_are_the_outermost_sectioning_elements() {
+        let page = root(
+            "<html><head><title>t</title></head><body>\
+             <header><nav>top</nav></header>\
+             <div class=\"wrap\"><main><article><section>s</section></article></main>\
+             <aside>side</aside></div>\
+             <footer>f</footer>\
+             </body></html>",
+        );
+        let tags: Vec<&str> = page
+            .page_sections()
+            .iter()
+            .filter_map(|section| section.tag_name())
+            .collect();
+        assert_eq!(tags, ["header", "main", "aside", "footer"]);
+        assert!(page.has_semantic_structure());
+
+        let divs = root(
+            "<html><body><div id=\"header\">h</div><div id=\"main\">m</div>\
+             <div id=\"footer\">f</div></body></html>",
+        );
+        assert!(divs.page_sections().is_empty());
+        assert!(!divs.has_semantic_structure());
+
+        // The same element used many times counts once.
+        let sections = root(
+            "<html><body><section>a</section><section>b</section>\
+             <article>c</article></body></html>",
+        );
+        assert_eq!(sections.page_sections().len(), 3);
+        assert!(!sections.has_semantic_structure());
     }
 }
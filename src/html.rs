@@ -1,17 +1,85 @@
-#![allow(dead_code)]
-use std::collections::HashMap;
+use core::fmt;
+use std::{collections::HashMap, ops::Range, sync::Arc};
 
-use crate::dom::{comment, element, text, AttrMap, AttrValue, DocumentData, Node, NodeType};
+use crate::dom::{
+    cdata, comment, document_fragment, element, processing_instruction, text, AttrMap, AttrValue,
+    DocumentData, Node, NodePath, NodeType,
+};
+
+/// Elements that cannot have any child nodes, e.g. `<br>` or `<link>`.
+pub const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// How far past a `&` to look for the `;` ending a character reference. Longer
+/// than any reference this decodes, and keeps decoding linear in the input.
+const MAX_REFERENCE_LEN: usize = 32;
+
+/// Replaces character references such as `&amp;` or `&#x27;` with the characters
+/// they stand for. Unknown references are kept as written.
+pub fn decode_entities(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.bytes().take(MAX_REFERENCE_LEN).position(|b| b == b';');
+        let decoded = end.and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                name => {
+                    let code = match name.strip_prefix('#')? {
+                        hex if hex.starts_with(['x', 'X']) => {
+                            let digits = &hex[1..];
+                            if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                                return None;
+                            }
+                            u32::from_str_radix(digits, 16)
+                        }
+                        decimal if decimal.bytes().all(|b| b.is_ascii_digit()) => decimal.parse(),
+                        _ => return None,
+                    };
+                    char::from_u32(code.ok()?)?
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
 
 struct Parser<'a> {
     pos: usize,
     input: String,
     context: &'a mut DocumentData,
+    /// Child indices down to the node being parsed, from the top-level nodes.
+    path: Vec<usize>,
+    /// Where each node parsed so far starts and ends in `input`, if recorded.
+    spans: Option<HashMap<NodePath, Range<usize>>>,
 }
 
+type ParseResult<T> = Result<T, ParseError>;
+
 impl Parser<'_> {
-    fn next_char(&self) -> char {
-        self.input[self.pos..].chars().next().unwrap()
+    fn next_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
     }
 
     fn starts_with(&self, s: &str) -> bool {
@@ -22,12 +90,12 @@ impl Parser<'_> {
         self.pos >= self.input.len()
     }
 
-    fn consume_char(&mut self) -> char {
+    fn consume_char(&mut self) -> ParseResult<char> {
         let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
+        let (_, cur_char) = iter.next().ok_or(ParseError::UnexpectedEof)?;
+        let (next_pos, _) = iter.next().unwrap_or((cur_char.len_utf8(), ' '));
         self.pos += next_pos;
-        cur_char
+        Ok(cur_char)
     }
 
     fn consume_while<F>(&mut self, test: F) -> String
@@ -35,8 +103,9 @@ impl Parser<'_> {
         F: Fn(char) -> bool,
     {
         let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
+        while let Some(c) = self.next_char().filter(|c| test(*c)) {
+            result.push(c);
+            self.pos += c.len_utf8();
         }
         result
     }
@@ -45,139 +114,857 @@ impl Parser<'_> {
         self.consume_while(|c| c.is_whitespace());
     }
 
+    fn unexpected(&self) -> ParseError {
+        match self.next_char() {
+            Some(found) => ParseError::UnexpectedChar {
+                pos: self.pos,
+                found,
+            },
+            None => ParseError::UnexpectedEof,
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> ParseResult<()> {
+        for expected in s.chars() {
+            if self.next_char() != Some(expected) {
+                return Err(self.unexpected());
+            }
+            self.consume_char()?;
+        }
+        Ok(())
+    }
+
     fn parse_tag_name(&mut self) -> String {
         self.consume_while(|c| c.is_ascii_alphanumeric())
     }
 
-    fn parse_node(&mut self) -> Node {
+    fn parse_node(&mut self) -> ParseResult<Node> {
         if self.starts_with("<!--") {
             return self.parse_comment();
         }
+        if self.starts_with("<?") {
+            return self.parse_processing_instruction();
+        }
+        if self.starts_with("<![CDATA[") {
+            return self.parse_cdata_section();
+        }
 
         match self.next_char() {
-            '<' => self.parse_element(),
-            _ => self.parse_text(),
+            Some('<') => self.parse_element(),
+            _ => Ok(self.parse_text()),
         }
     }
 
     fn parse_text(&mut self) -> Node {
-        text(self.consume_while(|c| c != '<'))
+        text(decode_entities(&self.consume_while(|c| c != '<')))
     }
 
-    fn parse_element(&mut self) -> Node {
+    fn parse_element(&mut self) -> ParseResult<Node> {
         // Opening tag.
-        assert!(self.consume_char() == '<');
+        self.expect("<")?;
         let tag_name = self.parse_tag_name();
-        let attrs = self.parse_attributes();
+        if tag_name.is_empty() {
+            return Err(self.unexpected());
+        }
+        let attrs = self.parse_attributes()?;
 
         // Optional self-closing
-        let next_char = self.consume_char();
-        if next_char == '/' {
-            assert!(self.consume_char() == '>');
-            return element(tag_name, attrs, vec![]);
+        if self.starts_with("/") {
+            self.expect("/>")?;
+            return Ok(element(tag_name, attrs, vec![]));
+        }
+        self.expect(">")?;
+
+        // Void elements never have contents or a closing tag.
+        if VOID_ELEMENTS.contains(&tag_name.as_str()) {
+            return Ok(element(tag_name, attrs, vec![]));
         }
-        assert!(next_char == '>');
 
         // Contents.
-        let children = self.parse_nodes();
+        let children = self.parse_nodes()?;
 
         if tag_name == "style" {
-            let inner_node = children.first().unwrap();
-            if let NodeType::Text(styling) = &inner_node.node_type {
-                self.context.load_css(styling.clone());
+            if let Some(NodeType::Text(styling)) = children.first().map(|n| &n.node_type) {
+                // Like browsers, drop stylesheets that fail to parse.
+                _ = self.context.load_css(styling.clone());
             }
         }
 
         // Closing tag.
-        assert!(self.consume_char() == '<');
-        assert!(self.consume_char() == '/');
-        assert!(self.parse_tag_name() == tag_name);
-        assert!(self.consume_char() == '>');
+        self.expect("</")?;
+        let start = self.pos;
+        if self.parse_tag_name() != tag_name {
+            return Err(ParseError::MismatchedClosingTag {
+                pos: start,
+                expected: tag_name,
+            });
+        }
+        self.expect(">")?;
 
-        element(tag_name, attrs, children)
+        Ok(element(tag_name, attrs, children))
     }
 
-    fn parse_comment(&mut self) -> Node {
-        assert!(self.starts_with("<!--"));
-        assert!(self.consume_char() == '<');
-        assert!(self.consume_char() == '!');
-        assert!(self.consume_char() == '-');
-        assert!(self.consume_char() == '-');
+    fn parse_comment(&mut self) -> ParseResult<Node> {
+        self.expect("<!--")?;
         let mut result = String::new();
-        loop {
-            let c = self.consume_char();
-            if c == '-' && self.starts_with("->") {
-                break;
-            }
-            result.push(c);
+        while !self.starts_with("-->") {
+            result.push(self.consume_char()?);
+        }
+        self.expect("-->")?;
+        Ok(comment(result))
+    }
+
+    /// The content is kept as written, without decoding character references.
+    fn parse_cdata_section(&mut self) -> ParseResult<Node> {
+        self.expect("<![CDATA[")?;
+        let mut content = String::new();
+        while !self.starts_with("]]>") {
+            content.push(self.consume_char()?);
         }
-        assert!(self.consume_char() == '-');
-        assert!(self.consume_char() == '>');
-        comment(result)
+        self.expect("]]>")?;
+        Ok(cdata(content))
     }
 
-    fn parse_attributes(&mut self) -> AttrMap {
+    fn parse_processing_instruction(&mut self) -> ParseResult<Node> {
+        self.expect("<?")?;
+        let target = self.parse_attr_name();
+        self.consume_whitespace();
+        let mut data = String::new();
+        while !self.starts_with("?>") {
+            data.push(self.consume_char()?);
+        }
+        self.expect("?>")?;
+        Ok(processing_instruction(target, data.trim_end().to_string()))
+    }
+
+    /// Attributes up to the end of the opening tag, or the end of input.
+    fn parse_attributes(&mut self) -> ParseResult<AttrMap> {
         let mut attributes = HashMap::new();
         loop {
             self.consume_whitespace();
 
-            let next_char = self.next_char();
-            if next_char == '>' || next_char == '/' {
+            if matches!(self.next_char(), None | Some('>' | '/')) {
                 break;
             }
 
-            let (name, value) = self.parse_attr();
-            if value.is_empty() {
-                attributes.insert(name, AttrValue::Text(value));
-            } else {
-                attributes.insert(name, AttrValue::Implicit);
-            }
+            let (name, value) = self.parse_attr()?;
+            match value {
+                Some(value) => attributes.insert(name, AttrValue::Text(value)),
+                None => attributes.insert(name, AttrValue::Implicit),
+            };
         }
-        AttrMap(attributes)
+        Ok(AttrMap(attributes))
+    }
+
+    fn parse_attr_name(&mut self) -> String {
+        self.consume_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
     }
 
-    fn parse_attr_value(&mut self) -> String {
-        let open_quote = self.consume_char();
-        assert!(open_quote == '"' || open_quote == '\'');
+    fn parse_attr_value(&mut self) -> ParseResult<String> {
+        let open_quote = match self.next_char() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.unexpected()),
+        };
+        self.consume_char()?;
         let value = self.consume_while(|c| c != open_quote);
-        assert!(self.consume_char() == open_quote);
-        value
+        self.expect(&open_quote.to_string())?;
+        Ok(decode_entities(&value))
     }
 
-    fn parse_attr(&mut self) -> (String, String) {
-        let name = self.parse_tag_name();
-        if self.consume_char() == '=' {
-            let value = self.parse_attr_value();
-            return (name, value);
+    fn parse_attr(&mut self) -> ParseResult<(String, Option<String>)> {
+        let name = self.parse_attr_name();
+        if name.is_empty() {
+            return Err(self.unexpected());
+        }
+        if self.next_char() == Some('=') {
+            self.consume_char()?;
+            let value = self.parse_attr_value()?;
+            return Ok((name, Some(value)));
         }
 
-        (name, String::new())
+        Ok((name, None))
     }
 
-    fn parse_nodes(&mut self) -> Vec<Node> {
+    fn parse_nodes(&mut self) -> ParseResult<Vec<Node>> {
         let mut nodes = Vec::new();
         loop {
             self.consume_whitespace();
             if self.eof() || self.starts_with("</") {
                 break;
             }
-            nodes.push(self.parse_node());
+            self.path.push(nodes.len());
+            let start = self.pos;
+            let node = self.parse_node()?;
+            if let Some(spans) = &mut self.spans {
+                spans.insert(NodePath(self.path.clone()), start..self.pos);
+            }
+            self.path.pop();
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+
+    /// A leading `<!DOCTYPE ...>`, recorded on the document.
+    fn parse_doctype(&mut self) -> ParseResult<()> {
+        self.consume_whitespace();
+        let is_doctype = self.input[self.pos..]
+            .get(.."<!doctype".len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("<!doctype"));
+        if !is_doctype {
+            return Ok(());
+        }
+        self.pos += "<!doctype".len();
+        let doctype = self.consume_while(|c| c != '>');
+        self.expect(">")?;
+        self.context.doctype = Some(doctype.trim().to_string());
+        Ok(())
+    }
+
+    /// A whole document. Several top-level nodes are wrapped in `<html>`.
+    fn parse_root(&mut self) -> ParseResult<Node> {
+        self.parse_doctype()?;
+        let mut nodes = self.parse_to_end()?;
+        if nodes.len() == 1 {
+            // The only top-level node becomes the root, with the empty path.
+            if let Some(spans) = &mut self.spans {
+                *spans = std::mem::take(spans)
+                    .into_iter()
+                    .map(|(path, span)| (NodePath(path.0[1..].to_vec()), span))
+                    .collect();
+            }
+            Ok(nodes.swap_remove(0))
+        } else {
+            Ok(element("html".into(), AttrMap::default(), nodes))
+        }
+    }
+
+    /// Parses nodes up to the end of input, which must not contain a stray closing tag.
+    fn parse_to_end(&mut self) -> ParseResult<Vec<Node>> {
+        let nodes = self.parse_nodes()?;
+        if !self.eof() {
+            return Err(self.unexpected());
+        }
+        Ok(nodes)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    UnexpectedEof,
+    UnexpectedChar { pos: usize, found: char },
+    MismatchedClosingTag { pos: usize, expected: String },
+    Encoding(EncodingError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::UnexpectedEof => write!(f, "unexpected end of input"),
+            Self::UnexpectedChar { pos, found } => {
+                write!(f, "unexpected character {:?} at {}", found, pos)
+            }
+            Self::MismatchedClosingTag { pos, expected } => {
+                write!(f, "expected closing tag </{}> at {}", expected, pos)
+            }
+            Self::Encoding(err) => write!(f, "{}", err),
         }
-        nodes
     }
 }
 
-pub fn parse(input: String, context: &mut DocumentData) -> Node {
+impl std::error::Error for ParseError {}
+
+impl From<EncodingError> for ParseError {
+    fn from(err: EncodingError) -> Self {
+        Self::Encoding(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodingError {
+    /// An encoding label that isn't supported, e.g. `shift_jis`.
+    Unsupported(String),
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::Unsupported(label) => write!(f, "unsupported encoding \"{}\"", label),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+/// A character encoding documents can be decoded from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// Also used for `ISO-8859-1` and `US-ASCII`, as browsers do.
+    Windows1252,
+}
+
+/// What bytes 0x80 to 0x9F stand for in Windows-1252. Undefined bytes map to
+/// the C1 control of the same value, like in Latin-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+impl Encoding {
+    /// The encoding a `charset` label such as `utf-8` or `ISO-8859-1` names.
+    pub fn for_label(label: &str) -> Result<Encoding, EncodingError> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "unicode-1-1-utf-8" => Ok(Encoding::Utf8),
+            "windows-1252" | "cp1252" | "x-cp1252" | "iso-8859-1" | "iso8859-1" | "iso_8859-1"
+            | "latin1" | "l1" | "us-ascii" | "ascii" => Ok(Encoding::Windows1252),
+            _ => Err(EncodingError::Unsupported(label.to_string())),
+        }
+    }
+
+    /// Malformed UTF-8 is replaced with U+FFFD.
+    pub fn decode(self, input: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(input).into_owned(),
+            Encoding::Windows1252 => input
+                .iter()
+                .map(|&byte| match byte {
+                    0x80..=0x9f => WINDOWS_1252_HIGH[byte as usize - 0x80],
+                    _ => byte as char,
+                })
+                .collect(),
+        }
+    }
+
+    /// The encoding named by a `<meta charset>` or `<meta http-equiv>` in the
+    /// first 1024 bytes of `input`, if it's one we support.
+    pub fn sniff(input: &[u8]) -> Option<Encoding> {
+        let head = input[..input.len().min(1024)].to_ascii_lowercase();
+        let head = String::from_utf8_lossy(&head);
+        head.match_indices("<meta").find_map(|(start, _)| {
+            let tag = &head[start..];
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            let value = tag[tag.find("charset")? + "charset".len()..]
+                .trim_start()
+                .strip_prefix('=')?
+                .trim_start()
+                .trim_start_matches(['"', '\'']);
+            let end = value
+                .find(|c: char| matches!(c, '"' | '\'' | ';' | '/') || c.is_whitespace())
+                .unwrap_or(value.len());
+            Encoding::for_label(&value[..end]).ok()
+        })
+    }
+}
+
+pub fn parse(input: String, context: &mut DocumentData) -> Result<Node, ParseError> {
     let mut parser = Parser {
         pos: 0,
         input,
         context,
+        path: vec![],
+        spans: None,
+    };
+    parser.parse_root()
+}
+
+/// Parses documents one after another, reusing its input buffer.
+#[derive(Debug, Default)]
+pub struct HtmlParser {
+    buffer: String,
+    /// The encoding set with [`HtmlParser::set_encoding`], overriding any
+    /// declared by the document.
+    encoding: Option<Encoding>,
+}
+
+impl HtmlParser {
+    pub fn new() -> HtmlParser {
+        HtmlParser::default()
+    }
+
+    /// The root element of `input`, and a fresh document holding its doctype
+    /// and stylesheets. The document's `root` is left empty.
+    pub fn parse_document(&mut self, input: &str) -> Result<(Node, DocumentData), ParseError> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        buffer.push_str(input);
+        let mut context = DocumentData::new();
+        let mut parser = Parser {
+            pos: 0,
+            input: buffer,
+            context: &mut context,
+            path: vec![],
+            spans: None,
+        };
+        let root = parser.parse_root();
+        self.buffer = parser.input;
+        Ok((root?, context))
+    }
+
+    /// Decodes documents given as bytes with `encoding`, a label such as
+    /// `ISO-8859-1`, instead of the one they declare.
+    pub fn set_encoding(&mut self, encoding: &str) -> Result<(), EncodingError> {
+        self.encoding = Some(Encoding::for_label(encoding)?);
+        Ok(())
+    }
+
+    /// Like [`HtmlParser::parse_document`] for undecoded input. Without an
+    /// encoding set, the one declared by an early `<meta charset>` is used,
+    /// falling back to UTF-8.
+    pub fn parse_document_bytes(
+        &mut self,
+        input: &[u8],
+    ) -> Result<(Node, DocumentData), ParseError> {
+        let encoding = self
+            .encoding
+            .or_else(|| Encoding::sniff(input))
+            .unwrap_or_default();
+        self.parse_document(&encoding.decode(input))
+    }
+}
+
+/// A parsed tree along with the source it came from, so the markup of any
+/// node can be viewed without serializing it again.
+#[derive(Debug, Clone)]
+pub struct ParsedNode {
+    pub node: Node,
+    source: Arc<String>,
+    spans: HashMap<NodePath, Range<usize>>,
+}
+
+impl ParsedNode {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Where the node at `path` starts and ends in the source. The `<html>`
+    /// wrapped around several top-level nodes wasn't written, so has none.
+    pub fn span(&self, path: &NodePath) -> Option<Range<usize>> {
+        self.spans.get(path).cloned()
+    }
+
+    /// The markup of the node at `path` as written, entities and all.
+    pub fn as_html_str(&self, path: &NodePath) -> Option<&str> {
+        self.original_slice(path, &self.source)
+    }
+
+    /// Like [`ParsedNode::as_html_str`], borrowing from a copy of the source
+    /// that outlives this value.
+    pub fn original_slice<'a>(&self, path: &NodePath, source: &'a str) -> Option<&'a str> {
+        source.get(self.span(path)?)
+    }
+}
+
+/// Like [`parse`], also recording where each node is in `input`.
+pub fn parse_with_spans(input: String) -> Result<ParsedNode, ParseError> {
+    let mut context = DocumentData::new();
+    let mut parser = Parser {
+        pos: 0,
+        input,
+        context: &mut context,
+        path: vec![],
+        spans: Some(HashMap::new()),
     };
-    let mut nodes = parser.parse_nodes();
+    let node = parser.parse_root()?;
+    Ok(ParsedNode {
+        node,
+        source: Arc::new(parser.input),
+        spans: parser.spans.unwrap_or_default(),
+    })
+}
+
+/// Parses a sequence of nodes that may not form a complete document, such as
+/// `<li>a</li><li>b</li>`. Stylesheets found within are discarded.
+pub fn parse_fragment(input: String) -> Result<Node, ParseError> {
+    let mut context = DocumentData::new();
+    let mut parser = Parser {
+        pos: 0,
+        input,
+        context: &mut context,
+        path: vec![],
+        spans: None,
+    };
+    Ok(document_fragment(parser.parse_to_end()?))
+}
+
+/// Parses an attribute list as it appears in an opening tag, e.g.
+/// `id="foo" class="bar" disabled`.
+pub fn parse_attr_list(s: &str) -> Result<AttrMap, ParseError> {
+    let mut context = DocumentData::new();
+    let mut parser = Parser {
+        pos: 0,
+        input: s.to_string(),
+        context: &mut context,
+        path: vec![],
+        spans: None,
+    };
+    let attributes = parser.parse_attributes()?;
+    if !parser.eof() {
+        return Err(parser.unexpected());
+    }
+    Ok(attributes)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// `<html>` has this many `<head>` children instead of one.
+    HeadCount(usize),
+    /// `<html>` has this many `<body>` children instead of one.
+    BodyCount(usize),
+    /// `<head>` has this many `<title>` descendants instead of one.
+    TitleCount(usize),
+    /// A `<table>` child that isn't a caption, column group, row group or row.
+    InvalidTableChild(String),
+    /// A `<ul>` or `<ol>` child that isn't a `<li>`.
+    InvalidListChild(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Tag names from the outermost element down to the offending one.
+    pub path: Vec<String>,
+    pub violation: Violation,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.join(" > ");
+        match &self.violation {
+            Violation::HeadCount(count) => {
+                write!(f, "{}: expected 1 <head>, found {}", path, count)
+            }
+            Violation::BodyCount(count) => {
+                write!(f, "{}: expected 1 <body>, found {}", path, count)
+            }
+            Violation::TitleCount(count) => {
+                write!(f, "{}: expected 1 <title>, found {}", path, count)
+            }
+            Violation::InvalidTableChild(tag) | Violation::InvalidListChild(tag) => {
+                write!(f, "{}: <{}> is not allowed here", path, tag)
+            }
+        }
+    }
+}
+
+const TABLE_CHILDREN: &[&str] = &["caption", "colgroup", "thead", "tbody", "tfoot", "tr"];
+
+/// Checks the structure of `node` and its descendants, returning every
+/// violation in document order.
+pub fn validate(node: &Node) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    validate_node(node, &mut vec![], &mut errors);
+    errors
+}
+
+fn validate_node(node: &Node, path: &mut Vec<String>, errors: &mut Vec<ValidationError>) {
+    let Some(tag) = node.tag_name() else {
+        for child in node.children() {
+            validate_node(child, path, errors);
+        }
+        return;
+    };
+    path.push(tag.to_string());
+    let child_tags: Vec<&str> = node.children().iter().filter_map(Node::tag_name).collect();
+    let count = |tag: &str| child_tags.iter().filter(|t| **t == tag).count();
+    let mut report = |violation| {
+        errors.push(ValidationError {
+            path: path.clone(),
+            violation,
+        })
+    };
+    match tag {
+        "html" => {
+            if count("head") != 1 {
+                report(Violation::HeadCount(count("head")));
+            }
+            if count("body") != 1 {
+                report(Violation::BodyCount(count("body")));
+            }
+        }
+        "head" => {
+            let titles = node.select_all_by_tag("title").count();
+            if titles != 1 {
+                report(Violation::TitleCount(titles));
+            }
+        }
+        "table" => child_tags
+            .iter()
+            .filter(|t| !TABLE_CHILDREN.contains(t))
+            .for_each(|t| report(Violation::InvalidTableChild(t.to_string()))),
+        "ul" | "ol" => child_tags
+            .iter()
+            .filter(|t| **t != "li")
+            .for_each(|t| report(Violation::InvalidListChild(t.to_string()))),
+        _ => {}
+    }
+    for child in node.children() {
+        validate_node(child, path, errors);
+    }
+    path.pop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(input: &str) -> Node {
+        parse(input.to_string(), &mut DocumentData::new()).unwrap()
+    }
+
+    #[test]
+    fn void_elements_have_no_closing_tag() {
+        let node =
+            parse_str("<head><link rel=\"icon\" href=\"a.ico\"><meta charset=\"utf-8\"></head>");
+        assert_eq!(node.children().len(), 2);
+        assert_eq!(node.children()[0].attr("href"), Some("a.ico"));
+        assert_eq!(node.children()[1].attr("charset"), Some("utf-8"));
+    }
+
+    #[test]
+    fn processing_instructions_keep_target_and_data() {
+        let node =
+            parse_fragment("<?xml version=\"1.0\" encoding=\"UTF-8\"?><p>x</p>".into()).unwrap();
+        let (target, data) = node.children()[0].as_processing_instruction().unwrap();
+        assert_eq!(target, "xml");
+        assert_eq!(data, "version=\"1.0\" encoding=\"UTF-8\"");
+        assert_eq!(node.children()[1].as_processing_instruction(), None);
+    }
+
+    #[test]
+    fn unterminated_processing_instruction_is_an_error() {
+        assert_eq!(
+            parse_fragment("<?php echo 1;".into()),
+            Err(ParseError::UnexpectedEof)
+        );
+    }
+
+    fn violations(html: &str) -> Vec<(String, Violation)> {
+        validate(&parse_fragment(html.into()).unwrap())
+            .into_iter()
+            .map(|error| (error.path.join(">"), error.violation))
+            .collect()
+    }
+
+    const HEAD: &str = "<head><title>T</title></head>";
+
+    #[test]
+    fn valid_document_has_no_violations() {
+        let html = format!(
+            "<html>{}<body><ul><li>a</li></ul><table><thead></thead><tr><td>x</td></tr></table></body></html>",
+            HEAD
+        );
+        assert_eq!(violations(&html), []);
+    }
+
+    #[test]
+    fn html_needs_one_head_and_body() {
+        assert_eq!(
+            violations("<html><body></body></html>"),
+            [("html".into(), Violation::HeadCount(0))]
+        );
+        assert_eq!(
+            violations(&format!("<html>{}<body></body><body></body></html>", HEAD)),
+            [("html".into(), Violation::BodyCount(2))]
+        );
+    }
+
+    #[test]
+    fn head_needs_one_title() {
+        assert_eq!(
+            violations("<html><head><title>a</title><title>b</title></head><body></body></html>"),
+            [("html>head".into(), Violation::TitleCount(2))]
+        );
+    }
+
+    #[test]
+    fn table_and_list_children() {
+        assert_eq!(
+            violations("<table><div></div><tbody></tbody></table>"),
+            [("table".into(), Violation::InvalidTableChild("div".into()))]
+        );
+        assert_eq!(
+            violations("<ol><li>a</li><p>b</p></ol>"),
+            [("ol".into(), Violation::InvalidListChild("p".into()))]
+        );
+    }
+
+    #[test]
+    fn all_violations_are_reported() {
+        assert_eq!(
+            violations("<html><head></head><body><ul><span>a</span></ul><table><li>b</li></table></body></html>"),
+            [
+                ("html>head".into(), Violation::TitleCount(0)),
+                ("html>body>ul".into(), Violation::InvalidListChild("span".into())),
+                ("html>body>table".into(), Violation::InvalidTableChild("li".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn cdata_sections_are_raw_text() {
+        let node = parse_str("<p><![CDATA[a < b && c &amp; d]]></p>");
+        let section = &node.children()[0];
+        assert_eq!(section.as_cdata_section(), Some("a < b && c &amp; d"));
+        assert_eq!(
+            section.node_type,
+            NodeType::CDATASection("a < b && c &amp; d".into())
+        );
+        assert_eq!(node.outer_html(), "<p><![CDATA[a < b && c &amp; d]]></p>");
+    }
+
+    #[test]
+    fn cdata_sections_round_trip() {
+        let html = "<svg><![CDATA[ <rect/> ]]><![CDATA[]]></svg>";
+        let node = parse_str(html);
+        assert_eq!(node.children()[0].as_cdata_section(), Some(" <rect/> "));
+        assert_eq!(node.children()[1].as_cdata_section(), Some(""));
+        assert_eq!(parse_str(&node.outer_html()), node);
+        assert_eq!(
+            parse_fragment("<![CDATA[x]]".into()),
+            Err(ParseError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn html_parser_documents_are_independent() {
+        let mut parser = HtmlParser::new();
+        let (first_root, first) = parser
+            .parse_document(
+                "<!DOCTYPE html><html><head><style>p { color: red; }</style></head>\
+                 <body><p>one</p></body></html>",
+            )
+            .unwrap();
+        let (second_root, second) = parser.parse_document("<div>two</div>").unwrap();
+
+        assert_eq!(first.doctype.as_deref(), Some("html"));
+        assert_eq!(first.stylesheets.len(), 1);
+        assert_eq!(first_root.tag_name(), Some("html"));
+        assert_eq!(second.doctype, None);
+        assert!(second.stylesheets.is_empty());
+        assert_eq!(second_root, parse_str("<div>two</div>"));
+        assert!(first.root.is_none() && second.root.is_none());
+    }
+
+    #[test]
+    fn html_parser_recovers_after_an_error() {
+        let mut parser = HtmlParser::new();
+        assert!(parser.parse_document("<div><p></div>").is_err());
+        let (root, _) = parser.parse_document("<p>ok</p>").unwrap();
+        assert_eq!(root.outer_html(), "<p>ok</p>");
+    }
+
+    #[test]
+    fn parsed_node_slices_the_source() {
+        let source = "<div id=\"x\"><p>a &amp; b</p><!--c--><br></div>";
+        let parsed = parse_with_spans(source.to_string()).unwrap();
+        let path = |indices: &[usize]| NodePath(indices.to_vec());
+        assert_eq!(parsed.source(), source);
+        assert_eq!(parsed.as_html_str(&path(&[])), Some(source));
+        assert_eq!(parsed.as_html_str(&path(&[0])), Some("<p>a &amp; b</p>"));
+        assert_eq!(parsed.as_html_str(&path(&[0, 0])), Some("a &amp; b"));
+        assert_eq!(parsed.as_html_str(&path(&[1])), Some("<!--c-->"));
+        assert_eq!(parsed.as_html_str(&path(&[2])), Some("<br>"));
+        assert_eq!(parsed.as_html_str(&path(&[3])), None);
+        assert_eq!(parsed.span(&path(&[0])), Some(12..28));
+        assert_eq!(parsed.node.children()[0].text_content(), "a & b");
+
+        let copy = source.to_string();
+        let slice = parsed.original_slice(&path(&[0]), &copy);
+        drop(parsed);
+        assert_eq!(slice, Some("<p>a &amp; b</p>"));
+    }
+
+    #[test]
+    fn parsed_node_with_several_top_level_nodes() {
+        let parsed = parse_with_spans("<p>a</p><p>b</p>".to_string()).unwrap();
+        assert_eq!(parsed.node.tag_name(), Some("html"));
+        assert_eq!(parsed.as_html_str(&NodePath(vec![])), None);
+        assert_eq!(parsed.as_html_str(&NodePath(vec![1])), Some("<p>b</p>"));
+    }
+
+    #[test]
+    fn latin1_input_is_decoded() {
+        let mut parser = HtmlParser::new();
+        parser.set_encoding("ISO-8859-1").unwrap();
+        let (root, _) = parser
+            .parse_document_bytes(b"<p>caf\xe9 na\xefve \xa9 \xbd\xff</p>")
+            .unwrap();
+        assert_eq!(root.text_content(), "café naïve © ½ÿ");
+    }
+
+    #[test]
+    fn declared_charset_is_used() {
+        let mut parser = HtmlParser::new();
+        let input = b"<html><head><meta charset=\"windows-1252\"></head>\
+                      <body><p>\x93quoted\x94 \x80 5</p></body></html>";
+        let (root, _) = parser.parse_document_bytes(input).unwrap();
+        assert_eq!(root.text_content(), "\u{201c}quoted\u{201d} € 5");
+
+        let input = b"<html><head><meta http-equiv=\"Content-Type\" \
+                      content=\"text/html; charset=latin1\"></head><body>\xe9</body></html>";
+        let (root, _) = parser.parse_document_bytes(input).unwrap();
+        assert_eq!(root.text_content(), "é");
+    }
+
+    #[test]
+    fn set_encoding_overrides_the_declared_charset() {
+        let input = "<p><meta charset=\"iso-8859-1\">é</p>".as_bytes();
+        let (root, _) = HtmlParser::new().parse_document_bytes(input).unwrap();
+        assert_eq!(root.text_content(), "Ã©");
+
+        let mut parser = HtmlParser::new();
+        parser.set_encoding("UTF-8").unwrap();
+        let (root, _) = parser.parse_document_bytes(input).unwrap();
+        assert_eq!(root.text_content(), "é");
+        let (root, _) = parser.parse_document_bytes(b"<p>\xe9</p>").unwrap();
+        assert_eq!(root.text_content(), "\u{fffd}");
+    }
+
+    #[test]
+    fn unsupported_encodings() {
+        assert_eq!(
+            HtmlParser::new().set_encoding("shift_jis"),
+            Err(EncodingError::Unsupported("shift_jis".into()))
+        );
+        assert_eq!(Encoding::sniff(b"<meta charset=\"koi8-r\">"), None);
+        assert_eq!(Encoding::sniff(b"<p>no meta</p>"), None);
+    }
+
+    #[test]
+    fn character_references() {
+        assert_eq!(
+            decode_entities("a &amp; b &lt;c&gt; &quot;&apos;&nbsp;"),
+            "a & b <c> \"'\u{a0}"
+        );
+        assert_eq!(
+            decode_entities("&#65;&#x42;&#X43;&#x1F600;"),
+            "ABC\u{1f600}"
+        );
+        assert_eq!(
+            decode_entities("&unknown; &amp &#; &#x; &#xZZ; &#1114112;"),
+            "&unknown; &amp &#; &#x; &#xZZ; &#1114112;"
+        );
+        assert_eq!(
+            decode_entities("&#+65; &#-65; &#x+41; &#x-41; &# 65;"),
+            "&#+65; &#-65; &#x+41; &#x-41; &# 65;"
+        );
+        // Leading zeros are fine as long as the reference isn't too long.
+        assert_eq!(decode_entities("&#x0041;&#00000065;"), "AA");
+        assert_eq!(
+            decode_entities(&format!("&#{}65;", "0".repeat(40))),
+            format!("&#{}65;", "0".repeat(40))
+        );
+    }
 
-    if nodes.len() == 1 {
-        nodes.swap_remove(0)
-    } else {
-        element("html".into(), AttrMap::default(), nodes)
+    #[test]
+    fn unterminated_references_decode_in_linear_time() {
+        // Looking for a `;` to the end of the input for every `&` would take
+        // minutes here.
+        let input = "&".repeat(1_000_000) + ";";
+        assert_eq!(decode_entities(&input), input);
+        let input = "&amp".repeat(250_000);
+        assert_eq!(decode_entities(&input), input);
     }
 }
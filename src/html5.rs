@@ -0,0 +1,807 @@
+//! HTML parsing that follows the WHATWG tokenizer and a simplified version of
+//! its tree construction rules, for markup the strict parser in
+//! [`crate::html`] rejects: unclosed `<p>` and `<li>`, stray end tags, missing
+//! `<head>`/`<body>` and so on. Parsing never fails; errors are recovered from
+//! the way browsers do.
+//!
+//! Not covered: the adoption agency algorithm for misnested formatting
+//! elements, foster parenting of content inside tables, templates and
+//! foreign (SVG/MathML) content.
+//!
+//! This is an in-tree implementation, not a wrapper around `html5ever`, so the
+//! crate stays free of dependencies. It isn't spec-complete; on well-formed
+//! documents it builds the same tree as the strict parser, which the tests
+//! check on a set of sample pages.
+use crate::{
+    dom::{comment, element, AttrMap, AttrValue, DocumentData, Node, NodeType},
+    html::{decode_entities, VOID_ELEMENTS},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Doctype(String),
+    StartTag {
+        name: String,
+        /// Attributes in source order. Duplicates are dropped, keeping the first.
+        attributes: Vec<(String, Option<String>)>,
+        self_closing: bool,
+    },
+    EndTag(String),
+    Comment(String),
+    Text(String),
+}
+
+/// Elements whose content is text up to the matching end tag.
+const RAW_TEXT_ELEMENTS: &[&str] = &["iframe", "noembed", "noframes", "script", "style", "xmp"];
+
+/// Like [`RAW_TEXT_ELEMENTS`], but character references are still decoded.
+const RCDATA_ELEMENTS: &[&str] = &["textarea", "title"];
+
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+    /// Set after the start tag of a raw text or RCDATA element.
+    raw_text: Option<String>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            raw_text: None,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn starts_with_ignore_case(&self, s: &str) -> bool {
+        self.rest()
+            .get(..s.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(s))
+    }
+
+    fn next_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn consume_while<F: Fn(char) -> bool>(&mut self, test: F) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(|c| !test(c)).unwrap_or(rest.len());
+        self.pos += end;
+        &rest[..end]
+    }
+
+    /// Consumes everything up to and including `end`, returning what came before it.
+    fn consume_until(&mut self, end: &str) -> &'a str {
+        let rest = self.rest();
+        match rest.find(end) {
+            Some(index) => {
+                self.pos += index + end.len();
+                &rest[..index]
+            }
+            None => {
+                self.pos = self.input.len();
+                rest
+            }
+        }
+    }
+
+    fn raw_text_token(&mut self, name: String) -> Token {
+        let rest = self.rest();
+        let lowercase = rest.to_ascii_lowercase();
+        let close = format!("</{}", name);
+        let mut end = rest.len();
+        let mut search = 0;
+        while let Some(index) = lowercase[search..].find(&close) {
+            let index = search + index;
+            let after = rest[index + close.len()..].chars().next();
+            if after.is_none_or(|c| c.is_ascii_whitespace() || c == '/' || c == '>') {
+                end = index;
+                break;
+            }
+            search = index + close.len();
+        }
+        self.pos += end;
+        let content = &rest[..end];
+        if RCDATA_ELEMENTS.contains(&&*name) {
+            Token::Text(decode_entities(content))
+        } else {
+            Token::Text(content.to_string())
+        }
+    }
+
+    fn markup_declaration(&mut self) -> Token {
+        if self.rest().starts_with("<!--") {
+            self.pos += 4;
+            // `<!-->` and `<!--->` are complete, empty comments.
+            for abrupt in [">", "->"] {
+                if self.rest().starts_with(abrupt) {
+                    self.pos += abrupt.len();
+                    return Token::Comment(String::new());
+                }
+            }
+            return Token::Comment(self.consume_until("-->").to_string());
+        }
+        self.pos += 2;
+        if self.starts_with_ignore_case("doctype") {
+            self.pos += "doctype".len();
+            let doctype = self.consume_until(">");
+            return Token::Doctype(doctype.trim().to_ascii_lowercase());
+        }
+        Token::Comment(self.consume_until(">").to_string())
+    }
+
+    fn end_tag(&mut self) -> Token {
+        self.pos += 2;
+        let name = self
+            .consume_while(|c| !c.is_ascii_whitespace() && c != '/' && c != '>')
+            .to_ascii_lowercase();
+        // Attributes on end tags are a parse error and ignored.
+        self.consume_until(">");
+        Token::EndTag(name)
+    }
+
+    /// A start tag, or `None` if the input ends before it does.
+    fn start_tag(&mut self) -> Option<Token> {
+        self.pos += 1;
+        let name = self
+            .consume_while(|c| !c.is_ascii_whitespace() && c != '/' && c != '>')
+            .to_ascii_lowercase();
+        let mut attributes: Vec<(String, Option<String>)> = vec![];
+        let mut self_closing = false;
+        loop {
+            self.consume_while(|c| c.is_ascii_whitespace());
+            match self.next_char()? {
+                '>' => {
+                    self.pos += 1;
+                    break;
+                }
+                '/' => {
+                    self.pos += 1;
+                    if self.next_char() == Some('>') {
+                        self.pos += 1;
+                        self_closing = true;
+                        break;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            let first = self.next_char()?;
+            self.pos += first.len_utf8();
+            let rest =
+                self.consume_while(|c| !c.is_ascii_whitespace() && !matches!(c, '/' | '>' | '='));
+            let attribute = format!("{}{}", first, rest).to_ascii_lowercase();
+            self.consume_while(|c| c.is_ascii_whitespace());
+            let value = if self.next_char() == Some('=') {
+                self.pos += 1;
+                self.consume_while(|c| c.is_ascii_whitespace());
+                let value = match self.next_char()? {
+                    quote @ ('"' | '\'') => {
+                        self.pos += 1;
+                        let rest = self.rest();
+                        let end = rest.find(quote)?;
+                        self.pos += end + 1;
+                        &rest[..end]
+                    }
+                    _ => self.consume_while(|c| !c.is_ascii_whitespace() && c != '>'),
+                };
+                Some(decode_entities(value))
+            } else {
+                None
+            };
+            if !attributes.iter().any(|(name, _)| *name == attribute) {
+                attributes.push((attribute, value));
+            }
+        }
+        if RAW_TEXT_ELEMENTS.contains(&&*name) || RCDATA_ELEMENTS.contains(&&*name) {
+            self.raw_text = Some(name.clone());
+        }
+        Some(Token::StartTag {
+            name,
+            attributes,
+            self_closing,
+        })
+    }
+}
+
+impl Iterator for Tokenizer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if let Some(name) = self.raw_text.take() {
+            let token = self.raw_text_token(name);
+            if token != Token::Text(String::new()) {
+                return Some(token);
+            }
+        }
+        let rest = self.rest();
+        let mut chars = rest.chars();
+        match (chars.next()?, chars.next()) {
+            ('<', Some('!')) => Some(self.markup_declaration()),
+            ('<', Some('/')) if chars.next().is_some_and(|c| c.is_ascii_alphabetic()) => {
+                Some(self.end_tag())
+            }
+            ('<', Some('/')) => {
+                self.pos += 2;
+                // `</>` is dropped entirely; anything else becomes a comment.
+                if self.next_char() == Some('>') {
+                    self.pos += 1;
+                    return self.next();
+                }
+                Some(Token::Comment(self.consume_until(">").to_string()))
+            }
+            ('<', Some('?')) => {
+                self.pos += 1;
+                Some(Token::Comment(self.consume_until(">").to_string()))
+            }
+            ('<', Some(c)) if c.is_ascii_alphabetic() => {
+                let token = self.start_tag();
+                if token.is_none() {
+                    // EOF inside a tag drops the tag.
+                    self.pos = self.input.len();
+                }
+                token
+            }
+            _ => {
+                // A `<` that doesn't start markup is just text.
+                let skip = if rest.starts_with('<') { 1 } else { 0 };
+                let end = rest[skip..].find('<').map_or(rest.len(), |i| i + skip);
+                self.pos += end;
+                Some(Token::Text(decode_entities(&rest[..end])))
+            }
+        }
+    }
+}
+
+/// Start tags that close an open `<p>`.
+const CLOSES_P: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+/// Elements that stop the search for an element to close.
+const SCOPE_BOUNDARIES: &[&str] = &[
+    "applet", "button", "caption", "html", "marquee", "object", "table", "td", "template", "th",
+];
+
+/// Elements that may only appear in `<head>` before the body starts.
+const HEAD_ELEMENTS: &[&str] = &[
+    "base", "basefont", "bgsound", "link", "meta", "noframes", "script", "style", "template",
+    "title",
+];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum InsertionMode {
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+}
+
+/// Builds a [`DocumentData`] from the [`Token`]s of a [`Tokenizer`].
+///
+/// Open elements are owned by the stack and appended to their parent when
+/// they're closed, so the tree is only complete after [`Html5TreeSink::finish`].
+pub struct Html5TreeSink {
+    stack: Vec<Node>,
+    mode: InsertionMode,
+    head: Option<Node>,
+    doctype: Option<String>,
+}
+
+impl Default for Html5TreeSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Html5TreeSink {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![element("html".into(), AttrMap::default(), vec![])],
+            mode: InsertionMode::BeforeHead,
+            head: None,
+            doctype: None,
+        }
+    }
+
+    fn current_tag(&self) -> &str {
+        self.stack
+            .last()
+            .and_then(Node::tag_name)
+            .unwrap_or_default()
+    }
+
+    fn append(&mut self, node: Node) {
+        let parent = self.stack.last_mut().unwrap();
+        if let NodeType::Text(content) = &node.node_type {
+            _ = parent.append_text(content);
+        } else {
+            _ = parent.append_child(node);
+        }
+    }
+
+    /// Closes the current element. The `<html>` element is never popped.
+    fn pop(&mut self) {
+        if self.stack.len() > 1 {
+            let node = self.stack.pop().unwrap();
+            if self.mode == InsertionMode::InHead && node.tag_name() == Some("head") {
+                self.head = Some(node);
+                self.mode = InsertionMode::AfterHead;
+                return;
+            }
+            self.append(node);
+        }
+    }
+
+    /// The stack index of the nearest open `tag`, unless a scope boundary
+    /// (or, for list items, another special element) comes first.
+    fn in_scope(&self, tag: &str, boundaries: &[&str]) -> Option<usize> {
+        for (index, node) in self.stack.iter().enumerate().rev() {
+            let name = node.tag_name().unwrap_or_default();
+            if name == tag {
+                return Some(index);
+            }
+            if boundaries.contains(&name) || SCOPE_BOUNDARIES.contains(&name) {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn pop_to(&mut self, index: usize) {
+        while self.stack.len() > index.max(1) {
+            self.pop();
+        }
+    }
+
+    fn close_p(&mut self) {
+        if let Some(index) = self.in_scope("p", &[]) {
+            self.pop_to(index);
+        }
+    }
+
+    fn ensure_head(&mut self) {
+        if self.mode == InsertionMode::BeforeHead {
+            self.stack
+                .push(element("head".into(), AttrMap::default(), vec![]));
+            self.mode = InsertionMode::InHead;
+        }
+    }
+
+    /// Closes `<head>` along with anything still open inside it.
+    fn close_head(&mut self) {
+        if self.mode != InsertionMode::InHead {
+            return;
+        }
+        if let Some(index) = self
+            .stack
+            .iter()
+            .rposition(|n| n.tag_name() == Some("head"))
+        {
+            self.pop_to(index);
+        }
+    }
+
+    /// Leaves the head and opens `<body>`, with `attributes` if it's explicit.
+    fn ensure_body(&mut self, attributes: AttrMap) {
+        if self.mode == InsertionMode::InBody {
+            return;
+        }
+        self.ensure_head();
+        self.close_head();
+        self.stack.push(element("body".into(), attributes, vec![]));
+        self.mode = InsertionMode::InBody;
+    }
+
+    fn merge_attributes(&mut self, index: usize, attributes: AttrMap) {
+        if let Some(existing) = self.stack.get_mut(index).and_then(Node::attributes_mut) {
+            for (name, value) in attributes.0 {
+                existing.0.entry(name).or_insert(value);
+            }
+        }
+    }
+
+    pub fn process(&mut self, token: Token) {
+        match token {
+            Token::Doctype(doctype) => {
+                if self.mode == InsertionMode::BeforeHead && self.doctype.is_none() {
+                    self.doctype = Some(doctype);
+                }
+            }
+            Token::Comment(text) => self.append(comment(text)),
+            Token::Text(text) => {
+                let in_head_element =
+                    self.mode == InsertionMode::InHead && self.current_tag() != "head";
+                if self.mode != InsertionMode::InBody && !in_head_element {
+                    if text.trim().is_empty() {
+                        if self.mode == InsertionMode::InHead {
+                            self.append(crate::dom::text(text));
+                        }
+                        return;
+                    }
+                    self.ensure_body(AttrMap::default());
+                }
+                self.append(crate::dom::text(text));
+            }
+            // A trailing `/` doesn't close non-void HTML elements.
+            Token::StartTag {
+                name, attributes, ..
+            } => self.start_tag(name, to_attr_map(attributes)),
+            Token::EndTag(name) => self.end_tag(&name),
+        }
+    }
+
+    fn start_tag(&mut self, name: String, attributes: AttrMap) {
+        match &*name {
+            "html" => return self.merge_attributes(0, attributes),
+            "head" if self.mode == InsertionMode::BeforeHead => {
+                self.stack.push(element(name, attributes, vec![]));
+                self.mode = InsertionMode::InHead;
+                return;
+            }
+            "head" => return,
+            "body" if self.mode == InsertionMode::InBody => {
+                // `<body>` always sits right below `<html>`.
+                return self.merge_attributes(1, attributes);
+            }
+            "body" => return self.ensure_body(attributes),
+            _ if self.mode < InsertionMode::InBody && HEAD_ELEMENTS.contains(&&*name) => {
+                if self.mode == InsertionMode::AfterHead {
+                    // The head is already closed; reopen it for this element.
+                    self.stack.push(self.head.take().unwrap());
+                    self.mode = InsertionMode::InHead;
+                } else {
+                    self.ensure_head();
+                }
+            }
+            _ => self.ensure_body(AttrMap::default()),
+        }
+
+        if self.mode == InsertionMode::InBody {
+            match &*name {
+                tag if CLOSES_P.contains(&tag) => {
+                    self.close_p();
+                    if HEADINGS.contains(&tag) && HEADINGS.contains(&self.current_tag()) {
+                        self.pop();
+                    }
+                }
+                "li" => self.close_list_item(&["li"]),
+                "dd" | "dt" => self.close_list_item(&["dd", "dt"]),
+                "option" if self.current_tag() == "option" => self.pop(),
+                "optgroup" => {
+                    while matches!(self.current_tag(), "option" | "optgroup") {
+                        self.pop();
+                    }
+                }
+                "a" => {
+                    if let Some(index) = self.in_scope("a", &[]) {
+                        self.pop_to(index);
+                    }
+                }
+                "tr" => {
+                    self.close_cell();
+                    if let Some(index) = self.in_scope("tr", &[]) {
+                        self.pop_to(index);
+                    }
+                    if self.current_tag() == "table" {
+                        self.stack
+                            .push(element("tbody".into(), AttrMap::default(), vec![]));
+                    }
+                }
+                "td" | "th" => {
+                    self.close_cell();
+                    if self.current_tag() == "table" {
+                        self.stack
+                            .push(element("tbody".into(), AttrMap::default(), vec![]));
+                    }
+                    if self.current_tag() == "tbody" {
+                        self.stack
+                            .push(element("tr".into(), AttrMap::default(), vec![]));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if VOID_ELEMENTS.contains(&&*name) {
+            self.append(element(name, attributes, vec![]));
+        } else {
+            self.stack.push(element(name, attributes, vec![]));
+        }
+    }
+
+    /// Closes the open `<td>` or `<th>` of the innermost table, if any.
+    fn close_cell(&mut self) {
+        let cell = self
+            .stack
+            .iter()
+            .rposition(|n| matches!(n.tag_name(), Some("td" | "th" | "table")));
+        if let Some(index) = cell.filter(|&index| self.stack[index].tag_name() != Some("table")) {
+            self.pop_to(index);
+        }
+    }
+
+    /// Closes an open list item of the given kinds before a new one starts.
+    fn close_list_item(&mut self, kinds: &[&str]) {
+        self.close_p();
+        for (index, node) in self.stack.iter().enumerate().rev() {
+            let name = node.tag_name().unwrap_or_default();
+            if kinds.contains(&name) {
+                return self.pop_to(index);
+            }
+            if !matches!(
+                name,
+                "address" | "div" | "p" | "span" | "a" | "b" | "i" | "em"
+            ) && (CLOSES_P.contains(&name) || SCOPE_BOUNDARIES.contains(&name))
+            {
+                return;
+            }
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        match name {
+            "head" => self.close_head(),
+            // Content after `</body>` or `</html>` still goes into the body.
+            "body" | "html" => {}
+            "br" => self.start_tag("br".into(), AttrMap::default()),
+            "p" if self.in_scope("p", &[]).is_none() => {
+                self.start_tag("p".into(), AttrMap::default());
+                self.pop();
+            }
+            _ => {
+                if let Some(index) = self.stack.iter().rposition(|n| n.tag_name() == Some(name)) {
+                    if index > 0 {
+                        self.pop_to(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Closes every open element and returns the document, which always has
+    /// a `<head>` and a `<body>`. Stylesheets are loaded from `<style>` elements.
+    pub fn finish(mut self) -> DocumentData {
+        self.ensure_body(AttrMap::default());
+        self.pop_to(1);
+        let mut html = self.stack.pop().unwrap();
+        if let (Some(head), NodeType::Element(_)) = (self.head.take(), &html.node_type) {
+            let mut children = vec![head];
+            children.extend(html.children().iter().cloned());
+            html = element(
+                "html".into(),
+                html.attributes().cloned().unwrap_or_default(),
+                children,
+            );
+        }
+        let mut document = DocumentData::new();
+        document.doctype = self.doctype;
+        for style in html.select_all_by_tag("style") {
+            _ = document.load_css(style.text_content());
+        }
+        document.root = Some(Box::new(html));
+        document
+    }
+}
+
+fn to_attr_map(attributes: Vec<(String, Option<String>)>) -> AttrMap {
+    AttrMap(
+        attributes
+            .into_iter()
+            .map(|(name, value)| (name, value.map_or(AttrValue::Implicit, AttrValue::Text)))
+            .collect(),
+    )
+}
+
+/// Parses a complete document, recovering from errors as browsers do.
+pub fn parse(input: &str) -> Node {
+    let mut sink = Html5TreeSink::new();
+    for token in Tokenizer::new(input) {
+        sink.process(token);
+    }
+    Node {
+        node_type: NodeType::Document(sink.finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom;
+
+    /// Well-formed documents on which both parsers must build the same tree.
+    const WELL_FORMED: &[&str] = &[
+        "<!DOCTYPE html><html><head><title>Home</title></head><body><p>Hello</p></body></html>",
+        "<html><head><meta charset=\"utf-8\"><link rel=\"stylesheet\" href=\"a.css\"></head>\
+         <body><nav><ul><li><a href=\"/\">Home</a></li><li><a href=\"/about\">About</a></li></ul></nav></body></html>",
+        "<html><head><style>p { color: red; }</style><script>var a = [1, 2];</script></head>\
+         <body><main><h1>Title</h1><p>Some <b>bold</b>, <i>italic</i>.</p><img src=\"a.png\" alt=\"A\"></main></body></html>",
+        "<html><head><title>Form</title></head><body><form action=\"/s\"><input type=\"text\" name=\"q\" required>\
+         <button type=\"submit\">Go</button></form><!-- footer --><footer>&copy; 2024</footer></body></html>",
+        "<html lang=\"en\"><head></head><body class=\"home\"><table><thead><tr><th>a</th></tr></thead>\
+         <tbody><tr><td>1</td></tr></tbody></table><br><hr></body></html>",
+    ];
+
+    #[test]
+    fn matches_the_strict_parser_on_well_formed_documents() {
+        for html in WELL_FORMED {
+            let strict = dom::parse(html.to_string());
+            assert!(strict.is_ok(), "{} {:?}", html, strict);
+            assert_eq!(parse(html), strict.unwrap(), "{}", html);
+        }
+    }
+
+    fn body_html(html: &str) -> String {
+        let NodeType::Document(document) = parse(html).node_type else {
+            unreachable!()
+        };
+        document.body().unwrap().inner_html()
+    }
+
+    #[test]
+    fn recovers_where_the_strict_parser_fails() {
+        for (html, body) in [
+            ("<p>one<p>two", "<p>one</p><p>two</p>"),
+            ("<ul><li>a<li>b</ul>", "<ul><li>a</li><li>b</li></ul>"),
+            ("<div>a</span>b</div>", "<div>ab</div>"),
+            ("<p>a<div>b</div>", "<p>a</p><div>b</div>"),
+        ] {
+            assert!(dom::parse(html.to_string()).is_err(), "{}", html);
+            assert_eq!(body_html(html), body, "{}", html);
+        }
+    }
+
+    #[test]
+    fn adds_missing_head_and_body() {
+        let NodeType::Document(document) = parse("<title>T</title><p>x</p>").node_type else {
+            unreachable!()
+        };
+        assert_eq!(document.title().as_deref(), Some("T"));
+        assert_eq!(document.body().unwrap().inner_html(), "<p>x</p>");
+    }
+
+    /// Real-world snippets as `(html, head, body)`, with the inner HTML the
+    /// head and body must end up with.
+    const SNIPPETS: &[(&str, &str, &str)] = &[
+        // Unclosed head content.
+        (
+            "<html><head><template><p>x</template></head><body><div>b</div>",
+            "<template></template>",
+            "<p>x</p><div>b</div>",
+        ),
+        ("<head><title>t", "<title>t</title>", ""),
+        (
+            "<head><style>p { color: red }",
+            "<style>p { color: red }</style>",
+            "",
+        ),
+        (
+            "<html><head><title>T</title><meta charset=utf-8><p>x",
+            "<title>T</title><meta charset=\"utf-8\">",
+            "<p>x</p>",
+        ),
+        (
+            "<head><script>var a;</script>text",
+            "<script>var a;</script>",
+            "text",
+        ),
+        (
+            "<head><title>T</title></head>\n<body>x",
+            "<title>T</title>",
+            "x",
+        ),
+        // Head elements after the head is closed still go into it.
+        (
+            "<head></head><link rel=icon><body><p>x</p>",
+            "<link rel=\"icon\">",
+            "<p>x</p>",
+        ),
+        // Implied end tags.
+        (
+            "<table><tr><td>1<td>2<tr><td>3</table>",
+            "",
+            "<table><tbody><tr><td>1</td><td>2</td></tr><tr><td>3</td></tr></tbody></table>",
+        ),
+        (
+            "<table><tr><th>h<tr><td><b>d<td>e</table>",
+            "",
+            "<table><tbody><tr><th>h</th></tr><tr><td><b>d</b></td><td>e</td></tr></tbody></table>",
+        ),
+        (
+            "<table><tr><td><table><tr><td>a</table>b<td>c</table>",
+            "",
+            "<table><tbody><tr><td><table><tbody><tr><td>a</td></tr></tbody></table>b</td>\
+             <td>c</td></tr></tbody></table>",
+        ),
+        (
+            "<ul><li>a<ul><li>b</ul><li>c</ul>",
+            "",
+            "<ul><li>a<ul><li>b</li></ul></li><li>c</li></ul>",
+        ),
+        (
+            "<dl><dt>a<dd>b<dt>c</dl>",
+            "",
+            "<dl><dt>a</dt><dd>b</dd><dt>c</dt></dl>",
+        ),
+        (
+            "<select><option>a<option>b</select>",
+            "",
+            "<select><option>a</option><option>b</option></select>",
+        ),
+        ("<h1>a<h2>b", "", "<h1>a</h1><h2>b</h2>"),
+        (
+            "<a href=1>x<a href=2>y",
+            "",
+            "<a href=\"1\">x</a><a href=\"2\">y</a>",
+        ),
+        (
+            "<div><p>a<ul><li>b</ul>c</div>",
+            "",
+            "<div><p>a</p><ul><li>b</li></ul>c</div>",
+        ),
+        // Stray and misnested end tags.
+        ("<b><i>x</b>y</i>", "", "<b><i>x</i></b>y"),
+        ("<p>a</p></p>", "", "<p>a</p><p></p>"),
+        (
+            "<body><p>x</p></body></html><p>after</p>",
+            "",
+            "<p>x</p><p>after</p>",
+        ),
+        ("<body class=a><body id=b>x", "", "x"),
+        ("hello <b>world", "", "hello <b>world</b>"),
+    ];
+
+    #[test]
+    fn real_world_snippets() {
+        for (html, head, body) in SNIPPETS {
+            let NodeType::Document(document) = parse(html).node_type else {
+                unreachable!()
+            };
+            let root = document.root_node().unwrap();
+            let children: Vec<_> = root.children().iter().filter_map(Node::tag_name).collect();
+            assert_eq!(children, ["head", "body"], "{}", html);
+            assert_eq!(document.head().unwrap().inner_html(), *head, "{}", html);
+            assert_eq!(document.body().unwrap().inner_html(), *body, "{}", html);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use core::{fmt, ops};
+use std::{
+    collections::HashSet,
+    sync::{Arc, OnceLock},
+};
+
+/// A tag name, shared by every element with that name if it's a known one.
+/// Cloning only bumps a reference count.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InternedStr(Arc<str>);
+
+impl InternedStr {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ops::Deref for InternedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for InternedStr {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for InternedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for InternedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The names that are shared: the elements of HTML, including obsolete ones
+/// still found in the wild, and the most common SVG and MathML elements.
+const TAG_NAMES: &[&str] = &[
+    "a",
+    "abbr",
+    "acronym",
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "audio",
+    "b",
+    "base",
+    "basefont",
+    "bdi",
+    "bdo",
+    "bgsound",
+    "big",
+    "blink",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "canvas",
+    "caption",
+    "center",
+    "circle",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "data",
+    "datalist",
+    "dd",
+    "defs",
+    "del",
+    "details",
+    "dfn",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "ellipse",
+    "em",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "font",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "g",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "i",
+    "iframe",
+    "image",
+    "img",
+    "input",
+    "ins",
+    "kbd",
+    "label",
+    "legend",
+    "li",
+    "line",
+    "link",
+    "main",
+    "map",
+    "mark",
+    "marquee",
+    "math",
+    "menu",
+    "meta",
+    "meter",
+    "mi",
+    "mn",
+    "mo",
+    "mrow",
+    "nav",
+    "nobr",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "optgroup",
+    "option",
+    "output",
+    "p",
+    "param",
+    "path",
+    "picture",
+    "plaintext",
+    "polygon",
+    "polyline",
+    "pre",
+    "progress",
+    "q",
+    "rb",
+    "rect",
+    "rp",
+    "rt",
+    "rtc",
+    "ruby",
+    "s",
+    "samp",
+    "script",
+    "search",
+    "section",
+    "select",
+    "slot",
+    "small",
+    "source",
+    "span",
+    "strike",
+    "strong",
+    "style",
+    "sub",
+    "summary",
+    "sup",
+    "svg",
+    "symbol",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "text",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "title",
+    "tr",
+    "track",
+    "tspan",
+    "tt",
+    "u",
+    "ul",
+    "use",
+    "var",
+    "video",
+    "wbr",
+    "xmp",
+];
+
+/// One shared copy of each of [`TAG_NAMES`]. Built on first use and never
+/// changed afterwards, so looking a name up doesn't take a lock.
+fn table() -> &'static HashSet<Arc<str>> {
+    static TABLE: OnceLock<HashSet<Arc<str>>> = OnceLock::new();
+    TABLE.get_or_init(|| TAG_NAMES.iter().map(|&name| Arc::from(name)).collect())
+}
+
+/// The shared copy of `s` if it's a known tag name. Anything else, such as a
+/// custom element name, gets an allocation of its own, so that documents
+/// can't grow a process-wide table.
+pub fn intern(s: &str) -> InternedStr {
+    match table().get(s) {
+        Some(shared) => InternedStr(shared.clone()),
+        None => InternedStr(Arc::from(s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_fragment;
+
+    #[test]
+    fn equal_strings_share_one_allocation() {
+        let a = intern("section");
+        let b = intern(&String::from("section"));
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, b);
+        assert_eq!(a.as_str(), "section");
+        assert_eq!(&*a, "section");
+        assert_eq!(a.to_string(), "section");
+        assert_eq!(format!("{:?}", a), "\"section\"");
+
+        let c = intern("Section");
+        assert!(!Arc::ptr_eq(&a.0, &c.0));
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn only_known_tag_names_are_shared() {
+        assert!(TAG_NAMES
+            .iter()
+            .all(|name| { Arc::ptr_eq(&intern(name).0, &intern(name).0) }));
+        let a = intern("my-widget");
+        let b = intern("my-widget");
+        assert_eq!(a, b);
+        assert_eq!(a.as_str(), "my-widget");
+        assert!(!Arc::ptr_eq(&a.0, &b.0));
+        assert!(table().get("my-widget").is_none());
+    }
+
+    #[test]
+    fn parsed_tag_names_are_interned() {
+        let fragment = parse_fragment("<div><p>a</p></div><div><p>b</p></div>".into()).unwrap();
+        let tags: Vec<&str> = fragment.iter().filter_map(|node| node.tag_name()).collect();
+        assert_eq!(tags, ["div", "p", "div", "p"]);
+        assert!(std::ptr::eq(tags[0], tags[2]));
+        assert!(std::ptr::eq(tags[1], tags[3]));
+        assert!(std::ptr::eq(tags[0], intern("div").as_str()));
+
+        let fragment = parse_fragment("<card>a</card><card>b</card>".into()).unwrap();
+        let tags: Vec<&str> = fragment.iter().filter_map(|node| node.tag_name()).collect();
+        assert_eq!(tags, ["card", "card"]);
+        assert!(!std::ptr::eq(tags[0], tags[1]));
+    }
+}
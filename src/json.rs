@@ -0,0 +1,493 @@
+use core::fmt;
+
+/// A parsed JSON value. Object members keep their order, and a repeated key
+/// keeps every occurrence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// The last member named `key`, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            Self::Object(members) => members
+                .iter()
+                .rev()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    UnexpectedEof,
+    UnexpectedChar {
+        pos: usize,
+        found: char,
+    },
+    InvalidNumber {
+        pos: usize,
+    },
+    InvalidEscape {
+        pos: usize,
+    },
+    /// Arrays and objects are nested more than [`MAX_DEPTH`] deep.
+    TooDeep {
+        pos: usize,
+    },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::UnexpectedEof => write!(f, "unexpected end of input"),
+            Self::UnexpectedChar { pos, found } => {
+                write!(f, "unexpected character {:?} at {}", found, pos)
+            }
+            Self::InvalidNumber { pos } => write!(f, "invalid number at {}", pos),
+            Self::InvalidEscape { pos } => write!(f, "invalid escape sequence at {}", pos),
+            Self::TooDeep { pos } => write!(f, "nested too deeply at {}", pos),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+/// How deeply arrays and objects may be nested, so that hostile input can't
+/// exhaust the stack.
+pub const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    pos: usize,
+    input: &'a str,
+    /// The number of arrays and objects currently open.
+    depth: usize,
+}
+
+type ParseResult<T> = Result<T, JsonError>;
+
+impl Parser<'_> {
+    fn next_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn consume_char(&mut self) -> ParseResult<char> {
+        let c = self.next_char().ok_or(JsonError::UnexpectedEof)?;
+        self.pos += c.len_utf8();
+        Ok(c)
+    }
+
+    fn consume_whitespace(&mut self) {
+        while self
+            .next_char()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn unexpected(&self) -> JsonError {
+        match self.next_char() {
+            Some(found) => JsonError::UnexpectedChar {
+                pos: self.pos,
+                found,
+            },
+            None => JsonError::UnexpectedEof,
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> ParseResult<()> {
+        if !self.input[self.pos..].starts_with(s) {
+            return Err(self.unexpected());
+        }
+        self.pos += s.len();
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> ParseResult<JsonValue> {
+        self.consume_whitespace();
+        match self.next_char() {
+            Some('{') => self.nested(Self::parse_object),
+            Some('[') => self.nested(Self::parse_array),
+            Some('"') => self.parse_string().map(JsonValue::String),
+            Some('t') => self.expect("true").map(|_| JsonValue::Bool(true)),
+            Some('f') => self.expect("false").map(|_| JsonValue::Bool(false)),
+            Some('n') => self.expect("null").map(|_| JsonValue::Null),
+            Some('-' | '0'..='9') => self.parse_number(),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> ParseResult<JsonValue>) -> ParseResult<JsonValue> {
+        if self.depth == MAX_DEPTH {
+            return Err(JsonError::TooDeep { pos: self.pos });
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_object(&mut self) -> ParseResult<JsonValue> {
+        self.expect("{")?;
+        let mut members = vec![];
+        self.consume_whitespace();
+        if self.next_char() == Some('}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.consume_whitespace();
+            let name = self.parse_string()?;
+            self.consume_whitespace();
+            self.expect(":")?;
+            members.push((name, self.parse_value()?));
+            self.consume_whitespace();
+            match self.consume_char()? {
+                ',' => {}
+                '}' => return Ok(JsonValue::Object(members)),
+                found => {
+                    return Err(JsonError::UnexpectedChar {
+                        pos: self.pos - found.len_utf8(),
+                        found,
+                    })
+                }
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> ParseResult<JsonValue> {
+        self.expect("[")?;
+        let mut values = vec![];
+        self.consume_whitespace();
+        if self.next_char() == Some(']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.consume_whitespace();
+            match self.consume_char()? {
+                ',' => {}
+                ']' => return Ok(JsonValue::Array(values)),
+                found => {
+                    return Err(JsonError::UnexpectedChar {
+                        pos: self.pos - found.len_utf8(),
+                        found,
+                    })
+                }
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> ParseResult<String> {
+        self.expect("\"")?;
+        let mut result = String::new();
+        loop {
+            let start = self.pos;
+            match self.consume_char()? {
+                '"' => return Ok(result),
+                '\\' => result.push(self.parse_escape(start)?),
+                c if (c as u32) < 0x20 => {
+                    return Err(JsonError::UnexpectedChar {
+                        pos: start,
+                        found: c,
+                    })
+                }
+                c => result.push(c),
+            }
+        }
+    }
+
+    /// The character after a backslash at `start`. Surrogate pairs are
+    /// combined; lone surrogates become U+FFFD.
+    fn parse_escape(&mut self, start: usize) -> ParseResult<char> {
+        let c = match self.consume_char()? {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.parse_hex4(start)?;
+                if (0xd800..0xdc00).contains(&high) && self.input[self.pos..].starts_with("\\u") {
+                    let second = self.pos;
+                    self.pos += 2;
+                    let low = self.parse_hex4(second)?;
+                    if (0xdc00..0xe000).contains(&low) {
+                        let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                        return Ok(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    // Not a pair: the second escape is decoded on its own.
+                    self.pos = second;
+                }
+                char::from_u32(high).unwrap_or('\u{fffd}')
+            }
+            _ => return Err(JsonError::InvalidEscape { pos: start }),
+        };
+        Ok(c)
+    }
+
+    fn parse_hex4(&mut self, start: usize) -> ParseResult<u32> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or(JsonError::InvalidEscape { pos: start })?;
+        let code =
+            u32::from_str_radix(digits, 16).map_err(|_| JsonError::InvalidEscape { pos: start })?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn parse_number(&mut self) -> ParseResult<JsonValue> {
+        let start = self.pos;
+        while self
+            .next_char()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let number = &self.input[start..self.pos];
+        match number.parse() {
+            Ok(value) if is_json_number(number) => Ok(JsonValue::Number(value)),
+            _ => Err(JsonError::InvalidNumber { pos: start }),
+        }
+    }
+}
+
+/// Whether `s` follows the JSON number grammar, which unlike Rust's rejects
+/// leading zeros and forms like `1.` or `.5`.
+fn is_json_number(s: &str) -> bool {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let int = digits(s);
+    if int == 0 || (int > 1 && s.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &s[int..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = digits(fraction);
+        if len == 0 {
+            return false;
+        }
+        rest = &fraction[len..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let len = digits(exponent);
+        if len == 0 {
+            return false;
+        }
+        rest = &exponent[len..];
+    }
+    rest.is_empty()
+}
+
+/// Parses a single JSON value, which may be surrounded by whitespace.
+pub fn parse(input: &str) -> Result<JsonValue, JsonError> {
+    let mut parser = Parser {
+        pos: 0,
+        input,
+        depth: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.consume_whitespace();
+    if parser.pos < input.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(json: &str) -> String {
+        match parse(json) {
+            Ok(JsonValue::String(s)) => s,
+            other => panic!("expected a string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn values() {
+        let value =
+            parse(" {\"a\": [1, -2.5e3, true, false, null], \"b\": {}, \"c\": [] } ").unwrap();
+        assert_eq!(
+            value,
+            JsonValue::Object(vec![
+                (
+                    "a".into(),
+                    JsonValue::Array(vec![
+                        JsonValue::Number(1.0),
+                        JsonValue::Number(-2500.0),
+                        JsonValue::Bool(true),
+                        JsonValue::Bool(false),
+                        JsonValue::Null,
+                    ])
+                ),
+                ("b".into(), JsonValue::Object(vec![])),
+                ("c".into(), JsonValue::Array(vec![])),
+            ])
+        );
+    }
+
+    #[test]
+    fn repeated_keys_are_kept() {
+        let value = parse("{\"k\": 1, \"k\": \"two\"}").unwrap();
+        assert_eq!(value.get("k").and_then(JsonValue::as_str), Some("two"));
+        assert_eq!(value.get("missing"), None);
+        let JsonValue::Object(members) = value else {
+            panic!("expected an object");
+        };
+        assert_eq!(members.len(), 2);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            string(r#""\" \\ \/ \b \f \n \r \t""#),
+            "\" \\ / \u{8} \u{c} \n \r \t"
+        );
+        assert_eq!(string(r#""\u00e9\u00E9 \u20ac""#), "éé €");
+        assert_eq!(string("\"raw é €\""), "raw é €");
+        assert_eq!(parse(r#""\x""#), Err(JsonError::InvalidEscape { pos: 1 }));
+        assert_eq!(
+            parse(r#""a\u12""#),
+            Err(JsonError::InvalidEscape { pos: 2 })
+        );
+        assert_eq!(
+            parse(r#""\u12g4""#),
+            Err(JsonError::InvalidEscape { pos: 1 })
+        );
+        assert_eq!(
+            parse("\"a\nb\""),
+            Err(JsonError::UnexpectedChar {
+                pos: 2,
+                found: '\n'
+            })
+        );
+        assert_eq!(parse("\"open"), Err(JsonError::UnexpectedEof));
+        for signed in [r#""\u+041""#, r#""\u-041""#, r#""\u 041""#] {
+            assert_eq!(
+                parse(signed),
+                Err(JsonError::InvalidEscape { pos: 1 }),
+                "{}",
+                signed
+            );
+        }
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(string(r#""\ud83d\ude00""#), "😀");
+        assert_eq!(string(r#""\uD834\uDD1E clef""#), "𝄞 clef");
+        // Lone surrogates can't be represented in a Rust string.
+        assert_eq!(string(r#""\ud83d""#), "\u{fffd}");
+        assert_eq!(string(r#""\ud83d x""#), "\u{fffd} x");
+        assert_eq!(string(r#""\ude00""#), "\u{fffd}");
+        assert_eq!(string(r#""\ud83dA""#), "\u{fffd}A");
+        assert_eq!(string(r#""\ud83d\u0041""#), "\u{fffd}A");
+        assert_eq!(string(r#""\ud83d\ud83d\ude00""#), "\u{fffd}\u{1f600}");
+        assert_eq!(string(r#""\ud83d\ue000""#), "\u{fffd}\u{e000}");
+        assert_eq!(
+            parse(r#""\ud83d\u12""#),
+            Err(JsonError::InvalidEscape { pos: 7 })
+        );
+    }
+
+    #[test]
+    fn number_grammar() {
+        for (json, expected) in [
+            ("0", 0.0),
+            ("-0", 0.0),
+            ("7", 7.0),
+            ("-12", -12.0),
+            ("3.25", 3.25),
+            ("1e3", 1000.0),
+            ("1E+2", 100.0),
+            ("25e-1", 2.5),
+            ("-0.5e1", -5.0),
+        ] {
+            assert_eq!(parse(json), Ok(JsonValue::Number(expected)), "{}", json);
+        }
+        for json in [
+            "01", "-", "+1", "1.", ".5", "-.5", "1e", "1e+", "0x10", "1.2.3", "--1", "1-2",
+            "Infinity", "NaN",
+        ] {
+            assert!(parse(json).is_err(), "{}", json);
+        }
+        assert_eq!(parse("01"), Err(JsonError::InvalidNumber { pos: 0 }));
+        assert_eq!(parse("[1, 2.]"), Err(JsonError::InvalidNumber { pos: 4 }));
+    }
+
+    #[test]
+    fn malformed_documents() {
+        assert_eq!(parse(""), Err(JsonError::UnexpectedEof));
+        assert_eq!(parse("[1, 2"), Err(JsonError::UnexpectedEof));
+        assert_eq!(
+            parse("[1 2]"),
+            Err(JsonError::UnexpectedChar { pos: 3, found: '2' })
+        );
+        assert_eq!(
+            parse("{\"a\" 1}"),
+            Err(JsonError::UnexpectedChar { pos: 5, found: '1' })
+        );
+        assert_eq!(
+            parse("{\"a\": 1,}"),
+            Err(JsonError::UnexpectedChar { pos: 8, found: '}' })
+        );
+        assert_eq!(
+            parse("true false"),
+            Err(JsonError::UnexpectedChar { pos: 5, found: 'f' })
+        );
+        assert_eq!(
+            parse("nul"),
+            Err(JsonError::UnexpectedChar { pos: 0, found: 'n' })
+        );
+        assert_eq!(
+            JsonError::UnexpectedChar { pos: 3, found: '2' }.to_string(),
+            "unexpected character '2' at 3"
+        );
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(MAX_DEPTH + 1)),
+            Err(JsonError::TooDeep { pos: MAX_DEPTH })
+        );
+        assert_eq!(
+            parse(&"[".repeat(200_000)),
+            Err(JsonError::TooDeep { pos: MAX_DEPTH })
+        );
+        assert_eq!(
+            parse(&"{\"a\": ".repeat(200_000)),
+            Err(JsonError::TooDeep { pos: 6 * MAX_DEPTH })
+        );
+        // Siblings don't add up.
+        let siblings = format!("[{}]", vec![nested(MAX_DEPTH - 1); 3].join(","));
+        assert!(parse(&siblings).is_ok());
+    }
+}
@@ -0,0 +1,16 @@
+#[cfg(feature = "binary-cache")]
+pub mod binary_cache;
+pub mod css;
+pub mod dom;
+pub mod html;
+#[cfg(feature = "html5")]
+pub mod html5;
+pub mod intern;
+pub mod json;
+pub mod minify;
+mod render;
+pub mod sanitize;
+pub mod style;
+pub mod to_markdown;
+pub mod to_text;
+pub mod tree_walker;
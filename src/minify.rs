@@ -0,0 +1,113 @@
+use crate::dom::{document_fragment, element, text, DocumentData, Node, NodeType};
+
+/// Elements whose text content is whitespace-sensitive.
+const PRESERVE_WHITESPACE: &[&str] = &["pre", "textarea", "script", "style"];
+
+/// A deep copy of `node` without comments or whitespace-only text, and with runs of
+/// whitespace inside text collapsed to a single space.
+pub fn minify(node: &Node) -> Node {
+    minify_node(node, false).unwrap_or_else(|| text(String::new()))
+}
+
+fn minify_node(node: &Node, preserve: bool) -> Option<Node> {
+    match &node.node_type {
+        NodeType::Text(content) if preserve => Some(text(content.clone())),
+        NodeType::Text(content) if content.trim().is_empty() => None,
+        NodeType::Text(content) => Some(text(collapse_whitespace(content))),
+        NodeType::Comment(_) => None,
+        NodeType::Element(_) => {
+            let tag_name = node.tag_name().unwrap();
+            let preserve = preserve || PRESERVE_WHITESPACE.contains(&tag_name);
+            let children = node
+                .children()
+                .iter()
+                .filter_map(|child| minify_node(child, preserve))
+                .collect();
+            Some(element(
+                tag_name.to_string(),
+                node.attributes().cloned().unwrap_or_default(),
+                children,
+            ))
+        }
+        NodeType::Document(data) => Some(Node {
+            node_type: NodeType::Document(DocumentData {
+                root: data
+                    .root_node()
+                    .and_then(|root| minify_node(root, preserve))
+                    .map(Box::new),
+                stylesheets: data.stylesheets.clone(),
+                doctype: data.doctype.clone(),
+            }),
+        }),
+        NodeType::DocumentFragment(children) => Some(document_fragment(
+            children
+                .iter()
+                .filter_map(|child| minify_node(child, preserve))
+                .collect(),
+        )),
+        _ => Some(node.clone()),
+    }
+}
+
+fn collapse_whitespace(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut in_whitespace = false;
+    for c in content.chars() {
+        if c.is_whitespace() {
+            if !in_whitespace {
+                result.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            result.push(c);
+            in_whitespace = false;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_fragment;
+
+    const HTML: &str =
+        "<div class=\"a\">\n  <!-- nav -->\n  <p>Hello,\n    big   <b>world</b></p>\n  \
+                        <pre>  keep\n   this </pre>\n</div>\n";
+
+    fn tags(node: &Node) -> Vec<String> {
+        node.iter()
+            .filter_map(|n| n.tag_name().map(String::from))
+            .collect()
+    }
+
+    fn words(node: &Node) -> Vec<String> {
+        node.text_content()
+            .split_whitespace()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn minified_tree_has_the_same_structure_and_text() {
+        let original = parse_fragment(HTML.into()).unwrap();
+        let minified = minify(&original);
+        let html = minified.outer_html();
+        assert!(html.len() < original.outer_html().len());
+        assert!(!html.contains("<!--"));
+
+        let reparsed = parse_fragment(html).unwrap();
+        assert_eq!(tags(&reparsed), tags(&original));
+        assert_eq!(words(&reparsed), words(&original));
+        assert_eq!(reparsed, minified);
+    }
+
+    #[test]
+    fn whitespace_is_collapsed_except_in_pre() {
+        let minified = minify(&parse_fragment(HTML.into()).unwrap());
+        let div = &minified.children()[0];
+        assert_eq!(div.children().len(), 2);
+        assert_eq!(div.children()[0].text_content(), "Hello, big world");
+        assert_eq!(div.children()[1].text_content(), "keep\n   this ");
+    }
+}
@@ -0,0 +1,161 @@
+use crate::dom::{Node, NodeType};
+
+/// Elements that are rendered as their own paragraph-level block.
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "div",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "html",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "ul",
+];
+
+/// Elements whose content never shows up in the output.
+const HIDDEN: &[&str] = &["head", "script", "style", "template", "title"];
+
+/// A text format made of blocks separated by blank lines, holding inline
+/// content with collapsed whitespace. The format decides how each block and
+/// inline element is written; walking the tree is shared.
+pub(crate) trait Renderer {
+    /// A block-level element on its own.
+    fn block(&self, node: &Node) -> String;
+
+    /// An element within inline content.
+    fn inline_element(&self, node: &Node) -> String;
+
+    /// Cleans up rendered inline content before it's used as a block.
+    fn tidy(&self, text: &str) -> String;
+
+    fn is_block(&self, node: &Node) -> bool {
+        is_block(node)
+    }
+
+    /// Renders a whole subtree, which may be a block, inline content, or a
+    /// document or fragment holding several blocks.
+    fn render(&self, node: &Node) -> String {
+        match &node.node_type {
+            NodeType::Element(_) if self.is_block(node) => self.block(node),
+            NodeType::Element(_) | NodeType::Text(_) => {
+                self.tidy(&self.inline(std::slice::from_ref(node)))
+            }
+            _ => self.blocks(node.children()),
+        }
+    }
+
+    /// Renders `nodes` as blocks separated by blank lines. Runs of inline
+    /// content between blocks become paragraphs.
+    fn blocks(&self, nodes: &[Node]) -> String {
+        let mut result: Vec<String> = vec![];
+        let mut run: Vec<&Node> = vec![];
+        let flush = |run: &mut Vec<&Node>, result: &mut Vec<String>| {
+            let paragraph = self.tidy(&self.inline_refs(run));
+            if !paragraph.is_empty() {
+                result.push(paragraph);
+            }
+            run.clear();
+        };
+        for node in nodes.iter().filter(|node| !is_hidden(node)) {
+            if self.is_block(node) {
+                flush(&mut run, &mut result);
+                let rendered = self.block(node);
+                if !rendered.is_empty() {
+                    result.push(rendered);
+                }
+            } else {
+                run.push(node);
+            }
+        }
+        flush(&mut run, &mut result);
+        result.join("\n\n")
+    }
+
+    /// One line per `<li>`, starting with `- ` or its number. Nested lists
+    /// and continuation lines are indented under their item.
+    fn list(&self, node: &Node, ordered: bool) -> String {
+        let mut lines = vec![];
+        let items = node
+            .children()
+            .iter()
+            .filter(|child| child.tag_name() == Some("li"));
+        for (index, item) in items.enumerate() {
+            let marker = if ordered {
+                format!("{}. ", index + 1)
+            } else {
+                "- ".to_string()
+            };
+            let (nested, content): (Vec<&Node>, Vec<&Node>) = item
+                .children()
+                .iter()
+                .partition(|child| matches!(child.tag_name(), Some("ul" | "ol")));
+            let indent = " ".repeat(marker.len());
+            let content = self
+                .tidy(&self.inline_refs(&content))
+                .replace('\n', &format!("\n{}", indent));
+            lines.push(format!("{}{}", marker, content));
+            for sublist in nested {
+                for line in self.block(sublist).lines() {
+                    lines.push(format!("{}{}", indent, line));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn inline(&self, nodes: &[Node]) -> String {
+        self.inline_refs(&nodes.iter().collect::<Vec<_>>())
+    }
+
+    fn inline_refs(&self, nodes: &[&Node]) -> String {
+        let mut result = String::new();
+        for node in nodes {
+            match &node.node_type {
+                NodeType::Text(text) | NodeType::CDATASection(text) => {
+                    push_collapsed(&mut result, text)
+                }
+                NodeType::Element(_) if is_hidden(node) => {}
+                NodeType::Element(_) => result.push_str(&self.inline_element(node)),
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
+/// Whether `node` is one of the elements every format renders as a block.
+pub(crate) fn is_block(node: &Node) -> bool {
+    node.tag_name().is_some_and(|tag| BLOCKS.contains(&tag))
+}
+
+fn is_hidden(node: &Node) -> bool {
+    node.tag_name().is_some_and(|tag| HIDDEN.contains(&tag))
+}
+
+/// Appends `text` with whitespace runs collapsed, without doubling up on a
+/// space already at the end of `result`.
+fn push_collapsed(result: &mut String, text: &str) {
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !result.is_empty() && !result.ends_with([' ', '\n']) {
+                result.push(' ');
+            }
+        } else {
+            result.push(c);
+        }
+    }
+}
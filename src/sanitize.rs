@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::dom::{document_fragment, element, text, AttrValue, DocumentData, Node, NodeType};
+
+/// Elements that are dropped along with their content rather than unwrapped.
+const DROP_WITH_CONTENT: &[&str] = &[
+    "embed", "iframe", "noscript", "object", "script", "style", "template",
+];
+
+/// Attributes holding a URL, which must use one of the allowed schemes.
+const URL_ATTRIBUTES: &[&str] = &["action", "cite", "formaction", "href", "poster", "src"];
+
+/// The tags, attributes and URL schemes that survive [`Node::sanitize`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Allowlist {
+    pub tags: HashSet<String>,
+    /// Allowed attributes per tag. Attributes under `"*"` are allowed on every tag.
+    pub attributes: HashMap<String, HashSet<String>>,
+    pub allowed_schemes: HashSet<String>,
+}
+
+impl Allowlist {
+    pub fn builder() -> AllowlistBuilder {
+        AllowlistBuilder::default()
+    }
+
+    /// Basic inline formatting and paragraphs, with links limited to
+    /// `http`, `https` and `mailto`.
+    pub fn basic() -> Self {
+        Self::builder()
+            .tags(&["a", "b", "i", "em", "strong", "p", "br"])
+            .attribute("a", "href")
+            .attribute("a", "title")
+            .schemes(&["http", "https", "mailto"])
+            .build()
+    }
+
+    fn allows_attribute(&self, tag: &str, name: &str) -> bool {
+        [tag, "*"].iter().any(|key| {
+            self.attributes
+                .get(*key)
+                .is_some_and(|names| names.contains(name))
+        })
+    }
+
+    /// Relative URLs are always allowed.
+    fn allows_url(&self, url: &str) -> bool {
+        url_scheme(url).is_none_or(|scheme| self.allowed_schemes.contains(&scheme))
+    }
+}
+
+/// The lowercased scheme of `url`, or `None` for a relative URL. Whitespace and
+/// control characters are ignored when looking for it, as browsers do.
+fn url_scheme(url: &str) -> Option<String> {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let scheme = &url[..url.find(':')?];
+    if scheme.contains(['/', '?', '#']) {
+        return None;
+    }
+    Some(scheme.to_ascii_lowercase())
+}
+
+#[derive(Debug, Default)]
+pub struct AllowlistBuilder {
+    allowlist: Allowlist,
+}
+
+impl AllowlistBuilder {
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.allowlist.tags.insert(tag.to_ascii_lowercase());
+        self
+    }
+
+    pub fn tags(self, tags: &[&str]) -> Self {
+        tags.iter().fold(self, |builder, tag| builder.tag(tag))
+    }
+
+    /// Allows `name` on `tag`, or on every tag when `tag` is `"*"`.
+    pub fn attribute(mut self, tag: &str, name: &str) -> Self {
+        self.allowlist
+            .attributes
+            .entry(tag.to_ascii_lowercase())
+            .or_default()
+            .insert(name.to_ascii_lowercase());
+        self
+    }
+
+    pub fn scheme(mut self, scheme: &str) -> Self {
+        self.allowlist
+            .allowed_schemes
+            .insert(scheme.to_ascii_lowercase());
+        self
+    }
+
+    pub fn schemes(self, schemes: &[&str]) -> Self {
+        schemes
+            .iter()
+            .fold(self, |builder, scheme| builder.scheme(scheme))
+    }
+
+    pub fn build(self) -> Allowlist {
+        self.allowlist
+    }
+}
+
+impl Node {
+    /// A copy of the tree containing only what `allowlist` permits. Disallowed
+    /// elements are replaced by their sanitized children, except for elements
+    /// such as `<script>` whose content is dropped too. Comments and processing
+    /// instructions are removed.
+    pub fn sanitize(&self, allowlist: &Allowlist) -> Node {
+        single_or_fragment(sanitize_node(self, allowlist))
+    }
+}
+
+impl Node {
+    /// A copy of the tree without `<script>` and `<style>` elements or `on*`
+    /// event handler attributes, and with `javascript:` URLs replaced by `#`.
+    pub fn strip_scripts_and_styles(&self) -> Node {
+        self.filter_map(|node| {
+            if matches!(node.tag_name(), Some("script" | "style")) {
+                return None;
+            }
+            let mut node = node.clone();
+            if let NodeType::Document(document) = &mut node.node_type {
+                document.stylesheets.clear();
+            }
+            if let Some(attributes) = node.attributes_mut() {
+                attributes
+                    .0
+                    .retain(|name, _| !name.to_ascii_lowercase().starts_with("on"));
+                for (name, value) in attributes.0.iter_mut() {
+                    if let AttrValue::Text(url) = value {
+                        if URL_ATTRIBUTES.contains(&&*name.to_ascii_lowercase())
+                            && url_scheme(url).as_deref() == Some("javascript")
+                        {
+                            *url = "#".to_string();
+                        }
+                    }
+                }
+            }
+            Some(node)
+        })
+        .unwrap_or_else(|| document_fragment(vec![]))
+    }
+}
+
+fn single_or_fragment(mut nodes: Vec<Node>) -> Node {
+    if nodes.len() == 1 {
+        nodes.remove(0)
+    } else {
+        document_fragment(nodes)
+    }
+}
+
+fn sanitize_children(node: &Node, allowlist: &Allowlist) -> Vec<Node> {
+    node.children()
+        .iter()
+        .flat_map(|child| sanitize_node(child, allowlist))
+        .collect()
+}
+
+fn sanitize_node(node: &Node, allowlist: &Allowlist) -> Vec<Node> {
+    match &node.node_type {
+        NodeType::Text(content) | NodeType::CDATASection(content) => {
+            vec![text(content.clone())]
+        }
+        NodeType::Element(_) => {
+            let tag = node.tag_name().unwrap().to_ascii_lowercase();
+            if DROP_WITH_CONTENT.contains(&&*tag) {
+                return vec![];
+            }
+            let children = sanitize_children(node, allowlist);
+            if !allowlist.tags.contains(&tag) {
+                return children;
+            }
+            let mut attributes = node.attributes().cloned().unwrap_or_default();
+            attributes.0.retain(|name, value| {
+                let name = name.to_ascii_lowercase();
+                allowlist.allows_attribute(&tag, &name)
+                    && match value {
+                        AttrValue::Text(url) if URL_ATTRIBUTES.contains(&&*name) => {
+                            allowlist.allows_url(url)
+                        }
+                        _ => true,
+                    }
+            });
+            vec![element(tag, attributes, children)]
+        }
+        NodeType::Document(_) => {
+            let mut document = DocumentData::new();
+            let children = sanitize_children(node, allowlist);
+            if !children.is_empty() {
+                document.root = Some(Box::new(single_or_fragment(children)));
+            }
+            vec![Node {
+                node_type: NodeType::Document(document),
+            }]
+        }
+        NodeType::DocumentFragment(_) => sanitize_children(node, allowlist),
+        NodeType::Comment(_) | NodeType::ProcessingInstruction { .. } => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_fragment;
+
+    fn sanitize(html: &str) -> String {
+        parse_fragment(html.into())
+            .unwrap()
+            .sanitize(&Allowlist::basic())
+            .outer_html()
+    }
+
+    #[test]
+    fn scripts_are_removed_with_their_content() {
+        assert_eq!(
+            sanitize("<p>a<script>alert(1)</script><style>p {}</style>b</p>"),
+            "<p>ab</p>"
+        );
+    }
+
+    #[test]
+    fn disallowed_tags_are_unwrapped() {
+        assert_eq!(
+            sanitize("<div><p>a <span>b</span></p><!-- c --></div>"),
+            "<p>a b</p>"
+        );
+    }
+
+    #[test]
+    fn disallowed_attributes_are_stripped() {
+        let node = parse_fragment(
+            "<p class=\"x\" onclick=\"evil()\"><a href=\"/a\" TITLE=\"t\" style=\"\">a</a></p>"
+                .into(),
+        )
+        .unwrap()
+        .sanitize(&Allowlist::basic());
+        assert_eq!(node.attributes().unwrap().0.len(), 0);
+        let link = &node.children()[0];
+        let mut names: Vec<&str> = link
+            .attributes()
+            .unwrap()
+            .0
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["TITLE", "href"]);
+
+        let allowlist = Allowlist::builder()
+            .tag("p")
+            .attribute("*", "class")
+            .build();
+        let node = parse_fragment("<p class=\"x\" id=\"y\">a</p>".into()).unwrap();
+        assert_eq!(
+            node.sanitize(&allowlist).outer_html(),
+            "<p class=\"x\">a</p>"
+        );
+    }
+
+    #[test]
+    fn hrefs_are_filtered_by_scheme() {
+        for (href, kept) in [
+            ("https://example.com", true),
+            ("mailto:a@example.com", true),
+            ("/relative?a:b", true),
+            ("javascript:alert(1)", false),
+            (" JaVa\tScript:alert(1)", false),
+            ("data:text/html,<p>", false),
+            ("vbscript:x", false),
+        ] {
+            let html = sanitize(&format!("<a href=\"{}\">x</a>", href));
+            assert_eq!(html.contains("href"), kept, "{}", href);
+        }
+    }
+
+    #[test]
+    fn strip_scripts_and_styles() {
+        let node = parse_fragment(
+            "<div><script>alert(1)</script><style>p {}</style>\
+             <button onclick=\"go()\" ONLOAD=\"x()\" type=\"submit\">Go</button>\
+             <a href=\"javascript:void(0)\" title=\"t\">a</a>\
+             <img src=\" JavaScript:x()\" alt=\"i\"><a href=\"/ok\">b</a></div>"
+                .into(),
+        )
+        .unwrap()
+        .strip_scripts_and_styles();
+        assert_eq!(node.select_all_by_tag("script").count(), 0);
+        assert_eq!(node.select_all_by_tag("style").count(), 0);
+
+        let button = node.select_first("button").unwrap();
+        assert_eq!(button.attr("onclick"), None);
+        assert_eq!(button.attr("ONLOAD"), None);
+        assert_eq!(button.attr("type"), Some("submit"));
+        assert_eq!(button.text_content(), "Go");
+
+        let links: Vec<&Node> = node.select_all_by_tag("a").collect();
+        assert_eq!(links[0].attr("href"), Some("#"));
+        assert_eq!(links[0].attr("title"), Some("t"));
+        assert_eq!(links[1].attr("href"), Some("/ok"));
+        let image = node.select_first("img").unwrap();
+        assert_eq!(image.attr("src"), Some("#"));
+        assert_eq!(image.attr("alt"), Some("i"));
+    }
+}
@@ -0,0 +1,1642 @@
+use crate::css::{CssLength, CssParseError, CssValue, Declaration, Unit};
+
+/// Declares a keyword-valued property type along with its CSS spelling.
+macro_rules! keyword_enum {
+    ($name:ident { $($variant:ident => $keyword:literal),+ $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant),+
+        }
+
+        impl $name {
+            pub fn from_keyword(keyword: &str) -> Option<Self> {
+                match &*keyword.to_ascii_lowercase() {
+                    $($keyword => Some(Self::$variant),)+
+                    _ => None,
+                }
+            }
+
+            pub fn as_keyword(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $keyword),+
+                }
+            }
+        }
+    };
+}
+
+keyword_enum!(FlexDirection {
+    Row => "row",
+    RowReverse => "row-reverse",
+    Column => "column",
+    ColumnReverse => "column-reverse",
+});
+
+keyword_enum!(FlexWrap {
+    NoWrap => "nowrap",
+    Wrap => "wrap",
+    WrapReverse => "wrap-reverse",
+});
+
+keyword_enum!(AlignItems {
+    Normal => "normal",
+    Stretch => "stretch",
+    FlexStart => "flex-start",
+    FlexEnd => "flex-end",
+    Start => "start",
+    End => "end",
+    Center => "center",
+    Baseline => "baseline",
+});
+
+keyword_enum!(AlignSelf {
+    Auto => "auto",
+    Normal => "normal",
+    Stretch => "stretch",
+    FlexStart => "flex-start",
+    FlexEnd => "flex-end",
+    Start => "start",
+    End => "end",
+    Center => "center",
+    Baseline => "baseline",
+});
+
+keyword_enum!(JustifyContent {
+    Normal => "normal",
+    FlexStart => "flex-start",
+    FlexEnd => "flex-end",
+    Start => "start",
+    End => "end",
+    Center => "center",
+    Left => "left",
+    Right => "right",
+    SpaceBetween => "space-between",
+    SpaceAround => "space-around",
+    SpaceEvenly => "space-evenly",
+});
+
+keyword_enum!(Overflow {
+    Visible => "visible",
+    Hidden => "hidden",
+    Scroll => "scroll",
+    Auto => "auto",
+    Clip => "clip",
+});
+
+keyword_enum!(ListStyleType {
+    Disc => "disc",
+    Circle => "circle",
+    Square => "square",
+    Decimal => "decimal",
+    LowerAlpha => "lower-alpha",
+    UpperAlpha => "upper-alpha",
+    LowerRoman => "lower-roman",
+    UpperRoman => "upper-roman",
+    None => "none",
+});
+
+keyword_enum!(ListStylePosition {
+    Inside => "inside",
+    Outside => "outside",
+});
+
+keyword_enum!(AnimationDirection {
+    Normal => "normal",
+    Reverse => "reverse",
+    Alternate => "alternate",
+    AlternateReverse => "alternate-reverse",
+});
+
+keyword_enum!(AnimationFillMode {
+    None => "none",
+    Forwards => "forwards",
+    Backwards => "backwards",
+    Both => "both",
+});
+
+keyword_enum!(CursorKeyword {
+    Auto => "auto",
+    Default => "default",
+    None => "none",
+    ContextMenu => "context-menu",
+    Help => "help",
+    Pointer => "pointer",
+    Progress => "progress",
+    Wait => "wait",
+    Cell => "cell",
+    Crosshair => "crosshair",
+    Text => "text",
+    VerticalText => "vertical-text",
+    Alias => "alias",
+    Copy => "copy",
+    Move => "move",
+    NoDrop => "no-drop",
+    NotAllowed => "not-allowed",
+    Grab => "grab",
+    Grabbing => "grabbing",
+    AllScroll => "all-scroll",
+    ColResize => "col-resize",
+    RowResize => "row-resize",
+    NResize => "n-resize",
+    EResize => "e-resize",
+    SResize => "s-resize",
+    WResize => "w-resize",
+    NeResize => "ne-resize",
+    NwResize => "nw-resize",
+    SeResize => "se-resize",
+    SwResize => "sw-resize",
+    EwResize => "ew-resize",
+    NsResize => "ns-resize",
+    NeswResize => "nesw-resize",
+    NwseResize => "nwse-resize",
+    ZoomIn => "zoom-in",
+    ZoomOut => "zoom-out",
+});
+
+keyword_enum!(StepPosition {
+    JumpStart => "jump-start",
+    JumpEnd => "jump-end",
+    JumpNone => "jump-none",
+    JumpBoth => "jump-both",
+    Start => "start",
+    End => "end",
+});
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingFunction {
+    Ease,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    StepStart,
+    StepEnd,
+    CubicBezier(f64, f64, f64, f64),
+    Steps(u32, StepPosition),
+}
+
+impl TimingFunction {
+    fn from_value(value: &CssValue) -> Option<Self> {
+        match value {
+            CssValue::Keyword(keyword) => match &*keyword.to_ascii_lowercase() {
+                "ease" => Some(Self::Ease),
+                "linear" => Some(Self::Linear),
+                "ease-in" => Some(Self::EaseIn),
+                "ease-out" => Some(Self::EaseOut),
+                "ease-in-out" => Some(Self::EaseInOut),
+                "step-start" => Some(Self::StepStart),
+                "step-end" => Some(Self::StepEnd),
+                _ => None,
+            },
+            CssValue::Function(name, args) => match (&*name.to_ascii_lowercase(), &args[..]) {
+                (
+                    "cubic-bezier",
+                    [CssValue::Number(x1), CssValue::Number(y1), CssValue::Number(x2), CssValue::Number(y2)],
+                ) if (0.0..=1.0).contains(x1) && (0.0..=1.0).contains(x2) => Some(
+                    Self::CubicBezier(*x1 as f64, *y1 as f64, *x2 as f64, *y2 as f64),
+                ),
+                ("steps", [CssValue::Number(count), rest @ ..])
+                    if *count >= 1.0 && count.fract() == 0.0 =>
+                {
+                    let position = match rest {
+                        [] => StepPosition::End,
+                        [position] => keyword(position).and_then(StepPosition::from_keyword)?,
+                        _ => return None,
+                    };
+                    Some(Self::Steps(*count as u32, position))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IterationCount {
+    Infinite,
+    Count(f64),
+}
+
+impl IterationCount {
+    fn from_value(value: &CssValue) -> Option<Self> {
+        match value {
+            CssValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("infinite") => {
+                Some(Self::Infinite)
+            }
+            CssValue::Number(count) if *count >= 0.0 => Some(Self::Count(*count as f64)),
+            _ => None,
+        }
+    }
+}
+
+/// A single entry of the `animation` property. Times are in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub name: String,
+    pub duration: f64,
+    pub timing: TimingFunction,
+    pub delay: f64,
+    pub iteration_count: IterationCount,
+    pub direction: AnimationDirection,
+    pub fill_mode: AnimationFillMode,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            name: "none".into(),
+            duration: 0.0,
+            timing: TimingFunction::Ease,
+            delay: 0.0,
+            iteration_count: IterationCount::Count(1.0),
+            direction: AnimationDirection::Normal,
+            fill_mode: AnimationFillMode::None,
+        }
+    }
+}
+
+impl Animation {
+    /// Parses one comma-separated entry of the `animation` shorthand. Each
+    /// component is assigned to the first property that accepts it and hasn't
+    /// been set yet, with the name tried last.
+    fn from_shorthand(value: &CssValue) -> Option<Self> {
+        let mut animation = Self::default();
+        let (mut duration, mut delay, mut timing, mut count) = (false, false, false, false);
+        let (mut direction, mut fill_mode, mut name) = (false, false, false);
+        for component in components(value) {
+            if let CssValue::Time(time) = component {
+                if !duration && *time >= 0.0 {
+                    animation.duration = *time as f64;
+                    duration = true;
+                } else if !delay {
+                    animation.delay = *time as f64;
+                    delay = true;
+                } else {
+                    return None;
+                }
+            } else if let Some(t) = TimingFunction::from_value(component).filter(|_| !timing) {
+                animation.timing = t;
+                timing = true;
+            } else if let Some(c) = IterationCount::from_value(component).filter(|_| !count) {
+                animation.iteration_count = c;
+                count = true;
+            } else if let Some(d) = keyword(component)
+                .and_then(AnimationDirection::from_keyword)
+                .filter(|_| !direction)
+            {
+                animation.direction = d;
+                direction = true;
+            } else if let Some(m) = keyword(component)
+                .and_then(AnimationFillMode::from_keyword)
+                .filter(|_| !fill_mode)
+            {
+                animation.fill_mode = m;
+                fill_mode = true;
+            } else if let Some(n) = keyword(component).filter(|_| !name) {
+                animation.name = n.to_string();
+                name = true;
+            } else {
+                return None;
+            }
+        }
+        Some(animation)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlexBasis {
+    Auto,
+    Content,
+    Length(CssLength),
+}
+
+/// A 2D transform function. Angles are in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform2D {
+    Rotate(f64),
+    Scale(f64, f64),
+    ScaleX(f64),
+    ScaleY(f64),
+    Translate(CssLength, CssLength),
+    TranslateX(CssLength),
+    TranslateY(CssLength),
+    /// Also used for `skewX()` and `skewY()`, with the other angle zero.
+    Skew(f64, f64),
+    Matrix(f64, f64, f64, f64, f64, f64),
+}
+
+impl Transform2D {
+    fn from_value(value: &CssValue) -> Option<Self> {
+        let CssValue::Function(name, args) = value else {
+            return None;
+        };
+        let numbers = || {
+            args.iter()
+                .map(|arg| match arg {
+                    CssValue::Number(number) => Some(*number as f64),
+                    _ => None,
+                })
+                .collect::<Option<Vec<f64>>>()
+        };
+        let angles = || args.iter().map(angle).collect::<Option<Vec<f64>>>();
+        let lengths = || args.iter().map(length).collect::<Option<Vec<CssLength>>>();
+        let transform = match &*name.to_ascii_lowercase() {
+            "rotate" => match angles()?[..] {
+                [angle] => Self::Rotate(angle),
+                _ => return None,
+            },
+            "scale" => match numbers()?[..] {
+                [scale] => Self::Scale(scale, scale),
+                [x, y] => Self::Scale(x, y),
+                _ => return None,
+            },
+            "scalex" => match numbers()?[..] {
+                [x] => Self::ScaleX(x),
+                _ => return None,
+            },
+            "scaley" => match numbers()?[..] {
+                [y] => Self::ScaleY(y),
+                _ => return None,
+            },
+            "translate" => match lengths()?[..] {
+                [x] => Self::Translate(x, ZERO_PX),
+                [x, y] => Self::Translate(x, y),
+                _ => return None,
+            },
+            "translatex" => match lengths()?[..] {
+                [x] => Self::TranslateX(x),
+                _ => return None,
+            },
+            "translatey" => match lengths()?[..] {
+                [y] => Self::TranslateY(y),
+                _ => return None,
+            },
+            "skew" => match angles()?[..] {
+                [x] => Self::Skew(x, 0.0),
+                [x, y] => Self::Skew(x, y),
+                _ => return None,
+            },
+            "skewx" => match angles()?[..] {
+                [x] => Self::Skew(x, 0.0),
+                _ => return None,
+            },
+            "skewy" => match angles()?[..] {
+                [y] => Self::Skew(0.0, y),
+                _ => return None,
+            },
+            "matrix" => match numbers()?[..] {
+                [a, b, c, d, e, f] => Self::Matrix(a, b, c, d, e, f),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(transform)
+    }
+}
+
+/// One entry of `counter-reset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterReset {
+    pub name: String,
+    pub initial: i32,
+}
+
+/// One entry of `counter-increment`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterIncrement {
+    pub name: String,
+    pub amount: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    pub flex_direction: FlexDirection,
+    pub flex_wrap: FlexWrap,
+    pub align_items: AlignItems,
+    pub align_self: AlignSelf,
+    pub justify_content: JustifyContent,
+    pub order: i32,
+    pub flex_grow: f32,
+    pub flex_shrink: f32,
+    pub flex_basis: FlexBasis,
+    pub list_style_type: ListStyleType,
+    pub list_style_position: ListStylePosition,
+    pub list_style_image: Option<String>,
+    pub animations: Vec<Animation>,
+    pub counter_reset: Vec<CounterReset>,
+    pub counter_increment: Vec<CounterIncrement>,
+    pub transform: Vec<Transform2D>,
+    pub overflow_x: Overflow,
+    pub overflow_y: Overflow,
+    /// The font size in pixels, with relative sizes resolved.
+    pub font_size: f32,
+    /// The font size of the parent, which `em`, `%`, `smaller` and `larger`
+    /// are relative to.
+    parent_font_size: f32,
+}
+
+/// The initial `font-size`, `medium`, in pixels. `rem` is relative to it.
+const MEDIUM_FONT_SIZE: f32 = 16.0;
+
+impl Default for ComputedStyle {
+    fn default() -> Self {
+        Self {
+            flex_direction: FlexDirection::Row,
+            flex_wrap: FlexWrap::NoWrap,
+            align_items: AlignItems::Normal,
+            align_self: AlignSelf::Auto,
+            justify_content: JustifyContent::Normal,
+            order: 0,
+            flex_grow: 0.0,
+            flex_shrink: 1.0,
+            flex_basis: FlexBasis::Auto,
+            list_style_type: ListStyleType::Disc,
+            list_style_position: ListStylePosition::Outside,
+            list_style_image: None,
+            animations: vec![],
+            counter_reset: vec![],
+            counter_increment: vec![],
+            transform: vec![],
+            overflow_x: Overflow::Visible,
+            overflow_y: Overflow::Visible,
+            font_size: MEDIUM_FONT_SIZE,
+            parent_font_size: MEDIUM_FONT_SIZE,
+        }
+    }
+}
+
+fn invalid(declaration: &Declaration) -> CssParseError {
+    CssParseError::InvalidValue {
+        property: declaration.name.clone(),
+        value: declaration.value.to_string(),
+    }
+}
+
+fn keyword(value: &CssValue) -> Option<&str> {
+    match value {
+        CssValue::Keyword(keyword) => Some(keyword),
+        _ => None,
+    }
+}
+
+/// The entries of a possibly comma-separated value.
+fn comma_components(value: &CssValue) -> &[CssValue] {
+    match value {
+        CssValue::CommaList(values) => values,
+        value => std::slice::from_ref(value),
+    }
+}
+
+/// The individual components of a possibly space-separated value.
+fn components(value: &CssValue) -> &[CssValue] {
+    match value {
+        CssValue::List(values) => values,
+        value => std::slice::from_ref(value),
+    }
+}
+
+fn parse_keyword<T>(
+    declaration: &Declaration,
+    from_keyword: fn(&str) -> Option<T>,
+) -> Result<T, CssParseError> {
+    keyword(&declaration.value)
+        .and_then(from_keyword)
+        .ok_or_else(|| invalid(declaration))
+}
+
+fn non_negative_number(value: &CssValue) -> Option<f32> {
+    match value {
+        CssValue::Number(number) if *number >= 0.0 => Some(*number),
+        _ => None,
+    }
+}
+
+/// An angle in degrees. Unitless zero is also accepted.
+fn angle(value: &CssValue) -> Option<f64> {
+    match value {
+        CssValue::Angle(angle) => Some(angle.0 as f64),
+        CssValue::Number(number) if *number == 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+/// A length, where unitless zero means `0px`.
+fn length(value: &CssValue) -> Option<CssLength> {
+    match value {
+        CssValue::Length(length) => Some(*length),
+        CssValue::Number(number) if *number == 0.0 => Some(ZERO_PX),
+        _ => None,
+    }
+}
+
+const ZERO_PX: CssLength = CssLength {
+    value: 0.0,
+    unit: Unit::Px,
+};
+
+/// A `font-size` in pixels. Relative sizes are resolved against `parent`,
+/// except `rem`, which is relative to the initial size as the root's own
+/// font size isn't known here.
+fn font_size(value: &CssValue, parent: f32) -> Option<f32> {
+    let size = match value {
+        CssValue::Keyword(keyword) => match &*keyword.to_ascii_lowercase() {
+            "xx-small" => 9.0,
+            "x-small" => 10.0,
+            "small" => 13.0,
+            "medium" => MEDIUM_FONT_SIZE,
+            "large" => 18.0,
+            "x-large" => 24.0,
+            "xx-large" => 32.0,
+            "xxx-large" => 48.0,
+            "smaller" => parent / 1.2,
+            "larger" => parent * 1.2,
+            _ => return None,
+        },
+        _ => {
+            let length = length(value)?;
+            match length.unit {
+                Unit::Px => length.value,
+                Unit::Em => length.value * parent,
+                Unit::Rem => length.value * MEDIUM_FONT_SIZE,
+                Unit::Percent => length.value / 100.0 * parent,
+            }
+        }
+    };
+    (size >= 0.0).then_some(size)
+}
+
+fn flex_basis(value: &CssValue) -> Option<FlexBasis> {
+    match value {
+        CssValue::Length(length) => Some(FlexBasis::Length(*length)),
+        CssValue::Number(number) if *number == 0.0 => Some(FlexBasis::Length(ZERO_PX)),
+        CssValue::Keyword(keyword) => match &*keyword.to_ascii_lowercase() {
+            "auto" => Some(FlexBasis::Auto),
+            "content" => Some(FlexBasis::Content),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+const ZERO_PERCENT: FlexBasis = FlexBasis::Length(CssLength {
+    value: 0.0,
+    unit: Unit::Percent,
+});
+
+/// Expands the `flex` shorthand into `(grow, shrink, basis)`.
+fn parse_flex(value: &CssValue) -> Option<(f32, f32, FlexBasis)> {
+    if let Some(keyword) = keyword(value) {
+        match &*keyword.to_ascii_lowercase() {
+            "none" => return Some((0.0, 0.0, FlexBasis::Auto)),
+            "auto" => return Some((1.0, 1.0, FlexBasis::Auto)),
+            "initial" => return Some((0.0, 1.0, FlexBasis::Auto)),
+            _ => {}
+        }
+    }
+    match components(value) {
+        [grow] => match non_negative_number(grow) {
+            Some(grow) => Some((grow, 1.0, ZERO_PERCENT)),
+            None => Some((1.0, 1.0, flex_basis(grow)?)),
+        },
+        [grow, second] => {
+            let grow = non_negative_number(grow)?;
+            match non_negative_number(second) {
+                Some(shrink) => Some((grow, shrink, ZERO_PERCENT)),
+                None => Some((grow, 1.0, flex_basis(second)?)),
+            }
+        }
+        [grow, shrink, basis] => Some((
+            non_negative_number(grow)?,
+            non_negative_number(shrink)?,
+            flex_basis(basis)?,
+        )),
+        _ => None,
+    }
+}
+
+/// `url(...)`, or `Some(None)` for `none`.
+fn image(value: &CssValue) -> Option<Option<String>> {
+    match value {
+        CssValue::Url(url) => Some(Some(url.clone())),
+        CssValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("none") => Some(None),
+        _ => None,
+    }
+}
+
+/// Expands the `list-style` shorthand, in which the components may appear in any order.
+fn parse_list_style(
+    value: &CssValue,
+) -> Option<(ListStyleType, ListStylePosition, Option<String>)> {
+    let mut style_type = None;
+    let mut position = None;
+    let mut image_url = None;
+    let mut nones = 0;
+    for component in components(value) {
+        let keyword = keyword(component);
+        if keyword.is_some_and(|k| k.eq_ignore_ascii_case("none")) {
+            nones += 1;
+        } else if let Some(t) = keyword.and_then(ListStyleType::from_keyword) {
+            if style_type.replace(t).is_some() {
+                return None;
+            }
+        } else if let Some(p) = keyword.and_then(ListStylePosition::from_keyword) {
+            if position.replace(p).is_some() {
+                return None;
+            }
+        } else if let CssValue::Url(url) = component {
+            if image_url.replace(url.clone()).is_some() {
+                return None;
+            }
+        } else {
+            return None;
+        }
+    }
+    // Each `none` applies to whichever of the type and image isn't otherwise given.
+    let unset = style_type.is_none() as usize + image_url.is_none() as usize;
+    if nones > unset {
+        return None;
+    }
+    if nones > 0 && style_type.is_none() {
+        style_type = Some(ListStyleType::None);
+    }
+    Some((
+        style_type.unwrap_or(ListStyleType::Disc),
+        position.unwrap_or(ListStylePosition::Outside),
+        image_url,
+    ))
+}
+
+/// Parses `none` or a list of counter names, each optionally followed by an
+/// integer. Names without one get `default`.
+fn parse_counters(value: &CssValue, default: i32) -> Option<Vec<(String, i32)>> {
+    if keyword(value).is_some_and(|k| k.eq_ignore_ascii_case("none")) {
+        return Some(vec![]);
+    }
+    let mut counters: Vec<(String, Option<i32>)> = vec![];
+    for component in components(value) {
+        match component {
+            CssValue::Keyword(name) => counters.push((name.clone(), None)),
+            CssValue::Number(number) if number.fract() == 0.0 => {
+                let (_, amount) = counters.last_mut()?;
+                if amount.replace(*number as i32).is_some() {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(
+        counters
+            .into_iter()
+            .map(|(name, amount)| (name, amount.unwrap_or(default)))
+            .collect(),
+    )
+}
+
+impl ComputedStyle {
+    /// Applies an `animation-*` longhand. `animation-name` decides how many
+    /// animations there are; other lists are repeated to cover all of them.
+    fn apply_animation_longhand<T>(
+        &mut self,
+        declaration: &Declaration,
+        parse: fn(&CssValue) -> Option<T>,
+        set: fn(&mut Animation, T),
+    ) -> Result<(), CssParseError>
+    where
+        T: Clone,
+    {
+        let values = comma_components(&declaration.value)
+            .iter()
+            .map(parse)
+            .collect::<Option<Vec<T>>>()
+            .ok_or_else(|| invalid(declaration))?;
+        if declaration.name.eq_ignore_ascii_case("animation-name")
+            || self.animations.len() < values.len()
+        {
+            self.animations
+                .resize_with(values.len(), Animation::default);
+        }
+        for (i, animation) in self.animations.iter_mut().enumerate() {
+            set(animation, values[i % values.len()].clone());
+        }
+        Ok(())
+    }
+
+    /// The starting style of an element whose parent has `parent`: inherited
+    /// properties take the parent's values, the rest their initial values.
+    pub fn inherited_from(parent: &ComputedStyle) -> Self {
+        Self {
+            list_style_type: parent.list_style_type,
+            list_style_position: parent.list_style_position,
+            list_style_image: parent.list_style_image.clone(),
+            font_size: parent.font_size,
+            parent_font_size: parent.font_size,
+            ..Self::default()
+        }
+    }
+
+    pub fn from_declarations<'a>(
+        declarations: impl IntoIterator<Item = &'a Declaration>,
+    ) -> Result<Self, CssParseError> {
+        let mut style = Self::default();
+        for declaration in declarations {
+            style.apply_declaration(declaration)?;
+        }
+        Ok(style)
+    }
+
+    /// Applies a single declaration. Properties that aren't modelled are ignored.
+    pub fn apply_declaration(&mut self, declaration: &Declaration) -> Result<(), CssParseError> {
+        let value = &declaration.value;
+        match &*declaration.name.to_ascii_lowercase() {
+            "flex" => {
+                let (grow, shrink, basis) =
+                    parse_flex(value).ok_or_else(|| invalid(declaration))?;
+                self.flex_grow = grow;
+                self.flex_shrink = shrink;
+                self.flex_basis = basis;
+            }
+            "flex-direction" => {
+                self.flex_direction = parse_keyword(declaration, FlexDirection::from_keyword)?
+            }
+            "flex-wrap" => self.flex_wrap = parse_keyword(declaration, FlexWrap::from_keyword)?,
+            "align-items" => {
+                self.align_items = parse_keyword(declaration, AlignItems::from_keyword)?
+            }
+            "align-self" => self.align_self = parse_keyword(declaration, AlignSelf::from_keyword)?,
+            "justify-content" => {
+                self.justify_content = parse_keyword(declaration, JustifyContent::from_keyword)?
+            }
+            "order" => match value {
+                CssValue::Number(number) if number.fract() == 0.0 => self.order = *number as i32,
+                _ => return Err(invalid(declaration)),
+            },
+            "flex-grow" => {
+                self.flex_grow = non_negative_number(value).ok_or_else(|| invalid(declaration))?
+            }
+            "flex-shrink" => {
+                self.flex_shrink = non_negative_number(value).ok_or_else(|| invalid(declaration))?
+            }
+            "flex-basis" => {
+                self.flex_basis = flex_basis(value).ok_or_else(|| invalid(declaration))?
+            }
+            "list-style" => {
+                let (style_type, position, image_url) =
+                    parse_list_style(value).ok_or_else(|| invalid(declaration))?;
+                self.list_style_type = style_type;
+                self.list_style_position = position;
+                self.list_style_image = image_url;
+            }
+            "list-style-type" => {
+                self.list_style_type = parse_keyword(declaration, ListStyleType::from_keyword)?
+            }
+            "list-style-position" => {
+                self.list_style_position =
+                    parse_keyword(declaration, ListStylePosition::from_keyword)?
+            }
+            "list-style-image" => {
+                self.list_style_image = image(value).ok_or_else(|| invalid(declaration))?
+            }
+            "animation" => {
+                self.animations = comma_components(value)
+                    .iter()
+                    .map(Animation::from_shorthand)
+                    .collect::<Option<Vec<Animation>>>()
+                    .ok_or_else(|| invalid(declaration))?;
+            }
+            "animation-name" => self.apply_animation_longhand(
+                declaration,
+                |v| keyword(v).map(String::from),
+                |a, name| a.name = name,
+            )?,
+            "animation-duration" => self.apply_animation_longhand(
+                declaration,
+                |v| match v {
+                    CssValue::Time(time) if *time >= 0.0 => Some(*time as f64),
+                    _ => None,
+                },
+                |a, duration| a.duration = duration,
+            )?,
+            "animation-timing-function" => self.apply_animation_longhand(
+                declaration,
+                TimingFunction::from_value,
+                |a, timing| a.timing = timing,
+            )?,
+            "animation-delay" => self.apply_animation_longhand(
+                declaration,
+                |v| match v {
+                    CssValue::Time(time) => Some(*time as f64),
+                    _ => None,
+                },
+                |a, delay| a.delay = delay,
+            )?,
+            "animation-iteration-count" => self.apply_animation_longhand(
+                declaration,
+                IterationCount::from_value,
+                |a, count| a.iteration_count = count,
+            )?,
+            "animation-direction" => self.apply_animation_longhand(
+                declaration,
+                |v| keyword(v).and_then(AnimationDirection::from_keyword),
+                |a, direction| a.direction = direction,
+            )?,
+            "animation-fill-mode" => self.apply_animation_longhand(
+                declaration,
+                |v| keyword(v).and_then(AnimationFillMode::from_keyword),
+                |a, fill_mode| a.fill_mode = fill_mode,
+            )?,
+            "transform" => {
+                self.transform = if keyword(value).is_some_and(|k| k.eq_ignore_ascii_case("none")) {
+                    vec![]
+                } else {
+                    components(value)
+                        .iter()
+                        .map(Transform2D::from_value)
+                        .collect::<Option<Vec<Transform2D>>>()
+                        .ok_or_else(|| invalid(declaration))?
+                }
+            }
+            "overflow" => {
+                let axes = components(value)
+                    .iter()
+                    .map(|v| keyword(v).and_then(Overflow::from_keyword))
+                    .collect::<Option<Vec<Overflow>>>();
+                (self.overflow_x, self.overflow_y) = match axes.as_deref() {
+                    Some(&[both]) => (both, both),
+                    Some(&[x, y]) => (x, y),
+                    _ => return Err(invalid(declaration)),
+                };
+            }
+            "font-size" => {
+                self.font_size =
+                    font_size(value, self.parent_font_size).ok_or_else(|| invalid(declaration))?
+            }
+            "overflow-x" => self.overflow_x = parse_keyword(declaration, Overflow::from_keyword)?,
+            "overflow-y" => self.overflow_y = parse_keyword(declaration, Overflow::from_keyword)?,
+            "counter-reset" => {
+                self.counter_reset = parse_counters(value, 0)
+                    .ok_or_else(|| invalid(declaration))?
+                    .into_iter()
+                    .map(|(name, initial)| CounterReset { name, initial })
+                    .collect()
+            }
+            "counter-increment" => {
+                self.counter_increment = parse_counters(value, 1)
+                    .ok_or_else(|| invalid(declaration))?
+                    .into_iter()
+                    .map(|(name, amount)| CounterIncrement { name, amount })
+                    .collect()
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn keyword_value(keyword: &str) -> CssValue {
+    CssValue::Keyword(keyword.to_string())
+}
+
+/// A single value as is, or several as a comma-separated list.
+fn comma_list(mut values: Vec<CssValue>) -> CssValue {
+    if values.len() == 1 {
+        values.swap_remove(0)
+    } else {
+        CssValue::CommaList(values)
+    }
+}
+
+impl TimingFunction {
+    fn to_value(self) -> CssValue {
+        match self {
+            Self::Ease => keyword_value("ease"),
+            Self::Linear => keyword_value("linear"),
+            Self::EaseIn => keyword_value("ease-in"),
+            Self::EaseOut => keyword_value("ease-out"),
+            Self::EaseInOut => keyword_value("ease-in-out"),
+            Self::StepStart => keyword_value("step-start"),
+            Self::StepEnd => keyword_value("step-end"),
+            Self::CubicBezier(x1, y1, x2, y2) => CssValue::Function(
+                "cubic-bezier".to_string(),
+                [x1, y1, x2, y2]
+                    .map(|n| CssValue::Number(n as f32))
+                    .to_vec(),
+            ),
+            Self::Steps(count, position) => CssValue::Function(
+                "steps".to_string(),
+                vec![
+                    CssValue::Number(count as f32),
+                    keyword_value(position.as_keyword()),
+                ],
+            ),
+        }
+    }
+}
+
+impl IterationCount {
+    fn to_value(self) -> CssValue {
+        match self {
+            Self::Infinite => keyword_value("infinite"),
+            Self::Count(count) => CssValue::Number(count as f32),
+        }
+    }
+}
+
+const ANIMATION_LONGHANDS: [&str; 7] = [
+    "animation-name",
+    "animation-duration",
+    "animation-timing-function",
+    "animation-delay",
+    "animation-iteration-count",
+    "animation-direction",
+    "animation-fill-mode",
+];
+
+impl Animation {
+    /// The values of the [`ANIMATION_LONGHANDS`], in that order.
+    fn longhand_values(&self) -> [CssValue; 7] {
+        [
+            keyword_value(&self.name),
+            CssValue::Time(self.duration as f32),
+            self.timing.to_value(),
+            CssValue::Time(self.delay as f32),
+            self.iteration_count.to_value(),
+            keyword_value(self.direction.as_keyword()),
+            keyword_value(self.fill_mode.as_keyword()),
+        ]
+    }
+}
+
+const TRANSITION_LONGHANDS: [&str; 4] = [
+    "transition-property",
+    "transition-duration",
+    "transition-timing-function",
+    "transition-delay",
+];
+
+/// Parses one comma-separated entry of the `transition` shorthand into the
+/// values of the [`TRANSITION_LONGHANDS`]. The first time is the duration.
+fn transition_values(value: &CssValue) -> Option<[CssValue; 4]> {
+    let (mut property, mut duration, mut timing, mut delay) = (None, None, None, None);
+    for component in components(value) {
+        let slot = if let CssValue::Time(_) = component {
+            if duration.is_none() {
+                &mut duration
+            } else {
+                &mut delay
+            }
+        } else if TimingFunction::from_value(component).is_some() {
+            &mut timing
+        } else if keyword(component).is_some() {
+            &mut property
+        } else {
+            return None;
+        };
+        if slot.replace(component.clone()).is_some() {
+            return None;
+        }
+    }
+    Some([
+        property.unwrap_or_else(|| keyword_value("all")),
+        duration.unwrap_or(CssValue::Time(0.0)),
+        timing.unwrap_or_else(|| keyword_value("ease")),
+        delay.unwrap_or(CssValue::Time(0.0)),
+    ])
+}
+
+/// One comma-separated longhand per name, listing that value of each entry.
+fn comma_longhands<const N: usize>(
+    names: [&str; N],
+    entries: &[[CssValue; N]],
+) -> Vec<(String, CssValue)> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let values = entries.iter().map(|entry| entry[i].clone()).collect();
+            (name.to_string(), comma_list(values))
+        })
+        .collect()
+}
+
+/// Expands `margin` or `padding` from one to four values, clockwise from the top.
+fn box_sides(property: &str, value: &CssValue) -> Option<Vec<(String, CssValue)>> {
+    let sides = match components(value) {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return None,
+    };
+    let valid = |side: &CssValue| {
+        length(side).is_some()
+            || property == "margin" && keyword(side).is_some_and(|k| k.eq_ignore_ascii_case("auto"))
+    };
+    if !sides.into_iter().all(valid) {
+        return None;
+    }
+    Some(
+        ["top", "right", "bottom", "left"]
+            .iter()
+            .zip(sides)
+            .map(|(side, value)| (format!("{}-{}", property, side), value.clone()))
+            .collect(),
+    )
+}
+
+const LINE_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// Expands `border` or `outline` into width, style and color, which may appear
+/// in any order.
+fn line_longhands(property: &str, value: &CssValue) -> Option<Vec<(String, CssValue)>> {
+    let (mut width, mut style, mut color) = (None, None, None);
+    for component in components(value) {
+        let keyword = keyword(component).map(str::to_ascii_lowercase);
+        let slot = if length(component).is_some()
+            || matches!(keyword.as_deref(), Some("thin" | "medium" | "thick"))
+        {
+            &mut width
+        } else if keyword.as_deref().is_some_and(|k| LINE_STYLES.contains(&k)) {
+            &mut style
+        } else if matches!(
+            component,
+            CssValue::Color(_) | CssValue::Keyword(_) | CssValue::Function(..)
+        ) {
+            &mut color
+        } else {
+            return None;
+        };
+        if slot.replace(component.clone()).is_some() {
+            return None;
+        }
+    }
+    Some(vec![
+        (
+            format!("{}-width", property),
+            width.unwrap_or_else(|| keyword_value("medium")),
+        ),
+        (
+            format!("{}-style", property),
+            style.unwrap_or_else(|| keyword_value("none")),
+        ),
+        (
+            format!("{}-color", property),
+            color.unwrap_or_else(|| keyword_value("currentcolor")),
+        ),
+    ])
+}
+
+/// Expands a single-layer `background`. Keywords that aren't otherwise
+/// recognised are taken to be the color.
+fn background_longhands(value: &CssValue) -> Option<Vec<(String, CssValue)>> {
+    let (mut color, mut image, mut repeat, mut attachment) = (None, None, None, None);
+    let mut position = vec![];
+    for component in components(value) {
+        let keyword = keyword(component).map(str::to_ascii_lowercase);
+        let slot = match (component, keyword.as_deref()) {
+            (CssValue::Url(_) | CssValue::Gradient(_), _) | (_, Some("none")) => &mut image,
+            (_, Some("repeat" | "repeat-x" | "repeat-y" | "no-repeat" | "space" | "round")) => {
+                &mut repeat
+            }
+            (_, Some("scroll" | "fixed" | "local")) => &mut attachment,
+            (_, Some("left" | "right" | "top" | "bottom" | "center")) => {
+                position.push(component.clone());
+                continue;
+            }
+            _ if length(component).is_some() => {
+                position.push(component.clone());
+                continue;
+            }
+            (CssValue::Color(_) | CssValue::Keyword(_) | CssValue::Function(..), _) => &mut color,
+            _ => return None,
+        };
+        if slot.replace(component.clone()).is_some() {
+            return None;
+        }
+    }
+    let position = match position.len() {
+        0 => CssValue::List(vec![
+            CssValue::Length(CssLength {
+                value: 0.0,
+                unit: Unit::Percent,
+            });
+            2
+        ]),
+        1 => position.swap_remove(0),
+        2..=4 => CssValue::List(position),
+        _ => return None,
+    };
+    Some(vec![
+        (
+            "background-color".to_string(),
+            color.unwrap_or_else(|| keyword_value("transparent")),
+        ),
+        (
+            "background-image".to_string(),
+            image.unwrap_or_else(|| keyword_value("none")),
+        ),
+        (
+            "background-repeat".to_string(),
+            repeat.unwrap_or_else(|| keyword_value("repeat")),
+        ),
+        (
+            "background-attachment".to_string(),
+            attachment.unwrap_or_else(|| keyword_value("scroll")),
+        ),
+        ("background-position".to_string(), position),
+    ])
+}
+
+const FONT_SIZE_KEYWORDS: &[&str] = &[
+    "xx-small",
+    "x-small",
+    "small",
+    "medium",
+    "large",
+    "x-large",
+    "xx-large",
+    "xxx-large",
+    "smaller",
+    "larger",
+];
+
+/// Expands `font`: optional style, variant and weight, then the size and the
+/// family. A `/line-height` isn't supported by the parser, so the line height
+/// is always reset to `normal`.
+fn font_longhands(value: &CssValue) -> Option<Vec<(String, CssValue)>> {
+    let (first, other_families) = comma_components(value).split_first()?;
+    let first = components(first);
+    let size_index = first.iter().position(|component| {
+        length(component).is_some()
+            || keyword(component)
+                .is_some_and(|k| FONT_SIZE_KEYWORDS.contains(&&*k.to_ascii_lowercase()))
+    })?;
+    let (prefix, rest) = first.split_at(size_index);
+    let (size, family) = rest.split_first()?;
+    if family.is_empty() {
+        return None;
+    }
+    let (mut style, mut variant, mut weight) = (None, None, None);
+    for component in prefix {
+        let keyword = keyword(component).map(str::to_ascii_lowercase);
+        let slot = match (component, keyword.as_deref()) {
+            (_, Some("normal")) => continue,
+            (_, Some("italic" | "oblique")) => &mut style,
+            (_, Some("small-caps")) => &mut variant,
+            (_, Some("bold" | "bolder" | "lighter")) => &mut weight,
+            (CssValue::Number(n), _) if (1.0..=1000.0).contains(n) => &mut weight,
+            _ => return None,
+        };
+        if slot.replace(component.clone()).is_some() {
+            return None;
+        }
+    }
+    let family = match family {
+        [single] => single.clone(),
+        words => CssValue::List(words.to_vec()),
+    };
+    let families = std::iter::once(family)
+        .chain(other_families.iter().cloned())
+        .collect();
+    Some(vec![
+        (
+            "font-style".to_string(),
+            style.unwrap_or_else(|| keyword_value("normal")),
+        ),
+        (
+            "font-variant".to_string(),
+            variant.unwrap_or_else(|| keyword_value("normal")),
+        ),
+        (
+            "font-weight".to_string(),
+            weight.unwrap_or_else(|| keyword_value("normal")),
+        ),
+        ("font-size".to_string(), size.clone()),
+        ("line-height".to_string(), keyword_value("normal")),
+        ("font-family".to_string(), comma_list(families)),
+    ])
+}
+
+fn flex_basis_value(basis: FlexBasis) -> CssValue {
+    match basis {
+        FlexBasis::Auto => keyword_value("auto"),
+        FlexBasis::Content => keyword_value("content"),
+        FlexBasis::Length(length) => CssValue::Length(length),
+    }
+}
+
+impl Declaration {
+    /// The longhand declarations this one stands for, each with the same
+    /// `!important` flag. Anything that isn't a shorthand comes back unchanged.
+    pub fn expand_shorthand(&self) -> Result<Vec<Declaration>, CssParseError> {
+        let value = &self.value;
+        let longhands = match &*self.name.to_ascii_lowercase() {
+            property @ ("margin" | "padding") => box_sides(property, value),
+            property @ ("border" | "outline") => line_longhands(property, value),
+            "background" => background_longhands(value),
+            "font" => font_longhands(value),
+            "flex" => parse_flex(value).map(|(grow, shrink, basis)| {
+                vec![
+                    ("flex-grow".to_string(), CssValue::Number(grow)),
+                    ("flex-shrink".to_string(), CssValue::Number(shrink)),
+                    ("flex-basis".to_string(), flex_basis_value(basis)),
+                ]
+            }),
+            "animation" => comma_components(value)
+                .iter()
+                .map(|entry| Some(Animation::from_shorthand(entry)?.longhand_values()))
+                .collect::<Option<Vec<_>>>()
+                .map(|entries| comma_longhands(ANIMATION_LONGHANDS, &entries)),
+            "transition" => comma_components(value)
+                .iter()
+                .map(transition_values)
+                .collect::<Option<Vec<_>>>()
+                .map(|entries| comma_longhands(TRANSITION_LONGHANDS, &entries)),
+            "list-style" => parse_list_style(value).map(|(style_type, position, image_url)| {
+                vec![
+                    (
+                        "list-style-type".to_string(),
+                        keyword_value(style_type.as_keyword()),
+                    ),
+                    (
+                        "list-style-position".to_string(),
+                        keyword_value(position.as_keyword()),
+                    ),
+                    (
+                        "list-style-image".to_string(),
+                        image_url.map_or_else(|| keyword_value("none"), CssValue::Url),
+                    ),
+                ]
+            }),
+            _ => return Ok(vec![self.clone()]),
+        };
+        Ok(longhands
+            .ok_or_else(|| invalid(self))?
+            .into_iter()
+            .map(|(name, value)| Declaration {
+                name,
+                value,
+                important: self.important,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::parse_inline;
+
+    fn style(css: &str) -> Result<ComputedStyle, CssParseError> {
+        ComputedStyle::from_declarations(&parse_inline(css).unwrap())
+    }
+
+    fn percent(value: f32) -> FlexBasis {
+        FlexBasis::Length(CssLength {
+            value,
+            unit: Unit::Percent,
+        })
+    }
+
+    #[test]
+    fn flex_shorthand() {
+        let flex = |css| {
+            let style = style(css).unwrap();
+            (style.flex_grow, style.flex_shrink, style.flex_basis)
+        };
+        assert_eq!(flex("flex: 1"), (1.0, 1.0, percent(0.0)));
+        assert_eq!(flex("flex: none"), (0.0, 0.0, FlexBasis::Auto));
+        assert_eq!(flex("flex: auto"), (1.0, 1.0, FlexBasis::Auto));
+        assert_eq!(flex("flex: 1 1 auto"), (1.0, 1.0, FlexBasis::Auto));
+        assert_eq!(flex("flex: 2 3"), (2.0, 3.0, percent(0.0)));
+        assert_eq!(flex("flex: 2 50%"), (2.0, 1.0, percent(50.0)));
+    }
+
+    #[test]
+    fn flexbox_longhands() {
+        let style = style(
+            "flex-direction: column; flex-wrap: wrap-reverse; align-items: center; \
+             align-self: baseline; justify-content: space-between; order: -2; \
+             flex-grow: 3; flex-shrink: 0; flex-basis: content",
+        )
+        .unwrap();
+        assert_eq!(style.flex_direction, FlexDirection::Column);
+        assert_eq!(style.flex_wrap, FlexWrap::WrapReverse);
+        assert_eq!(style.align_items, AlignItems::Center);
+        assert_eq!(style.align_self, AlignSelf::Baseline);
+        assert_eq!(style.justify_content, JustifyContent::SpaceBetween);
+        assert_eq!(style.order, -2);
+        assert_eq!(style.flex_grow, 3.0);
+        assert_eq!(style.flex_shrink, 0.0);
+        assert_eq!(style.flex_basis, FlexBasis::Content);
+    }
+
+    #[test]
+    fn invalid_flex_values_are_errors() {
+        for css in [
+            "flex: -1",
+            "flex: 1 2 3 4",
+            "flex: sideways",
+            "flex-direction: diagonal",
+            "justify-content: between",
+            "order: 1.5",
+            "flex-grow: -1",
+            "flex-basis: big",
+        ] {
+            assert!(
+                matches!(style(css), Err(CssParseError::InvalidValue { .. })),
+                "{}",
+                css
+            );
+        }
+    }
+
+    #[test]
+    fn list_style_type_values() {
+        for (css, expected) in [
+            ("disc", ListStyleType::Disc),
+            ("circle", ListStyleType::Circle),
+            ("square", ListStyleType::Square),
+            ("decimal", ListStyleType::Decimal),
+            ("lower-alpha", ListStyleType::LowerAlpha),
+            ("upper-alpha", ListStyleType::UpperAlpha),
+            ("lower-roman", ListStyleType::LowerRoman),
+            ("UPPER-ROMAN", ListStyleType::UpperRoman),
+            ("none", ListStyleType::None),
+        ] {
+            let style = style(&format!("list-style-type: {}", css)).unwrap();
+            assert_eq!(style.list_style_type, expected);
+        }
+        assert!(style("list-style-type: hebrew").is_err());
+    }
+
+    #[test]
+    fn list_style_shorthand() {
+        let list_style = |css| {
+            let style = style(css).unwrap();
+            (
+                style.list_style_type,
+                style.list_style_position,
+                style.list_style_image,
+            )
+        };
+        assert_eq!(
+            list_style("list-style: square inside url(\"dot.png\")"),
+            (
+                ListStyleType::Square,
+                ListStylePosition::Inside,
+                Some("dot.png".to_string())
+            )
+        );
+        assert_eq!(
+            list_style("list-style: none"),
+            (ListStyleType::None, ListStylePosition::Outside, None)
+        );
+        assert_eq!(
+            list_style("list-style-image: url(bullet.svg)").2,
+            Some("bullet.svg".to_string())
+        );
+        assert!(style("list-style: square circle").is_err());
+        assert!(style("list-style: sideways").is_err());
+        assert!(style("list-style-image: square").is_err());
+    }
+
+    #[test]
+    fn list_style_image_is_a_url_value() {
+        let declarations = parse_inline("list-style-image: url(bullet.svg)").unwrap();
+        assert_eq!(declarations[0].value, CssValue::Url("bullet.svg".into()));
+    }
+
+    #[test]
+    fn animation_shorthand_with_name_and_duration() {
+        let style = style("animation: fade 2s").unwrap();
+        assert_eq!(
+            style.animations,
+            vec![Animation {
+                name: "fade".into(),
+                duration: 2.0,
+                ..Animation::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn animation_shorthand_with_all_components() {
+        let style =
+            style("animation: slide-in 0.5s ease-in-out 1s infinite alternate forwards").unwrap();
+        assert_eq!(
+            style.animations,
+            vec![Animation {
+                name: "slide-in".into(),
+                duration: 0.5,
+                timing: TimingFunction::EaseInOut,
+                delay: 1.0,
+                iteration_count: IterationCount::Infinite,
+                direction: AnimationDirection::Alternate,
+                fill_mode: AnimationFillMode::Forwards,
+            }]
+        );
+        assert!(self::style("animation: a 1s 2s 3s").is_err());
+    }
+
+    #[test]
+    fn animation_longhands() {
+        let style = style(
+            "animation-name: a, b; animation-duration: 1s; \
+             animation-timing-function: steps(4, jump-start); animation-delay: -1s; \
+             animation-iteration-count: 2.5; animation-direction: reverse; \
+             animation-fill-mode: both",
+        )
+        .unwrap();
+        let expected = |name: &str| Animation {
+            name: name.into(),
+            duration: 1.0,
+            timing: TimingFunction::Steps(4, StepPosition::JumpStart),
+            delay: -1.0,
+            iteration_count: IterationCount::Count(2.5),
+            direction: AnimationDirection::Reverse,
+            fill_mode: AnimationFillMode::Both,
+        };
+        assert_eq!(style.animations, vec![expected("a"), expected("b")]);
+    }
+
+    #[test]
+    fn counter_reset_and_increment() {
+        let style = style("counter-reset: chapter 1 page; counter-increment: section -1").unwrap();
+        assert_eq!(
+            style.counter_reset,
+            [
+                CounterReset {
+                    name: "chapter".into(),
+                    initial: 1
+                },
+                CounterReset {
+                    name: "page".into(),
+                    initial: 0
+                }
+            ]
+        );
+        assert_eq!(
+            style.counter_increment,
+            [CounterIncrement {
+                name: "section".into(),
+                amount: -1
+            }]
+        );
+        let style = self::style("counter-increment: subsection").unwrap();
+        assert_eq!(style.counter_increment[0].amount, 1);
+        assert!(self::style("counter-reset: none")
+            .unwrap()
+            .counter_reset
+            .is_empty());
+        assert!(self::style("counter-reset: 1 chapter").is_err());
+        assert!(self::style("counter-increment: a 1.5").is_err());
+    }
+
+    fn px(value: f32) -> CssLength {
+        CssLength {
+            value,
+            unit: Unit::Px,
+        }
+    }
+
+    fn transform(css: &str) -> Vec<Transform2D> {
+        style(&format!("transform: {}", css)).unwrap().transform
+    }
+
+    #[test]
+    fn transform_functions() {
+        use Transform2D::*;
+        for (css, expected) in [
+            ("rotate(45deg)", Rotate(45.0)),
+            ("rotate(0)", Rotate(0.0)),
+            ("scale(2)", Scale(2.0, 2.0)),
+            ("scale(2, 0.5)", Scale(2.0, 0.5)),
+            ("scaleX(3)", ScaleX(3.0)),
+            ("scaleY(-1)", ScaleY(-1.0)),
+            ("translate(10px)", Translate(px(10.0), px(0.0))),
+            (
+                "translate(10px, 2em)",
+                Translate(
+                    px(10.0),
+                    CssLength {
+                        value: 2.0,
+                        unit: Unit::Em,
+                    },
+                ),
+            ),
+            ("translateX(-5px)", TranslateX(px(-5.0))),
+            ("translateY(0)", TranslateY(px(0.0))),
+            ("skew(10deg, 20deg)", Skew(10.0, 20.0)),
+            ("skewX(10deg)", Skew(10.0, 0.0)),
+            ("skewY(20deg)", Skew(0.0, 20.0)),
+            (
+                "matrix(1, 0, 0, 1, 5, 6)",
+                Matrix(1.0, 0.0, 0.0, 1.0, 5.0, 6.0),
+            ),
+        ] {
+            assert_eq!(transform(css), [expected], "{}", css);
+        }
+    }
+
+    #[test]
+    fn transform_lists() {
+        assert_eq!(
+            transform("rotate(45deg) scale(2)"),
+            [Transform2D::Rotate(45.0), Transform2D::Scale(2.0, 2.0)]
+        );
+        assert_eq!(transform("none"), []);
+    }
+
+    #[test]
+    fn invalid_transform_arguments_are_errors() {
+        for css in [
+            "rotate(45px)",
+            "rotate(45)",
+            "scale(2px)",
+            "scale(1, 2, 3)",
+            "translate(10deg)",
+            "matrix(1, 0, 0, 1)",
+            "spin(1deg)",
+            "rotate(45deg) bogus",
+        ] {
+            assert!(style(&format!("transform: {}", css)).is_err(), "{}", css);
+        }
+    }
+
+    #[test]
+    fn overflow_shorthand_and_longhands() {
+        let overflow = |css| {
+            let style = style(css).unwrap();
+            (style.overflow_x, style.overflow_y)
+        };
+        assert_eq!(
+            overflow("overflow: hidden scroll"),
+            (Overflow::Hidden, Overflow::Scroll)
+        );
+        assert_eq!(overflow("overflow: auto"), (Overflow::Auto, Overflow::Auto));
+        assert_eq!(
+            overflow("overflow: clip; overflow-y: visible"),
+            (Overflow::Clip, Overflow::Visible)
+        );
+        assert_eq!(
+            overflow("overflow-x: scroll"),
+            (Overflow::Scroll, Overflow::Visible)
+        );
+        for css in [
+            "overflow: sideways",
+            "overflow: hidden scroll auto",
+            "overflow-x: hidden scroll",
+            "overflow-y: 1px",
+        ] {
+            assert!(style(css).is_err(), "{}", css);
+        }
+    }
+
+    #[test]
+    fn font_size() {
+        let size = |css: &str| style(css).map(|style| style.font_size);
+        assert_eq!(size(""), Ok(16.0));
+        assert_eq!(size("font-size: 12px"), Ok(12.0));
+        assert_eq!(size("font-size: 0"), Ok(0.0));
+        assert_eq!(size("font-size: 2em"), Ok(32.0));
+        assert_eq!(size("font-size: 150%"), Ok(24.0));
+        assert_eq!(size("font-size: 1.5rem"), Ok(24.0));
+        assert_eq!(size("font-size: X-Large"), Ok(24.0));
+        assert_eq!(size("font-size: small"), Ok(13.0));
+        // A later declaration replaces an earlier one rather than scaling it.
+        assert_eq!(size("font-size: 2em; font-size: 2em"), Ok(32.0));
+        for invalid in ["font-size: -1px", "font-size: huge", "font-size: 3"] {
+            assert!(
+                matches!(size(invalid), Err(CssParseError::InvalidValue { .. })),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn font_size_is_inherited_and_relative_to_the_parent() {
+        let parent = style("font-size: 20px").unwrap();
+        let mut child = ComputedStyle::inherited_from(&parent);
+        assert_eq!(child.font_size, 20.0);
+        for (css, expected) in [
+            ("font-size: 2em", 40.0),
+            ("font-size: 50%", 10.0),
+            ("font-size: 2rem", 32.0),
+            ("font-size: larger", 24.0),
+            ("font-size: smaller", 20.0 / 1.2),
+            ("font-size: 9px", 9.0),
+        ] {
+            for declaration in parse_inline(css).unwrap() {
+                child.apply_declaration(&declaration).unwrap();
+            }
+            assert_eq!(child.font_size, expected, "{}", css);
+        }
+        let grandchild = ComputedStyle::inherited_from(&child);
+        assert_eq!(grandchild.font_size, 9.0);
+    }
+}
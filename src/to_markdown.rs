@@ -0,0 +1,134 @@
+use crate::{dom::Node, render::Renderer};
+
+struct Markdown;
+
+impl Node {
+    /// Converts the subtree to Markdown. Elements without a Markdown
+    /// equivalent contribute their text content.
+    pub fn to_markdown(&self) -> String {
+        Markdown.render(self)
+    }
+}
+
+impl Renderer for Markdown {
+    fn block(&self, node: &Node) -> String {
+        let tag = node.tag_name().unwrap_or_default();
+        match tag {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = tag[1..].parse().unwrap_or(1);
+                format!(
+                    "{} {}",
+                    "#".repeat(level),
+                    self.tidy(&self.inline(node.children()))
+                )
+            }
+            "p" => self.tidy(&self.inline(node.children())),
+            "ul" => self.list(node, false),
+            "ol" => self.list(node, true),
+            "pre" => format!(
+                "```\n{}\n```",
+                node.text_content().trim_matches('\n').trim_end()
+            ),
+            "blockquote" => self
+                .blocks(node.children())
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {}", line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => self.blocks(node.children()),
+        }
+    }
+
+    fn inline_element(&self, node: &Node) -> String {
+        let content = || self.tidy(&self.inline(node.children()));
+        match node.tag_name().unwrap_or_default() {
+            "strong" | "b" => format!("**{}**", content()),
+            "em" | "i" => format!("*{}*", content()),
+            "code" => format!("`{}`", node.text_content()),
+            "a" => match node.attr("href") {
+                Some(href) => format!("[{}]({})", content(), href),
+                None => content(),
+            },
+            "img" => format!(
+                "![{}]({})",
+                node.attr("alt").unwrap_or_default(),
+                node.attr("src").unwrap_or_default()
+            ),
+            "br" => "  \n".to_string(),
+            _ if self.is_block(node) => self.block(node),
+            _ => self.inline(node.children()),
+        }
+    }
+
+    fn tidy(&self, text: &str) -> String {
+        text.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::parse_fragment;
+
+    fn markdown(html: &str) -> String {
+        parse_fragment(html.into()).unwrap().to_markdown()
+    }
+
+    #[test]
+    fn headings_and_paragraphs() {
+        assert_eq!(
+            markdown("<h1>Title</h1><p>First\n paragraph.</p><h2>Sub</h2><p>Second.</p>"),
+            "# Title\n\nFirst paragraph.\n\n## Sub\n\nSecond."
+        );
+    }
+
+    #[test]
+    fn inline_elements() {
+        assert_eq!(
+            markdown(
+                "<p>Some <strong>strong</strong>, <b>bold</b>, <em>em</em>, <i>it</i>, \
+                 <code>a &lt; b</code>, <a href=\"/x\">a <b>link</b></a>.</p>"
+            ),
+            "Some **strong**, **bold**, *em*, *it*, `a < b`, [a **link**](/x)."
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            markdown("<ul><li>one</li><li>two<ol><li>a</li><li>b</li></ol></li></ul>"),
+            "- one\n- two\n  1. a\n  2. b"
+        );
+        assert_eq!(
+            markdown("<ol><li>first<br>line</li></ol>"),
+            "1. first  \n   line"
+        );
+    }
+
+    #[test]
+    fn pre_and_blockquote() {
+        assert_eq!(
+            markdown(
+                "<pre>\nfn main() {}\n</pre><blockquote><p>quoted</p><p>twice</p></blockquote>"
+            ),
+            "```\nfn main() {}\n```\n\n> quoted\n>\n> twice"
+        );
+    }
+
+    #[test]
+    fn unrecognized_elements_give_their_text() {
+        assert_eq!(
+            markdown("<article><font>plain <span>text</span></font></article>"),
+            "plain text"
+        );
+        assert_eq!(
+            markdown("<head><title>hidden</title></head><p>shown</p>"),
+            "shown"
+        );
+    }
+}
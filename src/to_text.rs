@@ -0,0 +1,243 @@
+use crate::{
+    dom::Node,
+    render::{self, Renderer},
+};
+
+/// Elements that are blocks in plain text on top of the shared ones.
+const EXTRA_BLOCKS: &[&str] = &["dl", "figure", "form", "table"];
+
+/// How the target of a link is written after its text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkFormat {
+    /// `text [https://example.com]`
+    #[default]
+    Brackets,
+    /// `text (https://example.com)`
+    Parentheses,
+    /// `text <https://example.com>`
+    AngleBrackets,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeadingStyle {
+    /// The heading text on a line of its own, underlined with `=` for `<h1>`
+    /// and `-` for lower levels.
+    #[default]
+    Underline,
+    /// Just the heading text.
+    Plain,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextExtractionOpts {
+    /// Whether to write the `href` of each link after its text.
+    pub preserve_links: bool,
+    pub link_format: LinkFormat,
+    pub heading_style: HeadingStyle,
+}
+
+struct PlainText(TextExtractionOpts);
+
+impl Node {
+    /// Converts the subtree to plain text that keeps its layout: blocks are
+    /// separated by blank lines, list items start with `- ` or their number
+    /// and table cells are separated by tabs, one row per line.
+    pub fn to_plain_text_with_structure(&self, opts: TextExtractionOpts) -> String {
+        PlainText(opts).render(self)
+    }
+}
+
+impl Renderer for PlainText {
+    fn is_block(&self, node: &Node) -> bool {
+        render::is_block(node)
+            || node
+                .tag_name()
+                .is_some_and(|tag| EXTRA_BLOCKS.contains(&tag))
+    }
+
+    fn block(&self, node: &Node) -> String {
+        let tag = node.tag_name().unwrap_or_default();
+        match tag {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.tidy(&self.inline(node.children()));
+                match self.0.heading_style {
+                    HeadingStyle::Underline if !text.is_empty() => {
+                        let underline = if tag == "h1" { "=" } else { "-" };
+                        let width = text.lines().map(|l| l.chars().count()).max();
+                        format!("{}\n{}", text, underline.repeat(width.unwrap_or(0)))
+                    }
+                    _ => text,
+                }
+            }
+            "p" => self.tidy(&self.inline(node.children())),
+            "ul" => self.list(node, false),
+            "ol" => self.list(node, true),
+            "pre" => node
+                .text_content()
+                .trim_matches('\n')
+                .trim_end()
+                .to_string(),
+            "table" => self.table(node),
+            _ => self.blocks(node.children()),
+        }
+    }
+
+    fn inline_element(&self, node: &Node) -> String {
+        match node.tag_name().unwrap_or_default() {
+            "a" => {
+                let content = self.tidy(&self.inline(node.children()));
+                match node.attr("href") {
+                    Some(href) if self.0.preserve_links => {
+                        let target = match self.0.link_format {
+                            LinkFormat::Brackets => format!("[{}]", href),
+                            LinkFormat::Parentheses => format!("({})", href),
+                            LinkFormat::AngleBrackets => format!("<{}>", href),
+                        };
+                        if content.is_empty() {
+                            target
+                        } else {
+                            format!("{} {}", content, target)
+                        }
+                    }
+                    _ => content,
+                }
+            }
+            "img" => node.attr("alt").unwrap_or_default().to_string(),
+            "br" => "\n".to_string(),
+            _ if self.is_block(node) => format!("\n{}\n", self.block(node)),
+            _ => self.inline(node.children()),
+        }
+    }
+
+    /// Trims each line of inline content and drops the blank lines around it.
+    fn tidy(&self, text: &str) -> String {
+        text.lines()
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join("\n")
+            .trim_matches('\n')
+            .to_string()
+    }
+}
+
+impl PlainText {
+    /// One line per row, with the cells separated by tabs.
+    fn table(&self, node: &Node) -> String {
+        node.iter()
+            .filter(|row| row.tag_name() == Some("tr"))
+            .map(|row| {
+                row.children()
+                    .iter()
+                    .filter(|cell| matches!(cell.tag_name(), Some("td" | "th")))
+                    .map(|cell| self.tidy(&self.inline(cell.children())).replace('\n', " "))
+                    .collect::<Vec<String>>()
+                    .join("\t")
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_fragment;
+
+    fn plain(html: &str) -> String {
+        plain_with(html, TextExtractionOpts::default())
+    }
+
+    fn plain_with(html: &str, opts: TextExtractionOpts) -> String {
+        parse_fragment(html.into())
+            .unwrap()
+            .to_plain_text_with_structure(opts)
+    }
+
+    #[test]
+    fn adjacent_blocks_are_separated_by_one_blank_line() {
+        assert_eq!(
+            plain("<p>one</p><div><p>two</p></div>\n\n\n<section><p>three</p></section>"),
+            "one\n\ntwo\n\nthree"
+        );
+        assert_eq!(plain("<p>one</p><p></p><p>two</p>"), "one\n\ntwo");
+        assert_eq!(
+            plain("loose <b>text</b><p>block</p>after"),
+            "loose text\n\nblock\n\nafter"
+        );
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(plain("<p>one<br>two<br><br>three</p>"), "one\ntwo\n\nthree");
+    }
+
+    #[test]
+    fn headings() {
+        assert_eq!(
+            plain("<h1>Title</h1><h2>Sub</h2><p>text</p>"),
+            "Title\n=====\n\nSub\n---\n\ntext"
+        );
+        let opts = TextExtractionOpts {
+            heading_style: HeadingStyle::Plain,
+            ..Default::default()
+        };
+        assert_eq!(
+            plain_with("<h1>Title</h1><p>text</p>", opts),
+            "Title\n\ntext"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            plain("<ul><li>one</li><li>two<ol><li>a</li><li>b<br>c</li></ol></li></ul>"),
+            "- one\n- two\n  1. a\n  2. b\n     c"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            plain(
+                "<table><tr><th>Name</th><th>Age</th></tr>\
+                 <tr><td>Ada</td><td>36</td></tr></table><p>after</p>"
+            ),
+            "Name\tAge\nAda\t36\n\nafter"
+        );
+    }
+
+    #[test]
+    fn links() {
+        let html = "<p>See <a href=\"https://example.com\">the docs</a>.</p>";
+        assert_eq!(plain(html), "See the docs.");
+        let with = |link_format| TextExtractionOpts {
+            preserve_links: true,
+            link_format,
+            ..Default::default()
+        };
+        assert_eq!(
+            plain_with(html, with(LinkFormat::Brackets)),
+            "See the docs [https://example.com]."
+        );
+        assert_eq!(
+            plain_with(html, with(LinkFormat::Parentheses)),
+            "See the docs (https://example.com)."
+        );
+        assert_eq!(
+            plain_with(html, with(LinkFormat::AngleBrackets)),
+            "See the docs <https://example.com>."
+        );
+    }
+
+    #[test]
+    fn hidden_and_inline_content() {
+        assert_eq!(
+            plain("<head><title>t</title></head><p>a <img alt=\"pic\"><script>x</script></p>"),
+            "a pic"
+        );
+        assert_eq!(
+            plain("<pre>fn main() {\n  x\n}\n</pre>"),
+            "fn main() {\n  x\n}"
+        );
+    }
+}
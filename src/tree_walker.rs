@@ -0,0 +1,228 @@
+use crate::dom::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterResult {
+    Accept,
+    /// Don't yield the node, but still visit its descendants.
+    Skip,
+    /// Don't yield the node nor any of its descendants.
+    Reject,
+}
+
+pub trait NodeFilter {
+    fn accept_node(&self, node: &Node) -> FilterResult;
+}
+
+impl<F> NodeFilter for F
+where
+    F: Fn(&Node) -> FilterResult,
+{
+    fn accept_node(&self, node: &Node) -> FilterResult {
+        self(node)
+    }
+}
+
+/// Filtered navigation over a subtree, mirroring the DOM `TreeWalker`.
+///
+/// Nodes have no parent pointers, so the walker keeps the path from the root
+/// down to the current node.
+pub struct TreeWalker<'a, F: NodeFilter> {
+    path: Vec<&'a Node>,
+    filter: F,
+}
+
+impl<'a, F: NodeFilter> TreeWalker<'a, F> {
+    pub fn new(root: &'a Node, filter: F) -> Self {
+        Self {
+            path: vec![root],
+            filter,
+        }
+    }
+
+    pub fn root(&self) -> &'a Node {
+        self.path[0]
+    }
+
+    pub fn current_node(&self) -> &'a Node {
+        self.path[self.path.len() - 1]
+    }
+
+    fn accept(&self, node: &Node) -> FilterResult {
+        self.filter.accept_node(node)
+    }
+
+    fn sibling(path: &[&'a Node], offset: isize) -> Option<&'a Node> {
+        let [.., parent, node] = path else {
+            return None;
+        };
+        let index = node.position_in_parent(parent)?;
+        parent.children().get(index.checked_add_signed(offset)?)
+    }
+
+    fn select(&mut self, path: Vec<&'a Node>) -> Option<&'a Node> {
+        self.path = path;
+        Some(self.current_node())
+    }
+
+    pub fn next_node(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        let mut result = FilterResult::Accept;
+        loop {
+            while result != FilterResult::Reject {
+                let Some(child) = path[path.len() - 1].children().first() else {
+                    break;
+                };
+                path.push(child);
+                result = self.accept(child);
+                if result == FilterResult::Accept {
+                    return self.select(path);
+                }
+            }
+            loop {
+                if path.len() == 1 {
+                    return None;
+                }
+                if let Some(sibling) = Self::sibling(&path, 1) {
+                    path.pop();
+                    path.push(sibling);
+                    break;
+                }
+                path.pop();
+            }
+            result = self.accept(path[path.len() - 1]);
+            if result == FilterResult::Accept {
+                return self.select(path);
+            }
+        }
+    }
+
+    pub fn previous_node(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        while path.len() > 1 {
+            while let Some(sibling) = Self::sibling(&path, -1) {
+                path.pop();
+                path.push(sibling);
+                let mut result = self.accept(sibling);
+                while result != FilterResult::Reject {
+                    let Some(child) = path[path.len() - 1].children().last() else {
+                        break;
+                    };
+                    path.push(child);
+                    result = self.accept(child);
+                }
+                if result == FilterResult::Accept {
+                    return self.select(path);
+                }
+            }
+            path.pop();
+            if self.accept(path[path.len() - 1]) == FilterResult::Accept {
+                return self.select(path);
+            }
+        }
+        None
+    }
+
+    pub fn first_child(&mut self) -> Option<&'a Node> {
+        self.traverse_children(true)
+    }
+
+    pub fn last_child(&mut self) -> Option<&'a Node> {
+        self.traverse_children(false)
+    }
+
+    fn traverse_children(&mut self, forward: bool) -> Option<&'a Node> {
+        let edge = |node: &'a Node| {
+            if forward {
+                node.children().first()
+            } else {
+                node.children().last()
+            }
+        };
+        let offset = if forward { 1 } else { -1 };
+        let depth = self.path.len();
+        let mut path = self.path.clone();
+        path.push(edge(self.current_node())?);
+        loop {
+            let node = path[path.len() - 1];
+            let result = self.accept(node);
+            if result == FilterResult::Accept {
+                return self.select(path);
+            }
+            if result == FilterResult::Skip {
+                if let Some(child) = edge(node) {
+                    path.push(child);
+                    continue;
+                }
+            }
+            loop {
+                if let Some(sibling) = Self::sibling(&path, offset) {
+                    path.pop();
+                    path.push(sibling);
+                    break;
+                }
+                path.pop();
+                if path.len() == depth {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Node {
+    pub fn create_tree_walker<F: NodeFilter>(&self, filter: F) -> TreeWalker<'_, F> {
+        TreeWalker::new(self, filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_fragment;
+
+    fn only_paragraphs(node: &Node) -> FilterResult {
+        match node.tag_name() {
+            Some("p") => FilterResult::Accept,
+            _ => FilterResult::Skip,
+        }
+    }
+
+    fn texts(nodes: Vec<&Node>) -> Vec<String> {
+        nodes.iter().map(|node| node.text_content()).collect()
+    }
+
+    const HTML: &str = "<div><p>1</p><section><p>2</p><!-- c --><p>3</p></section></div><p>4</p>";
+
+    #[test]
+    fn next_node_yields_accepted_nodes_in_document_order() {
+        let root = parse_fragment(HTML.into()).unwrap();
+        let mut walker = root.create_tree_walker(only_paragraphs);
+        let nodes: Vec<&Node> = std::iter::from_fn(|| walker.next_node()).collect();
+        assert_eq!(texts(nodes), ["1", "2", "3", "4"]);
+
+        let nodes: Vec<&Node> = std::iter::from_fn(|| walker.previous_node()).collect();
+        assert_eq!(texts(nodes), ["3", "2", "1"]);
+    }
+
+    #[test]
+    fn reject_prunes_the_subtree() {
+        let root = parse_fragment(HTML.into()).unwrap();
+        let mut walker = root.create_tree_walker(|node: &Node| match node.tag_name() {
+            Some("section") => FilterResult::Reject,
+            _ => only_paragraphs(node),
+        });
+        let nodes: Vec<&Node> = std::iter::from_fn(|| walker.next_node()).collect();
+        assert_eq!(texts(nodes), ["1", "4"]);
+    }
+
+    #[test]
+    fn first_and_last_child_look_through_skipped_nodes() {
+        let root = parse_fragment(HTML.into()).unwrap();
+        let mut walker = root.create_tree_walker(only_paragraphs);
+        assert_eq!(walker.first_child().unwrap().text_content(), "1");
+        assert_eq!(walker.first_child(), None);
+
+        let mut walker = root.create_tree_walker(only_paragraphs);
+        assert_eq!(walker.last_child().unwrap().text_content(), "4");
+    }
+}